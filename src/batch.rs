@@ -0,0 +1,129 @@
+use crate::cli;
+use crate::export;
+use crate::json::{self, Record, Sample, Snapshot, SCHEMA_VERSION};
+use crate::table::StatefulTable;
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    // aligned columns like `top -b`
+    Text,
+    // a JSON document per refresh
+    Json,
+    // a JSON object per socket per refresh
+    Ndjson,
+    // a header, then a row per socket per refresh
+    Csv,
+}
+
+pub struct Batch<'a> {
+    pub format: Format,
+    pub interval: Duration,
+    // 0 keeps going until killed
+    pub iterations: u64,
+    pub namespace: Option<&'a str>,
+}
+
+// The socket table printed to stdout every `interval` instead of drawn, for
+// cron jobs and scripts.
+pub fn run(table: &mut StatefulTable, batch: &Batch) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let started = Instant::now();
+    if batch.format == Format::Csv {
+        export::write_row(&mut out, &export::table_header(table))?;
+    }
+    let mut done = 0;
+    while batch.iterations == 0 || done < batch.iterations {
+        // rates need two samples, so even the first snapshot waits a tick
+        thread::sleep(batch.interval);
+        table.refresh();
+        if let Some(err) = table.record_error.take() {
+            eprintln!("tcptop: recording stopped: {}", err);
+        }
+        let written = match batch.format {
+            Format::Text => write_snapshot(&mut out, table, table.captured_at().0),
+            Format::Json => write_json(&mut out, table, Sample::of(table, started, batch.namespace)),
+            Format::Ndjson => write_ndjson(&mut out, table, Sample::of(table, started, batch.namespace)),
+            Format::Csv => export::write_table(&mut out, table),
+        };
+        let written = written.and_then(|_| out.flush());
+        match written {
+            // someone piped us into `head`, that's not an error
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+            result => result?,
+        }
+        done += 1;
+    }
+    Ok(())
+}
+
+fn write_snapshot<W: Write>(out: &mut W, table: &StatefulTable, at: SystemTime) -> io::Result<()> {
+    let mut header = format!("tcptop - {} - {} sockets", cli::timestamp(at), table.items.len());
+    if !table.filter.source.is_empty() {
+        header.push_str(&format!(" - filter: {}", table.filter.source));
+    }
+    writeln!(out, "{}\n", header)?;
+    let titles: Vec<String> = table.layout.iter().map(|column| table.registry[*column].title.to_string()).collect();
+    for line in align(&titles, &table.items) {
+        writeln!(out, "{}", line)?;
+    }
+    writeln!(out)
+}
+
+fn write_json<W: Write>(out: &mut W, table: &StatefulTable, sample: Sample) -> io::Result<()> {
+    let snapshot = Snapshot { version: SCHEMA_VERSION, sample, sockets: json::sockets(table) };
+    serde_json::to_writer(&mut *out, &snapshot)?;
+    writeln!(out)
+}
+
+fn write_ndjson<W: Write>(out: &mut W, table: &StatefulTable, sample: Sample) -> io::Result<()> {
+    for socket in json::sockets(table) {
+        let record = Record { version: SCHEMA_VERSION, sample: sample.clone(), socket };
+        serde_json::to_writer(&mut *out, &record)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+// Left-aligns every column to its widest cell, with two spaces between them.
+fn align(titles: &[String], rows: &[Vec<String>]) -> Vec<String> {
+    let mut widths: Vec<usize> = titles.iter().map(|title| title.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    std::iter::once(titles)
+        .chain(rows.iter().map(|row| row.as_slice()))
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            cells.join("  ").trim_end().to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_align() {
+        let titles = vec!["Source".to_string(), "State".to_string(), "RTT".to_string()];
+        let rows = vec![
+            vec!["10.0.0.1:443".to_string(), "ESTABLISHED".to_string(), "1.2ms".to_string()],
+            vec!["::1:22".to_string(), "LISTEN".to_string(), "".to_string()],
+        ];
+        assert_eq!(align(&titles, &rows), vec![
+            "Source        State        RTT",
+            "10.0.0.1:443  ESTABLISHED  1.2ms",
+            "::1:22        LISTEN",
+        ]);
+    }
+}
@@ -0,0 +1,290 @@
+use crate::fields::{human_bitrate, human_micros};
+use crate::table::{self, SocketHistory};
+use crate::theme::Theme;
+use ratatui::backend::Backend;
+use ratatui::layout::Rect;
+use ratatui::style::{Modifier, Style};
+use ratatui::symbols;
+use ratatui::terminal::Frame;
+use ratatui::text::Span;
+use ratatui::widgets::{Axis, Block, Borders, Chart, Dataset, GraphType};
+use std::collections::VecDeque;
+use std::time::Instant;
+
+// The charts the detail view can show. Each one plots one or more of the
+// rings kept in `SocketHistory`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Panel {
+    Window,
+    Send,
+    Receive,
+    Rtt,
+    Retransmits,
+    Pacing,
+}
+
+impl Panel {
+    pub const ALL: [Panel; 6] = [
+        Panel::Window,
+        Panel::Send,
+        Panel::Receive,
+        Panel::Rtt,
+        Panel::Retransmits,
+        Panel::Pacing,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Panel::Window => "Window",
+            Panel::Send => "Send",
+            Panel::Receive => "Receive",
+            Panel::Rtt => "RTT",
+            Panel::Retransmits => "Retransmits",
+            Panel::Pacing => "Pacing vs Delivery",
+        }
+    }
+
+    fn y_title(&self) -> &'static str {
+        match self {
+            Panel::Window => "Packets",
+            Panel::Send | Panel::Receive | Panel::Pacing => "Rate",
+            Panel::Rtt => "RTT",
+            Panel::Retransmits => "Segments",
+        }
+    }
+
+    fn format_value(&self, value: f64) -> String {
+        match self {
+            // small counts would all round to the same label
+            Panel::Window | Panel::Retransmits if value < 10.0 && value.fract() != 0.0 => {
+                return format!("{:.1}", value)
+            }
+            _ => {}
+        }
+        let value = value.round() as u64;
+        match self {
+            Panel::Window | Panel::Retransmits => value.to_string(),
+            Panel::Send | Panel::Receive => table::friendly_transfer_str(value),
+            Panel::Rtt => human_micros(value),
+            Panel::Pacing => human_bitrate(value),
+        }
+    }
+
+    pub fn next(&self) -> Panel {
+        let i = Panel::ALL.iter().position(|p| p == self).unwrap_or(0);
+        Panel::ALL[(i + 1) % Panel::ALL.len()]
+    }
+}
+
+struct Series {
+    name: &'static str,
+    style: Style,
+    points: Vec<(f64, f64)>,
+}
+
+// Zoom steps for the time axis, in seconds, up to `retention` which covers
+// everything we keep in `SocketHistory`. The hours are for a daemon's history.
+const STEPS: [u64; 14] = [5, 10, 15, 30, 60, 120, 300, 600, 1800, 3600, 10800, 21600, 43200, 86400];
+
+fn windows(retention: u64) -> Vec<u64> {
+    let mut windows: Vec<u64> = STEPS.iter().copied().filter(|w| *w < retention).collect();
+    windows.push(retention);
+    windows
+}
+
+pub fn zoom_in(window: u64, retention: u64) -> u64 {
+    let windows = windows(retention);
+    windows.iter().rev().copied().find(|w| *w < window).unwrap_or(windows[0])
+}
+
+pub fn zoom_out(window: u64, retention: u64) -> u64 {
+    windows(retention).into_iter().find(|w| *w > window).unwrap_or(retention)
+}
+
+// Samples are placed by how long before the newest one they were taken, so
+// the right edge of every chart is "now" and older samples run off to the left.
+fn sample_ages(history: &SocketHistory) -> Vec<f64> {
+    let newest = history.sampled_at.front().copied().unwrap_or_else(Instant::now);
+    history.sampled_at
+        .iter()
+        .map(|at| -newest.duration_since(*at).as_secs_f64())
+        .collect()
+}
+
+fn ring_points<'a, I: Iterator<Item = &'a u64>>(ages: &[f64], ring: I) -> Vec<(f64, f64)> {
+    ages.iter().zip(ring).map(|(age, value)| (*age, *value as f64)).collect()
+}
+
+fn series(name: &'static str, style: Style, ages: &[f64], ring: &VecDeque<u64>) -> Series {
+    Series { name, style, points: ring_points(ages, ring.iter()) }
+}
+
+fn panel_series(panel: Panel, history: &SocketHistory, theme: &Theme) -> Vec<Series> {
+    let ages = sample_ages(history);
+    let ages = ages.as_slice();
+    match panel {
+        Panel::Window => vec![
+            series("cwnd", theme.chart_primary, ages, &history.congestion_window),
+            // an infinite ssthresh would flatten everything else, leave those samples out
+            Series {
+                name: "ssthresh",
+                style: theme.chart_alert,
+                points: ring_points(ages, history.slow_start_threshold.iter())
+                    .into_iter()
+                    .filter(|(_, value)| *value < 0x7fff_ffff as f64)
+                    .collect(),
+            },
+        ],
+        Panel::Send => vec![series("send", theme.chart_primary, ages, &history.send_bps)],
+        Panel::Receive => vec![series("recv", theme.chart_primary, ages, &history.recv_bps)],
+        Panel::Rtt => {
+            let band = |sign: f64| {
+                ages.iter()
+                    .zip(history.rtt.iter().zip(history.rtt_variance.iter()))
+                    .map(|(age, (rtt, var))| (*age, (*rtt as f64 + sign * *var as f64).max(0.0)))
+                    .collect()
+            };
+            vec![
+                Series { name: "+rttvar", style: theme.chart_band, points: band(1.0) },
+                Series { name: "-rttvar", style: theme.chart_band, points: band(-1.0) },
+                series("min_rtt", theme.chart_secondary, ages, &history.min_rtt),
+                series("srtt", theme.chart_primary, ages, &history.rtt),
+            ]
+        }
+        Panel::Retransmits => vec![series("retrans", theme.chart_alert, ages, &history.retransmits)],
+        Panel::Pacing => vec![
+            series("pacing", theme.chart_primary, ages, &history.pacing_rate),
+            series("delivery", theme.chart_secondary, ages, &history.delivery_rate),
+        ],
+    }
+}
+
+// The y range covers whatever is on screen with a little headroom, rather than
+// always starting at 0, so small wobbles in something like RTT stay visible.
+fn y_bounds(series: &[Series]) -> [f64; 2] {
+    let mut values = series.iter().flat_map(|s| s.points.iter().map(|p| p.1));
+    let first = match values.next() {
+        Some(value) => value,
+        None => return [0.0, 1.0],
+    };
+    let (min, max) = values.fold((first, first), |(min, max), v| (min.min(v), max.max(v)));
+    let pad = match max - min {
+        spread if spread > 0.0 => spread * 0.1,
+        _ => (max.abs() * 0.1).max(1.0),
+    };
+    [(min - pad).max(0.0), max + pad]
+}
+
+fn age_label(seconds: u64) -> String {
+    match seconds {
+        0 => "now".to_string(),
+        s if s % 3600 == 0 => format!("-{}h", s / 3600),
+        s if s % 60 == 0 => format!("-{}m", s / 60),
+        s => format!("-{}s", s),
+    }
+}
+
+fn label(text: String) -> Span<'static> {
+    Span::styled(text, Style::default().add_modifier(Modifier::ITALIC))
+}
+
+pub fn draw<B: Backend>(
+    frame: &mut Frame<B>,
+    area: Rect,
+    panel: Panel,
+    history: &SocketHistory,
+    window: u64,
+    theme: &Theme,
+) {
+    let span = window as f64;
+    let mut series = panel_series(panel, history, theme);
+    for s in series.iter_mut() {
+        s.points.retain(|(age, _)| *age >= -span);
+    }
+    let bounds = y_bounds(&series);
+    let x_labels = vec![
+        label(age_label(window)),
+        label(age_label(window / 2)),
+        label(age_label(0)),
+    ];
+    let y_labels = vec![
+        label(panel.format_value(bounds[0])),
+        label(panel.format_value((bounds[0] + bounds[1]) / 2.0)),
+        label(panel.format_value(bounds[1])),
+    ];
+    let datasets = series
+        .iter()
+        .map(|s| {
+            Dataset::default()
+                .name(s.name)
+                .marker(symbols::Marker::Braille)
+                .style(s.style)
+                .graph_type(GraphType::Line)
+                .data(&s.points)
+        })
+        .collect();
+    let chart = Chart::new(datasets)
+        .block(
+            Block::default()
+                .title(panel.title())
+                .title_style(theme.chart_title)
+                .borders(Borders::ALL),
+        )
+        .x_axis(
+            Axis::default()
+                .style(theme.chart_axis)
+                .bounds([-span, 0.0])
+                .labels(x_labels),
+        )
+        .y_axis(
+            Axis::default()
+                .title(panel.y_title())
+                .style(theme.chart_axis)
+                .bounds(bounds)
+                .labels(y_labels),
+        );
+    frame.render_widget(chart, area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(values: &[f64]) -> Series {
+        Series {
+            name: "test",
+            style: Style::default(),
+            points: values.iter().enumerate().map(|(i, v)| (-(i as f64), *v)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_y_bounds() {
+        assert_eq!(y_bounds(&[]), [0.0, 1.0]);
+        assert_eq!(y_bounds(&[points(&[100.0, 200.0])]), [90.0, 210.0]);
+        // flat lines still get some room, but never go below zero
+        assert_eq!(y_bounds(&[points(&[0.0, 0.0])]), [0.0, 1.0]);
+        assert_eq!(y_bounds(&[points(&[50.0]), points(&[150.0])]), [40.0, 160.0]);
+    }
+
+    #[test]
+    fn test_zoom() {
+        assert_eq!(zoom_in(30, 30), 15);
+        assert_eq!(zoom_in(5, 30), 5);
+        assert_eq!(zoom_out(15, 30), 30);
+        assert_eq!(zoom_out(30, 30), 30);
+        // odd retentions are a step of their own
+        assert_eq!(zoom_out(60, 90), 90);
+        assert_eq!(zoom_in(90, 90), 60);
+        assert_eq!(zoom_out(600, 86400), 1800);
+    }
+
+    #[test]
+    fn test_age_label() {
+        assert_eq!(age_label(0), "now");
+        assert_eq!(age_label(15), "-15s");
+        assert_eq!(age_label(120), "-2m");
+        assert_eq!(age_label(5400), "-90m");
+        assert_eq!(age_label(21600), "-6h");
+    }
+}
@@ -1,136 +1,1144 @@
-use crate::table;
-use ratatui::layout::{Constraint, Layout, Direction, Rect};
-use ratatui::style::{Color, Modifier, Style};
-use ratatui::widgets::{Row, Table, Block, Borders, Chart, Dataset, Axis, GraphType, Paragraph, Wrap};
-use ratatui::text::{Spans, Span, Text};
+use crate::table::{self, Rows};
+use crate::views::{Listing, Tab, Target};
+use crate::filter::{Filter, FilterError};
+use crate::picker::{centered_rect, ColumnPicker, PickerResult};
+use crate::keys::{Action, Keymap, View};
+use crate::theme::Theme;
+use crate::highlight::Highlight;
+use crate::config;
+use crate::export;
+use crate::event;
+use crate::charts::{self, Panel};
+use crate::fields::{Group, INFO_FIELDS};
+use crate::tcpdiag::{DiagWithInode, TCPInfo, TCP_STATE};
+use ratatui::layout::{Alignment, Constraint, Layout, Direction, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Row, Table, Block, Borders, Clear, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState};
+use ratatui::widgets::block::{Position, Title};
+use ratatui::text::{Line, Span, Text};
 use ratatui::terminal::Frame;
 use ratatui::backend::Backend;
-use ratatui::symbols;
-use std::collections::VecDeque;
+use termion::event::{Key, MouseButton, MouseEvent};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::io;
+use std::path::{Path, PathBuf};
 
 
-fn vecdequeue_as_chart(rate: &VecDeque<u64>) -> [(f64, f64); table::HISTORY_RETENTION] {
-    let mut chart_points = [(0.0, 0.0); table::HISTORY_RETENTION];
-    for (index, value) in rate.iter().enumerate() {
-        chart_points[index] = (index as f64, *value as f64);
-    }
-    chart_points
+// State of the `/` filter prompt while it is open. The filter that was active
+// when the prompt opened is kept around so escape can put it back.
+struct FilterPrompt {
+    input: String,
+    error: Option<FilterError>,
+    previous: Filter,
+}
+
+// A copy of the socket the detail view is showing. It's kept separately from the
+// table so the view can stay on the last thing we knew after the socket closes.
+struct Detail {
+    socket: DiagWithInode,
+    history: table::SocketHistory,
+    closed: bool,
 }
 
-fn determine_min_max_values(rate: &VecDeque<u64>) -> [f64; 2] {
-    // For now the min here will always be 0. We might want to revisit this and
-    // create a more dynamic bound for each chart
-    let (min, mut max) = (0u64, 0u64);
-    for sample in rate {
-        if sample > &max {
-            max = *sample;
+impl Detail {
+    // Stepping through a paused display can go back to before the socket closed,
+    // so being closed isn't forever.
+    fn update(&mut self, overview: &table::StatefulTable) {
+        match overview.find(self.socket.id()) {
+            Some((socket, history)) => {
+                self.socket = socket.clone();
+                self.history = history.clone();
+                self.closed = false;
+            }
+            None => self.closed = true,
         }
     }
-    [min as f64, max as f64]
 }
 
-pub struct CLI<'a> {
+// What the help bar offers in each view, most useful first. It only shows as
+// many as fit, the `?` overlay has the rest.
+const TABLE_BAR: [(Action, &str); 8] = [
+    (Action::Help, "help"),
+    (Action::OpenDetail, "detail"),
+    (Action::Filter, "filter"),
+    (Action::Find, "find"),
+    (Action::SortNext, "sort"),
+    (Action::Columns, "columns"),
+    (Action::Pause, "pause"),
+    (Action::Quit, "quit"),
+];
+
+const GROUP_BAR: [(Action, &str); 4] = [
+    (Action::Help, "help"),
+    (Action::CloseDetail, "back"),
+    (Action::NextTab, "next tab"),
+    (Action::Quit, "quit"),
+];
+
+const DETAIL_BAR: [(Action, &str); 7] = [
+    (Action::Help, "help"),
+    (Action::CloseDetail, "back"),
+    (Action::CycleChart, "next chart"),
+    (Action::TileCharts, "tile"),
+    (Action::ZoomIn, "zoom in"),
+    (Action::ZoomOut, "zoom out"),
+    (Action::Quit, "quit"),
+];
+
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+// Where things ended up on screen in the last frame, so mouse clicks can be
+// matched back to what was under them.
+#[derive(Default)]
+struct HitAreas {
+    // x and width of each header cell, with the registry index of its column
+    headers: Vec<(u16, u16, usize)>,
+    header_y: u16,
+    rows: Rect,
+    stats: Rect,
+    charts: Rect,
+    help: Vec<(u16, u16, Action)>,
+    help_y: u16,
+    tabs: Vec<(u16, u16, Tab)>,
+    tabs_y: u16,
+    // the bar of a replay's timeline
+    timeline: Rect,
+}
+
+fn contains(area: Rect, x: u16, y: u16) -> bool {
+    x >= area.x && x < area.x + area.width && y >= area.y && y < area.y + area.height
+}
+
+// The `f` prompt for jumping to a row by its text. The row the selection started
+// on is kept so escape can go back to it.
+struct FindPrompt {
+    input: String,
+    origin: Option<usize>,
+}
+
+pub struct CLI {
+    // the socket table, which also does the collecting for every other tab
     pub overview: table::StatefulTable,
-    detail_toggle: bool,
-    seconds_labels: Vec<Span<'a>>,
+    tab: Tab,
+    // one for each tab after Sockets
+    listings: Vec<Listing>,
+    // set while the detail view is open
+    detail: Option<Detail>,
+    // or while drilled into a listener, host or process
+    group: Option<Target>,
+    detail_scroll: u16,
+    // either every chart panel tiled, or just `chart_panel` using all the space
+    charts_tiled: bool,
+    chart_panel: Panel,
+    // how many seconds of history the charts cover
+    chart_window: u64,
+    filter_prompt: Option<FilterPrompt>,
+    find_prompt: Option<FindPrompt>,
+    // the last thing searched for, for `n` and `N`
+    last_find: String,
+    column_picker: Option<ColumnPicker>,
+    // one-off message shown in place of the help bar until the next key press
+    status: Option<String>,
+    // how often we sample, main passes changes on to the tick thread
+    pub interval: Duration,
+    // where the column layout is saved, the user's config file or --config
+    pub config_path: Option<PathBuf>,
+    pub keymap: Keymap,
+    theme: Theme,
+    help_open: bool,
+    hits: HitAreas,
+    // when and on which row the last click landed, for spotting double clicks
+    last_click: Option<(Instant, usize)>,
 }
 
-impl<'a> CLI<'a> {
-    pub fn new() -> Self {
+impl CLI {
+    pub fn new(overview: table::StatefulTable, keymap: Keymap, theme: Theme) -> Self {
+        let retention = overview.options.retention;
         Self {
-            overview: table::StatefulTable::new(),
-            detail_toggle: false,
-            seconds_labels: vec![
-                Span::styled("0", Style::default().add_modifier(Modifier::ITALIC)),
-                Span::styled("15", Style::default().add_modifier(Modifier::ITALIC)),
-                Span::styled("30", Style::default().add_modifier(Modifier::ITALIC)),
-            ],
+            overview,
+            tab: Tab::Sockets,
+            listings: Tab::ALL[1..].iter().map(|tab| Listing::new(*tab)).collect(),
+            detail: None,
+            group: None,
+            detail_scroll: 0,
+            charts_tiled: true,
+            chart_panel: Panel::Window,
+            chart_window: retention.as_secs(),
+            filter_prompt: None,
+            find_prompt: None,
+            last_find: String::new(),
+            column_picker: None,
+            status: None,
+            interval: Duration::from_secs(1),
+            config_path: config::config_path(),
+            keymap,
+            theme,
+            help_open: false,
+            hits: HitAreas::default(),
+            last_click: None,
         }
     }
 
     pub fn render<B: Backend>(&mut self, frame: &mut Frame<B>) {
+        let timeline = self.overview.player().is_some() as u16;
         let terminal_chunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints(
                 [
-                    Constraint::Length(frame.size().height - 1),
+                    Constraint::Length(1),
+                    Constraint::Min(0),
+                    Constraint::Length(timeline),
                     Constraint::Length(1),
                 ]
                 .as_ref(),
             )
             .split(frame.size());
-        match self.detail_toggle {
-            false => self.draw_overview(frame, terminal_chunks[0]),
-            true => self.draw_detail(frame, terminal_chunks[0]) 
-        }
-
-        let help_text = Spans::from(vec![
-            Span::styled("<j, down>", Style::default().bg(Color::Gray).fg(Color::Blue).add_modifier(Modifier::BOLD)),
-            Span::raw(format!(" to move down  ")),
-            Span::styled("<k, up>", Style::default().bg(Color::Gray).fg(Color::Blue).add_modifier(Modifier::BOLD)),
-            Span::raw(format!(" to move up  ")),
-            Span::styled("<ENTER>", Style::default().bg(Color::Gray).fg(Color::Blue).add_modifier(Modifier::BOLD)),
-            Span::raw(format!(" defail for selected socket  ")),
-            Span::styled("<b>", Style::default().bg(Color::Gray).fg(Color::Blue).add_modifier(Modifier::BOLD)),
-            Span::raw(format!(" back to table view  ")),
-        ]);
-        let help = Paragraph::new(help_text).wrap(Wrap{trim: true});
-        frame.render_widget(help, terminal_chunks[1]);
+        self.draw_tab_bar(frame, terminal_chunks[0]);
+        let body = terminal_chunks[1];
+        if self.detail.is_some() {
+            self.draw_detail(frame, body);
+        } else if self.group.is_some() {
+            self.draw_group(frame, body);
+        } else {
+            self.draw_overview(frame, body);
+        }
+        if let Some(picker) = self.column_picker.as_mut() {
+            picker.draw(frame, body, &self.overview, &self.theme);
+        }
+        if self.help_open {
+            self.draw_help(frame, body);
+        }
+        self.draw_timeline(frame, terminal_chunks[2]);
+
+        let bar = terminal_chunks[3];
+        // whatever was on the bar last time can't be clicked any more
+        self.hits.help.clear();
+        self.hits.help_y = bar.y;
+        if let Some(prompt) = &self.filter_prompt {
+            frame.render_widget(Paragraph::new(prompt_line(prompt, &self.theme)), bar);
+            return;
+        }
+        if let Some(prompt) = &self.find_prompt {
+            frame.render_widget(Paragraph::new(find_line(prompt, &self.theme)), bar);
+            return;
+        }
+        if let Some(status) = &self.status {
+            frame.render_widget(Paragraph::new(status.as_str()), bar);
+            return;
+        }
+
+        let key_style = self.theme.key;
+        let entries: &[(Action, &str)] = if self.detail.is_some() {
+            &DETAIL_BAR
+        } else if self.group.is_some() {
+            &GROUP_BAR
+        } else {
+            &TABLE_BAR
+        };
+        let mut spans = Vec::new();
+        let mut x = bar.x;
+        let right = bar.x + bar.width;
+        for (action, description) in entries.iter() {
+            // unbound actions have nothing to show
+            let key = match self.keymap.keys_for(*action).into_iter().next() {
+                Some(key) => format!("<{}>", key),
+                None => continue,
+            };
+            let description = format!(" {}  ", description);
+            let width = (key.chars().count() + description.chars().count()) as u16;
+            if x + width > right {
+                break;
+            }
+            self.hits.help.push((x, width, *action));
+            x += width;
+            spans.push(Span::styled(key, key_style));
+            spans.push(Span::raw(description));
+        }
+        frame.render_widget(Paragraph::new(Line::from(spans)), bar);
+    }
+
+    // Where a replay is up to: whether it's playing and how fast, then the
+    // recording's span with a marker on the sample being shown. Clicking on the
+    // span jumps there.
+    fn draw_timeline<B: Backend>(&mut self, frame: &mut Frame<B>, area: Rect) {
+        let player = match self.overview.player() {
+            Some(player) if area.height > 0 => player,
+            _ => return,
+        };
+        let position = player.position();
+        let state = match player.playing {
+            true => format!(" playing {}x ", player.speed()),
+            false => " paused ".to_string(),
+        };
+        let now = format!(" {} ", timestamp(player.time(position)));
+        let end = format!(" {}  {}/{} ", clock(player.time(player.len() - 1)), position + 1, player.len());
+        let start = (state.chars().count() + now.chars().count()) as u16;
+        let width = area.width.saturating_sub(start + end.chars().count() as u16);
+        let marker = (player.fraction(position) * width.saturating_sub(1) as f64).round() as usize;
+        let played = "━".repeat(marker);
+        let left = "─".repeat((width as usize).saturating_sub(marker + 1));
+        self.hits.timeline = Rect { x: area.x + start, y: area.y, width, height: 1 };
+        let mut spans = vec![
+            Span::styled(state, self.theme.key),
+            Span::styled(now, self.theme.text),
+            Span::styled(played, self.theme.chart_primary),
+        ];
+        if width > 0 {
+            spans.push(Span::styled("●", self.theme.chart_primary));
+        }
+        spans.push(Span::styled(left, self.theme.chart_axis));
+        spans.push(Span::styled(end, self.theme.text));
+        frame.render_widget(Paragraph::new(Line::from(spans)), area);
+    }
+
+    // One entry per tab with the key that switches to it, the current one picked out.
+    fn draw_tab_bar<B: Backend>(&mut self, frame: &mut Frame<B>, area: Rect) {
+        let mut spans = Vec::new();
+        let mut x = area.x;
+        self.hits.tabs.clear();
+        self.hits.tabs_y = area.y;
+        for tab in Tab::ALL.iter() {
+            let key = match self.keymap.keys_for(tab_action(*tab)).into_iter().next() {
+                Some(key) => format!("{} ", key),
+                None => String::new(),
+            };
+            let label = format!(" {}{} ", key, tab.title());
+            let style = match *tab == self.tab {
+                true => self.theme.key,
+                false => self.theme.muted,
+            };
+            let width = label.chars().count() as u16;
+            self.hits.tabs.push((x, width, *tab));
+            x += width + 1;
+            spans.push(Span::styled(label, style));
+            spans.push(Span::raw(" "));
+        }
+        frame.render_widget(Paragraph::new(Line::from(spans)), area);
+    }
+
+    pub fn switch_tab(&mut self, tab: Tab) {
+        self.detail = None;
+        self.group = None;
+        self.tab = tab;
+        self.rebuild_listing();
+    }
+
+    pub fn next_tab(&mut self) {
+        self.switch_tab(self.tab.next());
+    }
+
+    pub fn previous_tab(&mut self) {
+        self.switch_tab(self.tab.previous());
+    }
+
+    fn listing(&self) -> Option<&Listing> {
+        self.listings.iter().find(|listing| listing.tab == self.tab)
+    }
+
+    // The table on the current tab.
+    fn rows(&mut self) -> &mut dyn Rows {
+        let tab = self.tab;
+        match self.listings.iter_mut().find(|listing| listing.tab == tab) {
+            Some(listing) => listing,
+            None => &mut self.overview,
+        }
+    }
+
+    fn rebuild_listing(&mut self) {
+        let tab = self.tab;
+        if let Some(listing) = self.listings.iter_mut().find(|listing| listing.tab == tab) {
+            listing.rebuild(&self.overview);
+        }
+    }
+
+    fn in_detail(&self) -> bool {
+        self.detail.is_some() || self.group.is_some()
+    }
+
+    fn current_filter(&self) -> &Filter {
+        match self.listing() {
+            Some(listing) => &listing.filter,
+            None => &self.overview.filter,
+        }
+    }
+
+    fn set_filter(&mut self, filter: Filter) {
+        let tab = self.tab;
+        match self.listings.iter_mut().find(|listing| listing.tab == tab) {
+            Some(listing) => {
+                listing.filter = filter;
+                listing.rebuild(&self.overview);
+            }
+            None => self.overview.set_filter(filter),
+        }
+    }
+
+    pub fn sort_next(&mut self) {
+        self.rows().sort_next();
+        self.rebuild_listing();
+    }
+
+    pub fn sort_reverse(&mut self) {
+        self.rows().sort_reverse();
+        self.rebuild_listing();
+    }
+
+    fn sort_by(&mut self, column: usize) {
+        self.rows().sort_by(column);
+        self.rebuild_listing();
+    }
+
+    pub fn help_active(&self) -> bool {
+        self.help_open
+    }
+
+    pub fn open_help(&mut self) {
+        self.help_open = true;
+    }
+
+    // Any key closes the help overlay.
+    pub fn on_help_key(&mut self, _key: Key) {
+        self.help_open = false;
+    }
+
+    // Lists every action that does something in the current view, with its keys.
+    fn draw_help<B: Backend>(&self, frame: &mut Frame<B>, area: Rect) {
+        let area = centered_rect(70, 80, area);
+        let view = match self.in_detail() {
+            true => View::Detail,
+            false => View::Table,
+        };
+        let key_style = self.theme.prompt;
+        let lines: Vec<Line> = Action::ALL
+            .iter()
+            .filter(|action| action.view() == view || action.view() == View::Both)
+            .map(|action| {
+                let keys = match self.keymap.keys_for(*action) {
+                    keys if keys.is_empty() => "-".to_string(),
+                    keys => keys.join(", "),
+                };
+                Line::from(vec![
+                    Span::styled(format!("{:<20}", keys), key_style),
+                    Span::raw(format!("{:<28}", action.description())),
+                    Span::styled(action.name(), self.theme.muted),
+                ])
+            })
+            .collect();
+        let help = Paragraph::new(Text::from(lines)).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Keys: press any key to close")
+                .title_style(self.theme.title),
+        );
+        frame.render_widget(Clear, area);
+        frame.render_widget(help, area);
+    }
+
+    pub fn filter_prompt_active(&self) -> bool {
+        self.filter_prompt.is_some()
+    }
+
+    pub fn column_picker_active(&self) -> bool {
+        self.column_picker.is_some()
+    }
+
+    // True while some popup or prompt wants every key for itself.
+    pub fn find_prompt_active(&self) -> bool {
+        self.find_prompt.is_some()
+    }
+
+    pub fn modal_active(&self) -> bool {
+        self.filter_prompt_active() || self.column_picker_active() || self.find_prompt_active() || self.help_open
+    }
+
+    pub fn clear_status(&mut self) {
+        self.status = None;
+    }
+
+    pub fn open_column_picker(&mut self) {
+        if !self.in_detail() && self.tab == Tab::Sockets {
+            self.column_picker = Some(ColumnPicker::new(&self.overview));
+        }
+    }
+
+    pub fn on_column_picker_key(&mut self, key: Key) {
+        let picker = match self.column_picker.as_mut() {
+            Some(picker) => picker,
+            None => return,
+        };
+        if let PickerResult::Closed = picker.on_key(key, &mut self.overview) {
+            let changed = picker.changed(&self.overview);
+            self.column_picker = None;
+            // don't go writing a config file when nothing changed
+            if !changed {
+                return;
+            }
+            // a fleet keeps its own layout, see `[fleet] columns`
+            let section = match self.overview.hosts.is_empty() {
+                true => None,
+                false => Some("fleet"),
+            };
+            let saved = match &self.config_path {
+                Some(path) => config::save_columns(path, section, &self.overview.layout_ids()),
+                None => Err("can't work out where the config file lives".into()),
+            };
+            if let Err(err) = saved {
+                self.status = Some(format!("couldn't save column layout: {}", err));
+            }
+        }
+    }
+
+    // Saves the history of the socket in the detail view, or the one selected on
+    // the Sockets or Closed tab.
+    pub fn export_history(&mut self) {
+        let history = match (&self.detail, self.listing().map(|listing| listing.selected_target())) {
+            (Some(detail), _) => Some(&detail.history),
+            (None, Some(Some(Target::Closed(seq)))) => {
+                self.overview.closed.iter().find(|closed| closed.seq == seq).map(|closed| &*closed.history)
+            }
+            (None, None) if self.group.is_none() => self.overview
+                .selected_socket()
+                .and_then(|sock| self.overview.find(sock.id()))
+                .map(|(_, history)| history),
+            _ => None,
+        };
+        let history = match history {
+            Some(history) => history,
+            None => {
+                self.status = Some("select a socket to save its history".to_string());
+                return;
+            }
+        };
+        let path = export::file_name("history", &file_stamp(SystemTime::now()));
+        // a replay's samples were taken on its own clock
+        let now = self.overview.captured_at();
+        let saved = export::save(&path, |out| export::write_history(out, history, now));
+        self.status = Some(saved_message(&path, saved));
+    }
+
+    // Saves the current tab's table. The socket table gets plain numbers, the
+    // others are saved as shown.
+    pub fn export_table(&mut self) {
+        if self.in_detail() {
+            return;
+        }
+        let path = export::file_name(&self.tab.title().to_lowercase(), &file_stamp(SystemTime::now()));
+        let saved = match self.listing() {
+            Some(listing) => export::save(&path, |out| {
+                export::write_row(out, listing.titles())?;
+                listing.items.iter().try_for_each(|row| export::write_row(out, row))
+            }),
+            None => export::save(&path, |out| {
+                export::write_row(out, &export::table_header(&self.overview))?;
+                export::write_table(out, &self.overview)
+            }),
+        };
+        self.status = Some(saved_message(&path, saved));
+    }
+
+    pub fn open_filter_prompt(&mut self) {
+        if self.in_detail() || self.filter_prompt.is_some() {
+            return;
+        }
+        self.filter_prompt = Some(FilterPrompt {
+            input: self.current_filter().source.clone(),
+            error: None,
+            previous: self.current_filter().clone(),
+        });
+    }
+
+    // Every edit re-parses the input and, if it is valid, applies it straight
+    // away so the table previews the result. Invalid input leaves the last good
+    // filter in place and highlights the problem instead.
+    pub fn on_filter_key(&mut self, key: Key) {
+        let prompt = match self.filter_prompt.as_mut() {
+            Some(prompt) => prompt,
+            None => return,
+        };
+        match key {
+            Key::Char('\n') => {
+                if prompt.error.is_none() {
+                    self.filter_prompt = None;
+                }
+                return;
+            }
+            Key::Esc => {
+                let previous = prompt.previous.clone();
+                self.filter_prompt = None;
+                self.set_filter(previous);
+                return;
+            }
+            Key::Backspace => {
+                prompt.input.pop();
+            }
+            Key::Ctrl('u') => {
+                prompt.input.clear();
+            }
+            Key::Char(c) => {
+                prompt.input.push(c);
+            }
+            _ => return,
+        }
+        match Filter::parse(&prompt.input) {
+            Ok(filter) => {
+                prompt.error = None;
+                self.set_filter(filter);
+            }
+            Err(err) => prompt.error = Some(err),
+        }
+    }
+
+    pub fn open_find_prompt(&mut self) {
+        if self.in_detail() || self.modal_active() {
+            return;
+        }
+        let origin = self.rows().selected_row();
+        self.find_prompt = Some(FindPrompt { input: String::new(), origin });
+    }
+
+    // Jumps as you type, starting from wherever the selection was when the
+    // prompt opened.
+    pub fn on_find_key(&mut self, key: Key) {
+        let prompt = match self.find_prompt.as_mut() {
+            Some(prompt) => prompt,
+            None => return,
+        };
+        match key {
+            Key::Char('\n') => {
+                self.last_find = prompt.input.clone();
+                self.find_prompt = None;
+                return;
+            }
+            Key::Esc => {
+                let origin = prompt.origin;
+                self.find_prompt = None;
+                self.rows().select(origin);
+                return;
+            }
+            Key::Backspace => {
+                prompt.input.pop();
+            }
+            Key::Ctrl('u') => {
+                prompt.input.clear();
+            }
+            Key::Char(c) => {
+                prompt.input.push(c);
+            }
+            _ => return,
+        }
+        // the row we started on counts as a match too
+        let (origin, input) = (prompt.origin, prompt.input.clone());
+        let rows = self.rows();
+        let count = rows.items().len().max(1);
+        let start = (origin.unwrap_or(0) + count - 1) % count;
+        if let Some(row) = table::find_row(rows.items(), &input, start, true) {
+            rows.select(Some(row));
+        }
+    }
+
+    pub fn find_next(&mut self, forward: bool) {
+        if self.in_detail() || self.last_find.is_empty() {
+            return;
+        }
+        let needle = self.last_find.clone();
+        let rows = self.rows();
+        let from = rows.selected_row().unwrap_or(0);
+        match table::find_row(rows.items(), &needle, from, forward) {
+            Some(row) => rows.select(Some(row)),
+            None => self.status = Some(format!("no row matches '{}'", needle)),
+        }
+    }
+
+    pub fn toggle_spark_scale(&mut self) {
+        if self.in_detail() || self.tab != Tab::Sockets {
+            return;
+        }
+        self.overview.toggle_spark_scale();
+        self.status = Some(match self.overview.shared_spark_scale {
+            true => "sparklines share a scale down each column".to_string(),
+            false => "sparklines scaled to their own peak".to_string(),
+        });
+    }
+
+    // A replay's speed is the recording's, so these change how fast it plays instead.
+    pub fn slower(&mut self) {
+        match self.overview.player_mut() {
+            Some(player) => {
+                player.slower();
+                self.status = Some(format!("playing at {}x", player.speed()));
+            }
+            None => self.set_interval(event::longer_interval(self.interval)),
+        }
+    }
+
+    pub fn faster(&mut self) {
+        match self.overview.player_mut() {
+            Some(player) => {
+                player.faster();
+                self.status = Some(format!("playing at {}x", player.speed()));
+            }
+            None => self.set_interval(event::shorter_interval(self.interval)),
+        }
+    }
+
+    fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+        self.status = Some(format!("refreshing every {}", event::format_interval(interval)));
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if let Some(player) = self.overview.player_mut() {
+            match (player.playing, player.at_end()) {
+                (true, _) => player.pause(),
+                (false, false) => player.play(),
+                // played to the end, so start again
+                (false, true) => {
+                    player.play();
+                    self.seek(0);
+                }
+            }
+            return;
+        }
+        match self.overview.paused.is_some() {
+            true => self.overview.resume(),
+            false => self.overview.pause(),
+        }
+        self.refresh_views();
+    }
+
+    // Stepping through a replay pauses it and moves a sample at a time.
+    pub fn step_back(&mut self) {
+        match self.overview.player_mut() {
+            Some(player) => {
+                player.pause();
+                let position = player.position();
+                self.seek(position.saturating_sub(1));
+            }
+            None => {
+                self.overview.step_back();
+                self.refresh_views();
+            }
+        }
+    }
+
+    pub fn step_forward(&mut self) {
+        match self.overview.player_mut() {
+            Some(player) => {
+                player.pause();
+                let position = player.position();
+                self.seek(position + 1);
+            }
+            None => {
+                self.overview.step_forward();
+                self.refresh_views();
+            }
+        }
+    }
+
+    // Jumps a tenth of the way through a replay, by time rather than samples.
+    pub fn seek_back(&mut self) {
+        self.seek_by(-0.1);
+    }
+
+    pub fn seek_forward(&mut self) {
+        self.seek_by(0.1);
+    }
+
+    fn seek_by(&mut self, fraction: f64) {
+        let frame = match self.overview.player() {
+            Some(player) => player.frame_at(player.fraction(player.position()) + fraction),
+            None => return,
+        };
+        self.seek(frame);
+    }
+
+    fn seek(&mut self, frame: usize) {
+        self.overview.seek(frame);
+        self.refresh_views();
+    }
+
+    // Brings whatever is on screen besides the socket table up to date with it.
+    fn refresh_views(&mut self) {
+        if let Some(detail) = self.detail.as_mut() {
+            detail.update(&self.overview);
+        }
+        self.rebuild_listing();
     }
 
     // TODO: result return here?
     pub fn on_tick(&mut self) {
         self.overview.refresh();
+        self.refresh_views();
+        if let Some(err) = self.overview.player_mut().and_then(|player| player.error.take()) {
+            self.status = Some(format!("couldn't read the recording: {}", err));
+        } else if let Some(err) = self.overview.record_error.take() {
+            self.status = Some(format!("recording stopped: {}", err));
+        } else if self.overview.detached {
+            self.status = Some("the daemon has gone away, showing the last of its samples".to_string());
+        } else if !self.overview.down.is_empty() {
+            self.status = Some(format!("can't reach {}", self.overview.down.join(", ")));
+        }
     }
 
     pub fn enter_detail_view(&mut self) {
-        if self.detail_toggle == false {
-            self.detail_toggle = true;
+        if self.in_detail() {
+            return;
+        }
+        match self.listing().map(|listing| listing.selected_target()) {
+            // nothing selected
+            Some(None) => {}
+            // a closed socket gets the same view as an open one, stuck on its last sample
+            Some(Some(Target::Closed(seq))) => {
+                if let Some(closed) = self.overview.closed.iter().find(|closed| closed.seq == seq) {
+                    self.detail = Some(Detail { socket: closed.socket.clone(), history: (*closed.history).clone(), closed: true });
+                }
+            }
+            Some(Some(target)) => self.group = Some(target),
+            None => {
+                let id = match self.overview.selected_socket() {
+                    Some(sock) => sock.id(),
+                    None => return,
+                };
+                if let Some((socket, history)) = self.overview.find(id) {
+                    self.detail = Some(Detail { socket: socket.clone(), history: history.clone(), closed: false });
+                }
+            }
         }
+        self.detail_scroll = 0;
     }
 
-    pub fn exit_detail_view(&mut self) {
-        if self.detail_toggle == true {
-            self.detail_toggle = false;
+    // j/k move the selection in the table and scroll the socket info in the detail view
+    pub fn move_down(&mut self) {
+        match self.in_detail() {
+            false => self.rows().next(),
+            true => self.detail_scroll = self.detail_scroll.saturating_add(1),
         }
     }
 
+    pub fn move_up(&mut self) {
+        match self.in_detail() {
+            false => self.rows().previous(),
+            true => self.detail_scroll = self.detail_scroll.saturating_sub(1),
+        }
+    }
+
+    // How far a page moves: the visible table rows, or socket info lines in the
+    // detail view.
+    fn page_size(&self) -> isize {
+        let rows = match self.in_detail() {
+            true => self.hits.stats.height.saturating_sub(2),
+            false => self.hits.rows.height,
+        };
+        rows.max(1) as isize
+    }
+
+    fn scroll_by(&mut self, delta: isize) {
+        match self.in_detail() {
+            false => self.rows().move_by(delta),
+            true if delta < 0 => self.detail_scroll = self.detail_scroll.saturating_sub(delta.unsigned_abs() as u16),
+            true => self.detail_scroll = self.detail_scroll.saturating_add(delta as u16),
+        }
+    }
+
+    pub fn page_down(&mut self) {
+        self.scroll_by(self.page_size());
+    }
+
+    pub fn page_up(&mut self) {
+        self.scroll_by(-self.page_size());
+    }
+
+    pub fn half_page_down(&mut self) {
+        self.scroll_by((self.page_size() / 2).max(1));
+    }
+
+    pub fn half_page_up(&mut self) {
+        self.scroll_by(-(self.page_size() / 2).max(1));
+    }
+
+    pub fn go_top(&mut self) {
+        match self.in_detail() {
+            false => self.rows().first(),
+            true => self.detail_scroll = 0,
+        }
+    }
+
+    pub fn go_bottom(&mut self) {
+        match self.in_detail() {
+            false => self.rows().last(),
+            // clamped to the end of the text when it's drawn
+            true => self.detail_scroll = u16::MAX,
+        }
+    }
+
+    // Shows one chart at a time, stepping to the next one if we already were.
+    pub fn cycle_chart(&mut self) {
+        if self.detail.is_none() {
+            return;
+        }
+        match self.charts_tiled {
+            true => self.charts_tiled = false,
+            false => self.chart_panel = self.chart_panel.next(),
+        }
+    }
+
+    pub fn toggle_chart_tiling(&mut self) {
+        if self.detail.is_some() {
+            self.charts_tiled = !self.charts_tiled;
+        }
+    }
+
+    pub fn zoom_in_charts(&mut self) {
+        if self.detail.is_some() {
+            self.chart_window = charts::zoom_in(self.chart_window, self.overview.options.retention.as_secs());
+        }
+    }
+
+    pub fn zoom_out_charts(&mut self) {
+        if self.detail.is_some() {
+            self.chart_window = charts::zoom_out(self.chart_window, self.overview.options.retention.as_secs());
+        }
+    }
+
+    pub fn exit_detail_view(&mut self) {
+        self.detail = None;
+        self.group = None;
+    }
+
     fn draw_overview<B: Backend>(&mut self, frame: &mut Frame<B>, area: Rect) {
         let rects = Layout::default()
             .constraints([Constraint::Percentage(100)].as_ref())
             .margin(0)
             .split(area);
 
-        let selected_style = Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD);
-        let normal_style = Style::default().fg(Color::White);
-        //let header = ["Source", "Dest", "State", "Send", "Recv", "Loss"];
-        let rows = self.overview
-            .items
+        let selected_style = self.theme.selected;
+        let normal_style = self.theme.text;
+
+        // header titles, plus whatever a click on each one should sort by
+        let (titles, columns, sort): (Vec<&str>, Vec<usize>, _) = match self.listing() {
+            Some(listing) => (listing.titles().to_vec(), (0..listing.titles().len()).collect(), listing.sort),
+            None => (
+                self.overview.layout.iter().map(|column| self.overview.registry[*column].title).collect(),
+                self.overview.layout.clone(),
+                self.overview.sort,
+            ),
+        };
+        let header: Vec<String> = titles
+            .iter()
+            .zip(columns.iter())
+            .map(|(title, column)| {
+                match sort {
+                    Some(sort) if sort.column == *column && sort.descending => format!("{} ▼", title),
+                    Some(sort) if sort.column == *column => format!("{} ▲", title),
+                    _ => title.to_string(),
+                }
+            })
+            .collect();
+
+        let items: Vec<Vec<String>> = self.rows().items().to_vec();
+        // size every column to fit its widest cell, the last one soaks up whatever is left
+        let mut widths: Vec<Constraint> = header
+            .iter()
+            .enumerate()
+            .map(|(i, title)| {
+                let widest = items
+                    .iter()
+                    .map(|row| row[i].chars().count())
+                    .chain(std::iter::once(title.chars().count()))
+                    .max()
+                    .unwrap_or(0);
+                Constraint::Length(widest as u16)
+            })
+            .collect();
+        if let Some(Constraint::Length(last)) = widths.pop() {
+            widths.push(Constraint::Min(last));
+        }
+
+        let highlights: &[Highlight] = match self.tab {
+            Tab::Sockets => &self.overview.highlights,
+            _ => &[],
+        };
+        let rows = items
             .iter()
-            .map(|i| Row::new(i.iter().map(|s| s.clone())).style(normal_style));
-		//let rows = Row::new(Vec::from_iter(self.overview.items.iter()));
-		//	  .map(|s| )
+            .enumerate()
+            .map(|(row, i)| {
+                let style = match highlights.get(row).unwrap_or(&Highlight::Normal) {
+                    Highlight::Alert => self.theme.alert_row,
+                    Highlight::Closing => self.theme.closing_row,
+                    Highlight::New => self.theme.new_row,
+                    Highlight::Normal => normal_style,
+                };
+                Row::new(i.iter().cloned()).style(style)
+            });
         let t = Table::new(rows)
             .header(
-                Row::new(vec!["Source", "Dest", "State", "Send", "Recv", "Loss"])
-                	.style(Style::default().fg(Color::Yellow))
+                Row::new(header)
+                	.style(self.theme.header)
                 	.bottom_margin(1),            
 			)
-            .block(Block::default().borders(Borders::ALL).title("TCPtop"))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(Line::from(vec![Span::raw(self.overview_title()), self.pause_banner()]))
+                    .title(Title::from(self.position_title()).position(Position::Bottom).alignment(Alignment::Right)),
+            )
             .highlight_style(selected_style)
             .highlight_symbol(">> ")
-            .widths(&[
-                Constraint::Percentage(25),
-                Constraint::Percentage(25),
-                Constraint::Percentage(20),
-                Constraint::Percentage(10),
-                Constraint::Percentage(10),
-                Constraint::Percentage(10),
-            ]);
-        frame.render_stateful_widget(t, rects[0], &mut self.overview.state);
+            .column_spacing(2)
+            .widths(&widths);
+        frame.render_stateful_widget(t, rects[0], self.rows().state());
+        self.record_table_areas(rects[0], &widths, &columns);
+
+        // only worth a scrollbar once the rows don't all fit
+        if items.len() > self.hits.rows.height as usize {
+            let mut scrollbar = ScrollbarState::default()
+                .content_length(items.len().min(u16::MAX as usize) as u16)
+                .viewport_content_length(self.hits.rows.height)
+                .position(self.rows().selected_row().unwrap_or(0).min(u16::MAX as usize) as u16);
+            let track = Rect { y: self.hits.rows.y, height: self.hits.rows.height, ..rects[0] };
+            frame.render_stateful_widget(
+                Scrollbar::default().orientation(ScrollbarOrientation::VerticalRight).begin_symbol(None).end_symbol(None),
+                track,
+                &mut scrollbar,
+            );
+        }
 
     }
 
+    fn overview_title(&self) -> String {
+        let filter = self.current_filter();
+        if filter.is_empty() {
+            return "TCPtop".to_string();
+        }
+        match self.listing() {
+            Some(listing) => format!("TCPtop [filter: {}] {} rows", filter.source, listing.items.len()),
+            None => format!(
+                "TCPtop [filter: {}] {}/{}",
+                filter.source,
+                self.overview.sockets.len(),
+                self.overview.shown().0.len()
+            ),
+        }
+    }
+
+    fn pause_banner(&self) -> Span<'static> {
+        match &self.overview.paused {
+            Some(paused) => Span::styled(
+                format!(" PAUSED at {} (-{}) ", clock(paused.at), paused.age),
+                self.theme.critical,
+            ),
+            None => Span::raw(""),
+        }
+    }
+
+    fn position_title(&mut self) -> String {
+        let rows = self.rows();
+        match rows.selected_row() {
+            Some(row) => format!(" row {} of {} ", row + 1, rows.items().len()),
+            None => format!(" {} rows ", rows.items().len()),
+        }
+    }
+
+    // Mirrors how the table lays itself out: a border, the highlight symbol gutter
+    // when something is selected, the header and its margin, then the rows.
+    fn record_table_areas(&mut self, area: Rect, widths: &[Constraint], columns: &[usize]) {
+        let inner = Block::default().borders(Borders::ALL).inner(area);
+        let gutter = match self.rows().selected_row() {
+            Some(_) => ">> ".len() as u16,
+            None => 0,
+        };
+        let mut x = inner.x + gutter;
+        self.hits.headers.clear();
+        for (constraint, column) in widths.iter().zip(columns.iter()) {
+            let width = match constraint {
+                Constraint::Length(width) => *width,
+                _ => (inner.x + inner.width).saturating_sub(x),
+            };
+            self.hits.headers.push((x, width, *column));
+            x += width + 2;
+        }
+        self.hits.header_y = inner.y;
+        self.hits.rows = Rect { y: inner.y + 2, height: inner.height.saturating_sub(2), ..inner };
+    }
+
+    // Returns an action to carry out when the click landed on the help bar.
+    pub fn on_mouse(&mut self, event: MouseEvent) -> Option<Action> {
+        if self.help_open {
+            if let MouseEvent::Press(..) = event {
+                self.help_open = false;
+            }
+            return None;
+        }
+        if self.modal_active() {
+            return None;
+        }
+        match event {
+            // termion counts from 1, ratatui from 0
+            MouseEvent::Press(button, x, y) => self.on_press(button, x.saturating_sub(1), y.saturating_sub(1)),
+            _ => None,
+        }
+    }
+
+    fn on_press(&mut self, button: MouseButton, x: u16, y: u16) -> Option<Action> {
+        match button {
+            MouseButton::Left if y == self.hits.help_y => {
+                return self.hits.help.iter().find(|(start, width, _)| x >= *start && x < start + width).map(|hit| hit.2);
+            }
+            MouseButton::Left if contains(self.hits.timeline, x, y) => {
+                let fraction = (x - self.hits.timeline.x) as f64 / self.hits.timeline.width.saturating_sub(1).max(1) as f64;
+                if let Some(frame) = self.overview.player().map(|player| player.frame_at(fraction)) {
+                    self.seek(frame);
+                }
+            }
+            MouseButton::Left if y == self.hits.tabs_y => {
+                let tab = self.hits.tabs.iter().find(|(start, width, _)| x >= *start && x < start + width);
+                if let Some((_, _, tab)) = tab {
+                    self.switch_tab(*tab);
+                }
+            }
+            MouseButton::Left if !self.in_detail() => self.on_table_click(x, y),
+            MouseButton::WheelDown | MouseButton::WheelUp => {
+                let down = button == MouseButton::WheelDown;
+                match self.detail.is_some() {
+                    true if contains(self.hits.charts, x, y) && down => self.zoom_out_charts(),
+                    true if contains(self.hits.charts, x, y) => self.zoom_in_charts(),
+                    _ if down => self.move_down(),
+                    _ => self.move_up(),
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn on_table_click(&mut self, x: u16, y: u16) {
+        if y == self.hits.header_y {
+            let column = self.hits.headers.iter().find(|(start, width, _)| x >= *start && x < start + width);
+            if let Some((_, _, column)) = column.copied() {
+                self.sort_by(column);
+            }
+            return;
+        }
+        if !contains(self.hits.rows, x, y) {
+            return;
+        }
+        let top = self.hits.rows.y;
+        let rows = self.rows();
+        let row = rows.state().offset() + (y - top) as usize;
+        if row >= rows.items().len() {
+            return;
+        }
+        rows.select(Some(row));
+        let now = Instant::now();
+        match self.last_click {
+            Some((at, previous)) if previous == row && now.duration_since(at) < DOUBLE_CLICK => {
+                self.last_click = None;
+                self.enter_detail_view();
+            }
+            _ => self.last_click = Some((now, row)),
+        }
+    }
+
     fn draw_detail<B: Backend>(&mut self, frame: &mut Frame<B>, area: Rect) {
         let chunks = Layout::default()
             .direction(Direction::Horizontal)
@@ -142,209 +1150,292 @@ impl<'a> CLI<'a> {
                 .as_ref(),
             )
             .split(area);
+        self.hits.stats = chunks[0];
+        self.hits.charts = chunks[1];
         self.draw_detail_stats(frame, chunks[0]);
         self.draw_detail_charts(frame, chunks[1]);
     }
 
     fn draw_detail_stats<B: Backend>(&mut self, frame: &mut Frame<B>, area: Rect) {
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints(
-                [
-                    Constraint::Ratio(1, 2),
-                    Constraint::Ratio(1, 2),
-                ]
-                .as_ref(),
-            )
-            .split(area);
-        let detail_entry = &self.overview.sockets[self.overview.state.selected().unwrap()];
-        let detail_history = &self.overview.history.get(&detail_entry.inode).unwrap();
-        let tcp_info = detail_entry.info.as_ref().unwrap();
-        let chart_data_window = vecdequeue_as_chart(&detail_history.congestion_window);
-        let chart_bounds_window = determine_min_max_values(&detail_history.congestion_window);
-        let chart_labels_window = vec![
-            Span::styled(chart_bounds_window[0].to_string(), Style::default().add_modifier(Modifier::ITALIC)),
-            Span::styled((chart_bounds_window[1]/2.0).to_string(), Style::default().add_modifier(Modifier::ITALIC)),
-            Span::styled(chart_bounds_window[1].to_string(), Style::default().add_modifier(Modifier::ITALIC)),
-        ];
+        let detail = self.detail.as_ref().unwrap();
+        let detail_entry = &detail.socket;
+        let detail_history = &detail.history;
+        let bold = Style::default().add_modifier(Modifier::BOLD);
 
-        // theres gotta be a better way....
-        let mut text = Text::from(Spans::from(vec![
-            Span::styled("Src: ", Style::default().add_modifier(Modifier::BOLD)),
-            Span::raw(format!("{}:{}\n", detail_entry.src.ip().to_string(), detail_entry.src.port().to_string())),
-        ]));
-        text.extend(Text::from(Spans::from(vec![
-            Span::styled("Dst: ", Style::default().add_modifier(Modifier::BOLD)),
-            Span::raw(format!("{}:{}\n", detail_entry.dst.ip().to_string(), detail_entry.dst.port().to_string())),
-        ])));
-        text.extend(Text::from(Spans::from(vec![
-            Span::styled("Inode: ", Style::default().add_modifier(Modifier::BOLD)),
-            Span::raw(format!("{}\n", detail_entry.inode)),
-        ])));
-        text.extend(Text::from(Spans::from(vec![
-            Span::styled("Retransmits: ", Style::default().add_modifier(Modifier::BOLD)),
-            Span::raw(format!("{}\n", tcp_info.tcpi_total_retrans)),
-        ])));
-        text.extend(Text::from(Spans::from(vec![
-            Span::styled("RTO: ", Style::default().add_modifier(Modifier::BOLD)),
-            Span::raw(format!("{}\n", tcp_info.tcpi_rto)),
-        ])));
-        text.extend(Text::from(Spans::from(vec![
-            Span::styled("ATO: ", Style::default().add_modifier(Modifier::BOLD)),
-            Span::raw(format!("{}\n", tcp_info.tcpi_ato)),
-        ])));
-        text.extend(Text::from(Spans::from(vec![
-            Span::styled("Send MSS: ", Style::default().add_modifier(Modifier::BOLD)),
-            Span::raw(format!("{}\n", tcp_info.tcpi_snd_mss)),
-        ])));
-        text.extend(Text::from(Spans::from(vec![
-            Span::styled("Recv Mss: ", Style::default().add_modifier(Modifier::BOLD)),
-            Span::raw(format!("{}\n", tcp_info.tcpi_rcv_mss)),
-        ])));
-        text.extend(Text::from(Spans::from(vec![
-            Span::styled("Lost: ", Style::default().add_modifier(Modifier::BOLD)),
-            Span::raw(format!("{}\n", tcp_info.tcpi_lost)),
-        ])));
-        text.extend(Text::from(Spans::from(vec![
-            Span::styled("RTT: ", Style::default().add_modifier(Modifier::BOLD)),
-            Span::raw(format!("{}\n", tcp_info.tcpi_rtt)),
-        ])));
-        text.extend(Text::from(Spans::from(vec![
-            Span::styled("RTT variance: ", Style::default().add_modifier(Modifier::BOLD)),
-            Span::raw(format!("{}\n", tcp_info.tcpi_rttvar)),
-        ])));
-        text.extend(Text::from(Spans::from(vec![
-            Span::styled("Congestion window: ", Style::default().add_modifier(Modifier::BOLD)),
-            Span::raw(format!("{}\n", tcp_info.tcpi_snd_cwnd)),
-        ])));
-        text.extend(Text::from(Spans::from(vec![
-            Span::styled("Pacing rate: ", Style::default().add_modifier(Modifier::BOLD)),
-            Span::raw(format!("{}\n", tcp_info.tcpi_pacing_rate)),
-        ])));
+        let mut lines = Vec::new();
+        if detail.closed {
+            lines.push(Line::from(Span::styled(
+                " CLOSED - showing the last data seen ",
+                self.theme.critical,
+            )));
+        }
+        lines.extend(vec![
+            Line::from(vec![
+                Span::styled("Src: ", bold),
+                Span::raw(format!("{}:{}", detail_entry.src.ip(), detail_entry.src.port())),
+            ]),
+            Line::from(vec![
+                Span::styled("Dst: ", bold),
+                Span::raw(format!("{}:{}", detail_entry.dst.ip(), detail_entry.dst.port())),
+            ]),
+            Line::from(vec![
+                Span::styled("Inode: ", bold),
+                Span::raw(detail_entry.inode.to_string()),
+            ]),
+            Line::from(vec![
+                Span::styled("Process: ", bold),
+                Span::raw(match &detail_entry.process {
+                    Some(process) => format!("{} ({})", process.comm, process.pid),
+                    None => "-".to_string(),
+                }),
+            ]),
+            Line::from(vec![
+                Span::styled("Congestion control: ", bold),
+                Span::raw(detail_entry.cc.clone().unwrap_or_else(|| "-".to_string())),
+            ]),
+        ]);
+        lines.extend(info_lines(&detail_history.previous, &detail_history.current, &self.theme));
+
+        // keep the scroll position from running past the end of the text
+        let visible = area.height.saturating_sub(2);
+        let max_scroll = (lines.len() as u16).saturating_sub(visible);
+        self.detail_scroll = self.detail_scroll.min(max_scroll);
 
         let block = Block::default()
             .borders(Borders::ALL)
-            .title("Socket Info")
-            .title_style(Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD));
-        let paragraph = Paragraph::new(text)
+            .title(Line::from(vec![
+                Span::raw(format!("Socket Info [{}/{}]", self.detail_scroll + visible.min(lines.len() as u16), lines.len())),
+                self.pause_banner(),
+            ]))
+            .title_style(self.theme.title);
+        let paragraph = Paragraph::new(Text::from(lines))
             .block(block)
-            // HERE
-            .wrap(Wrap{trim: true});
-        let window_dataset = vec![Dataset::default()
-            .name("data")
-            .marker(symbols::Marker::Braille)
-            .style(Style::default().fg(Color::Yellow))
-            .graph_type(GraphType::Line)
-            .data(&chart_data_window)];
-        let window_chart = Chart::new(window_dataset)
-            .block(
-                Block::default()
-                    .title("Window")
-                    .title_style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
-                    .borders(Borders::ALL),
-            )
-            .x_axis(
-                Axis::default()
-                    .title("Seconds")
-                    .style(Style::default().fg(Color::Gray))
-                    .bounds([0.0, 30.0])
-                    // TODO: this should be dynamically determined
-                    .labels(self.seconds_labels.clone()),
-            )
-            .y_axis(
-                Axis::default()
-                    .title("Packets")
-                    .style(Style::default().fg(Color::Gray))
-                    .bounds(chart_bounds_window)
-                    .labels(chart_labels_window),
-            );
-        frame.render_widget(paragraph, chunks[0]);
-        frame.render_widget(window_chart, chunks[1]);
+            .scroll((self.detail_scroll, 0));
+        frame.render_widget(paragraph, area);
     }
 
     fn draw_detail_charts<B: Backend>(&mut self, frame: &mut Frame<B>, area: Rect) {
-        let detail_entry = &self.overview.sockets[self.overview.state.selected().unwrap()];
-        let detail_history = &self.overview.history.get(&detail_entry.inode).unwrap();
-        let chart_bounds_recv = determine_min_max_values(&detail_history.recv_bps);
-        let chart_bounds_send = determine_min_max_values(&detail_history.send_bps);
-        let chart_labels_recv = vec![
-            Span::styled(chart_bounds_recv[0].to_string(), Style::default().add_modifier(Modifier::ITALIC)),
-            Span::styled((chart_bounds_recv[1]/2.0).to_string(), Style::default().add_modifier(Modifier::ITALIC)),
-            Span::styled(chart_bounds_recv[1].to_string(), Style::default().add_modifier(Modifier::ITALIC)),
-        ];
-        let chart_labels_send = vec![
-            Span::styled(chart_bounds_send[0].to_string(), Style::default().add_modifier(Modifier::ITALIC)),
-            Span::styled((chart_bounds_send[1]/2.0).to_string(), Style::default().add_modifier(Modifier::ITALIC)),
-            Span::styled(chart_bounds_send[1].to_string(), Style::default().add_modifier(Modifier::ITALIC)),
-        ];
-        let chart_data_recv = vecdequeue_as_chart(&detail_history.recv_bps);
-        let chart_data_send = vecdequeue_as_chart(&detail_history.send_bps);
-        let chunks = Layout::default()
+        let detail_history = &self.detail.as_ref().unwrap().history;
+
+        if !self.charts_tiled {
+            charts::draw(frame, area, self.chart_panel, detail_history, self.chart_window, &self.theme);
+            return;
+        }
+
+        // tiled: every panel in a grid two wide
+        let rows = (Panel::ALL.len() as u32).div_ceil(2);
+        let row_chunks = Layout::default()
             .direction(Direction::Vertical)
-            .constraints(
-                [
-                    Constraint::Ratio(1, 2),
-                    Constraint::Ratio(1, 2),
-                ]
-                .as_ref(),
-            )
+            .constraints((0..rows).map(|_| Constraint::Ratio(1, rows)).collect::<Vec<_>>())
             .split(area);
-        let send_datasets = vec![Dataset::default()
-            .name("data")
-            .marker(symbols::Marker::Braille)
-            .style(Style::default().fg(Color::Yellow))
-            .graph_type(GraphType::Line)
-            .data(&chart_data_send)];
-        let recv_datasets = vec![Dataset::default()
-            .name("data")
-            .marker(symbols::Marker::Braille)
-            .style(Style::default().fg(Color::Yellow))
-            .graph_type(GraphType::Line)
-            .data(&chart_data_recv)];
-        let send_chart = Chart::new(send_datasets)
-            .block(
-                Block::default()
-                    .title("Send")
-                    .title_style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
-                    .borders(Borders::ALL),
-            )
-            .x_axis(
-                Axis::default()
-                    .title("Seconds")
-                    .style(Style::default().fg(Color::Gray))
-                    .bounds([0.0, 30.0])
-                    // TODO: this should be dynamically determined
-                    .labels(self.seconds_labels.clone()),
-            )
-            .y_axis(
-                Axis::default()
-                    .title("Rate")
-                    .style(Style::default().fg(Color::Gray))
-                    .bounds(chart_bounds_send)
-                    .labels(chart_labels_send),
-            );
-        let recv_chart = Chart::new(recv_datasets)
-            .block(
-                Block::default()
-                    .title("Receive")
-                    .title_style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
-                    .borders(Borders::ALL),
-            )
-            .x_axis(
-                Axis::default()
-                    .title("Time")
-                    .style(Style::default().fg(Color::Gray))
-                    .bounds([0.0, 30.0])
-                    .labels(self.seconds_labels.clone()),
-            )
-            .y_axis(
-                Axis::default()
-                    .title("Rate")
-                    .style(Style::default().fg(Color::Gray))
-                    .bounds(chart_bounds_recv)
-                    .labels(chart_labels_recv),
-            );
-        frame.render_widget(send_chart, chunks[0]);
-        frame.render_widget(recv_chart, chunks[1]);
+        for (row, pair) in Panel::ALL.chunks(2).enumerate() {
+            let cells = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)].as_ref())
+                .split(row_chunks[row]);
+            for (cell, panel) in pair.iter().enumerate() {
+                charts::draw(frame, cells[cell], *panel, detail_history, self.chart_window, &self.theme);
+            }
+        }
+    }
+    // The drill-down for a listener, host or process: a summary and every
+    // connection that belongs to it.
+    fn draw_group<B: Backend>(&mut self, frame: &mut Frame<B>, area: Rect) {
+        let target = self.group.unwrap();
+        let (sockets, history) = self.overview.shown();
+        let members: Vec<&DiagWithInode> = sockets.iter().filter(|sock| target.contains(sock)).collect();
+        let listeners: Vec<&DiagWithInode> = self.overview
+            .shown_listeners()
+            .iter()
+            .filter(|listener| match target {
+                Target::Listener(addr) => listener.src == addr,
+                _ => target.contains(listener),
+            })
+            .collect();
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let field = |label: &str, value: String| Line::from(vec![Span::styled(format!("{:<14}", label), bold), Span::raw(value)]);
+        let owner = |sock: &DiagWithInode| match &sock.process {
+            Some(process) => format!("{} ({})", process.comm, process.pid),
+            None => "-".to_string(),
+        };
+
+        let mut lines = Vec::new();
+        let title = match target {
+            Target::Listener(addr) => {
+                match listeners.first() {
+                    Some(listener) => {
+                        let info = listener.info.as_ref().unwrap();
+                        lines.push(field("Process:", owner(listener)));
+                        lines.push(field("Accept queue:", format!("{} of {}", info.tcpi_unacked, info.tcpi_sacked)));
+                    }
+                    None => lines.push(Line::from(Span::styled(" NOT LISTENING any more ", self.theme.critical))),
+                }
+                format!("Listener {}", addr)
+            }
+            Target::Host(ip) => {
+                lines.push(field("Address:", ip.to_string()));
+                format!("Host {}", self.overview.resolve_name(ip))
+            }
+            Target::Process(pid) => {
+                let command = members.iter().chain(listeners.iter()).find_map(|sock| sock.process.as_ref());
+                lines.push(field("Command:", command.map(|process| process.comm.clone()).unwrap_or_else(|| "-".to_string())));
+                let addrs: Vec<String> = listeners.iter().map(|listener| listener.src.to_string()).collect();
+                lines.push(field("Listening on:", if addrs.is_empty() { "-".to_string() } else { addrs.join(", ") }));
+                match pid {
+                    0 => "Sockets without a known owner".to_string(),
+                    pid => format!("Process {}", pid),
+                }
+            }
+            Target::Closed(_) => String::new(),
+        };
+        let send: u64 = members.iter().map(|sock| history[&sock.id()].send_bps[0]).sum();
+        let recv: u64 = members.iter().map(|sock| history[&sock.id()].recv_bps[0]).sum();
+        lines.push(field("Send:", table::friendly_transfer_str(send)));
+        lines.push(field("Recv:", table::friendly_transfer_str(recv)));
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(format!("Connections ({})", members.len()), self.theme.heading)));
+        lines.push(Line::from(Span::styled(
+            format!("{:<24}{:<24}{:<13}{:>12}{:>12}{:>10}", "Source", "Dest", "State", "Send", "Recv", "RTT ms"),
+            self.theme.header,
+        )));
+        for sock in &members {
+            let info = sock.info.as_ref().unwrap();
+            let history = &history[&sock.id()];
+            lines.push(Line::from(format!(
+                "{:<24}{:<24}{:<13}{:>12}{:>12}{:>10.2}",
+                sock.src.to_string(),
+                sock.dst.to_string(),
+                TCP_STATE::from_u8(info.tcpi_state).to_string(),
+                table::friendly_transfer_str(history.send_bps[0]),
+                table::friendly_transfer_str(history.recv_bps[0]),
+                info.tcpi_rtt as f64 / 1000.0,
+            )));
+        }
+
+        self.hits.stats = area;
+        let visible = area.height.saturating_sub(2);
+        let max_scroll = (lines.len() as u16).saturating_sub(visible);
+        self.detail_scroll = self.detail_scroll.min(max_scroll);
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(Line::from(vec![Span::raw(title), self.pause_banner()]))
+            .title_style(self.theme.title);
+        let paragraph = Paragraph::new(Text::from(lines))
+            .block(block)
+            .scroll((self.detail_scroll, 0));
+        frame.render_widget(paragraph, area);
+    }
+}
+
+fn tab_action(tab: Tab) -> Action {
+    match tab {
+        Tab::Sockets => Action::SocketsTab,
+        Tab::Listeners => Action::ListenersTab,
+        Tab::Hosts => Action::HostsTab,
+        Tab::Processes => Action::ProcessesTab,
+        Tab::Closed => Action::ClosedTab,
+    }
+}
+
+// Every tcp_info field grouped under a heading, with how much it moved since the
+// previous sample. Values that changed are highlighted.
+fn info_lines(previous: &TCPInfo, current: &TCPInfo, theme: &Theme) -> Vec<Line<'static>> {
+    let heading = theme.heading;
+    let changed = theme.changed;
+    let mut lines = Vec::new();
+    for group in Group::ALL.iter() {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(group.title(), heading)));
+        for field in INFO_FIELDS.iter().filter(|field| field.group == *group) {
+            let delta = field.delta(previous, current);
+            let value_style = match delta {
+                Some(_) => changed,
+                None => Style::default(),
+            };
+            let mut spans = vec![
+                Span::styled(format!("{:<22}", field.label), Style::default().add_modifier(Modifier::BOLD)),
+                Span::styled(field.format(current), value_style),
+            ];
+            if let Some(delta) = delta {
+                spans.push(Span::styled(format!("  ({})", delta), theme.muted));
+            }
+            lines.push(Line::from(spans));
+        }
+    }
+    lines
+}
+
+fn local_time(at: SystemTime) -> Option<libc::tm> {
+    let secs = at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0) as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&secs, &mut tm) }.is_null() {
+        return None;
+    }
+    Some(tm)
+}
+
+// Local wall clock time as HH:MM:SS.
+pub fn clock(at: SystemTime) -> String {
+    match local_time(at) {
+        Some(tm) => format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec),
+        None => "??:??:??".to_string(),
+    }
+}
+
+// For file names, e.g. 20261018-125307.
+fn file_stamp(at: SystemTime) -> String {
+    match local_time(at) {
+        Some(tm) => format!(
+            "{}{:02}{:02}-{:02}{:02}{:02}",
+            tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday, tm.tm_hour, tm.tm_min, tm.tm_sec
+        ),
+        None => "unknown".to_string(),
+    }
+}
+
+fn saved_message(path: &Path, saved: io::Result<()>) -> String {
+    match saved {
+        Ok(()) => format!("saved {}", path.display()),
+        Err(err) => format!("couldn't save {}: {}", path.display(), err),
+    }
+}
+
+// The same with the date in front, for output that outlives the day.
+pub fn timestamp(at: SystemTime) -> String {
+    match local_time(at) {
+        Some(tm) => format!("{}-{:02}-{:02} {}", tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday, clock(at)),
+        None => "????-??-?? ??:??:??".to_string(),
+    }
+}
+
+fn find_line(prompt: &FindPrompt, theme: &Theme) -> Line<'static> {
+    Line::from(vec![
+        Span::styled("find: ", theme.prompt),
+        Span::styled(prompt.input.clone(), theme.text),
+        Span::styled(" ", Style::default().add_modifier(Modifier::REVERSED)),
+    ])
+}
+
+fn prompt_line(prompt: &FilterPrompt, theme: &Theme) -> Line<'static> {
+    let normal = theme.text;
+    let bad = theme.critical;
+    let mut spans = vec![Span::styled("/", theme.prompt)];
+    match &prompt.error {
+        Some(err) => {
+            let start = err.start.min(prompt.input.len());
+            let end = err.end.clamp(start, prompt.input.len());
+            spans.push(Span::styled(prompt.input[..start].to_string(), normal));
+            spans.push(Span::styled(prompt.input[start..end].to_string(), bad));
+            spans.push(Span::styled(prompt.input[end..].to_string(), normal));
+            // errors at the end of the input have nothing to underline, so mark the cursor
+            let cursor = if start == prompt.input.len() { bad } else { normal.add_modifier(Modifier::REVERSED) };
+            spans.push(Span::styled(" ", cursor));
+            spans.push(Span::styled(format!("  {}", err.msg), theme.chart_alert));
+        }
+        None => {
+            spans.push(Span::styled(prompt.input.clone(), normal));
+            spans.push(Span::styled(" ", normal.add_modifier(Modifier::REVERSED)));
+        }
     }
+    Line::from(spans)
 }
@@ -0,0 +1,397 @@
+use crate::fields::{self, InfoField, INFO_FIELDS};
+use crate::table::{friendly_transfer_str, SocketHistory};
+use crate::tcpdiag::{DiagWithInode, TCPInfo, TCP_STATE};
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+
+pub const DEFAULT_COLUMNS: [&str; 6] = ["src", "dst", "state", "send", "recv", "loss"];
+
+// What a fleet shows unless the config says otherwise, each end of a connection
+// next to the other, see fleet.rs.
+pub const FLEET_COLUMNS: [&str; 12] = [
+    "host",
+    "src",
+    "dst",
+    "state",
+    "rtt_ms",
+    "peer_rtt_ms",
+    "total_retrans",
+    "peer_total_retrans",
+    "send_q",
+    "peer_send_q",
+    "recv_q",
+    "peer_recv_q",
+];
+
+// Everything a column might need to render one row of the overview table.
+pub struct RowContext<'a> {
+    pub sock: &'a DiagWithInode,
+    pub info: &'a TCPInfo,
+    pub history: &'a SocketHistory,
+    pub src_name: String,
+    pub dst_name: String,
+    // the agent a fleet got the socket from, empty otherwise
+    pub host: &'a str,
+    // the other end of the connection, when a fleet has it too
+    pub peer: Option<Peer<'a>>,
+    // what the sparklines are scaled against when every row shares a scale,
+    // otherwise each one is scaled to its own peak
+    pub peaks: Option<Peaks>,
+}
+
+#[derive(Clone, Copy)]
+pub struct Peer<'a> {
+    pub host: &'a str,
+    pub sock: &'a DiagWithInode,
+}
+
+impl Peer<'_> {
+    fn info(&self) -> Option<&TCPInfo> {
+        self.sock.info.as_ref()
+    }
+}
+
+// A number about the other end, or a blank that sorts first when there isn't one.
+fn peer_key(c: &RowContext, value: fn(&Peer) -> Option<f64>) -> SortKey {
+    SortKey::Num(c.peer.as_ref().and_then(value).unwrap_or(-1.0))
+}
+
+// How many samples a sparkline shows, newest on the right.
+pub const SPARK_WIDTH: usize = 16;
+const SPARKS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Peaks {
+    pub send: u64,
+    pub recv: u64,
+    pub rtt: u64,
+}
+
+impl Peaks {
+    pub fn of<'a>(histories: impl Iterator<Item = &'a SocketHistory>) -> Peaks {
+        let peak = |ring: &VecDeque<u64>| ring.iter().take(SPARK_WIDTH).copied().max().unwrap_or(0);
+        histories.fold(Peaks::default(), |peaks, history| Peaks {
+            send: peaks.send.max(peak(&history.send_bps)),
+            recv: peaks.recv.max(peak(&history.recv_bps)),
+            rtt: peaks.rtt.max(peak(&history.rtt)),
+        })
+    }
+}
+
+// The newest SPARK_WIDTH samples as block characters, padded on the left until
+// there are enough of them. Anything above zero gets at least the lowest block.
+pub fn sparkline(ring: &VecDeque<u64>, peak: Option<u64>) -> String {
+    let samples: Vec<u64> = ring.iter().take(SPARK_WIDTH).rev().copied().collect();
+    let peak = peak.unwrap_or_else(|| samples.iter().copied().max().unwrap_or(0));
+    let mut line = " ".repeat(SPARK_WIDTH - samples.len());
+    for sample in samples {
+        let level = match (sample, peak) {
+            (0, _) | (_, 0) => 0,
+            _ => ((sample.min(peak) as f64 / peak as f64 * 8.0).round() as usize).max(1),
+        };
+        line.push(SPARKS[level]);
+    }
+    line
+}
+
+fn mean(ring: &VecDeque<u64>) -> f64 {
+    let samples = ring.iter().take(SPARK_WIDTH);
+    let count = samples.len().max(1);
+    samples.sum::<u64>() as f64 / count as f64
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SortKey {
+    Num(f64),
+    Text(String),
+}
+
+impl SortKey {
+    pub fn compare(&self, other: &SortKey) -> Ordering {
+        match (self, other) {
+            (SortKey::Num(a), SortKey::Num(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+            (SortKey::Text(a), SortKey::Text(b)) => a.cmp(b),
+            (SortKey::Num(_), SortKey::Text(_)) => Ordering::Less,
+            (SortKey::Text(_), SortKey::Num(_)) => Ordering::Greater,
+        }
+    }
+}
+
+enum Kind {
+    Info(&'static InfoField),
+    Derived {
+        format: fn(&RowContext) -> String,
+        sort: fn(&RowContext) -> SortKey,
+    },
+}
+
+pub struct Column {
+    pub id: &'static str,
+    pub title: &'static str,
+    kind: Kind,
+    // for exports, when `format` makes the number friendlier to read than to use
+    raw: Option<fn(&RowContext) -> String>,
+}
+
+impl Column {
+    pub fn format(&self, ctx: &RowContext) -> String {
+        match &self.kind {
+            Kind::Info(field) => field.format(ctx.info),
+            Kind::Derived { format, .. } => format(ctx),
+        }
+    }
+
+    // The value as plain as it comes: tcp_info fields in the kernel's units, rates
+    // in bytes per second and so on.
+    pub fn raw(&self, ctx: &RowContext) -> String {
+        match (&self.kind, self.raw) {
+            (Kind::Info(field), _) => field.value(ctx.info).to_string(),
+            (_, Some(raw)) => raw(ctx),
+            _ => self.format(ctx),
+        }
+    }
+
+    fn with_raw(mut self, raw: fn(&RowContext) -> String) -> Column {
+        self.raw = Some(raw);
+        self
+    }
+
+    pub fn sort_key(&self, ctx: &RowContext) -> SortKey {
+        match &self.kind {
+            Kind::Info(field) => SortKey::Num(field.value(ctx.info) as f64),
+            Kind::Derived { sort, .. } => sort(ctx),
+        }
+    }
+}
+
+fn derived(
+    id: &'static str,
+    title: &'static str,
+    format: fn(&RowContext) -> String,
+    sort: fn(&RowContext) -> SortKey,
+) -> Column {
+    Column { id, title, kind: Kind::Derived { format, sort }, raw: None }
+}
+
+// Bandwidth delay product, how many bytes need to be in flight to fill the pipe.
+fn bdp(info: &TCPInfo) -> u64 {
+    let rtt = match info.tcpi_min_rtt {
+        0 => info.tcpi_rtt,
+        rtt => rtt,
+    };
+    (info.tcpi_delivery_rate as f64 * rtt as f64 / 1_000_000.0) as u64
+}
+
+// All columns the overview can show: a handful of derived ones first, then one
+// for every `TCPInfo` field.
+pub fn registry() -> Vec<Column> {
+    let mut columns = vec![
+        derived(
+            "src",
+            "Source",
+            |c| format!("{}:{}", c.src_name, c.sock.src.port()),
+            |c| SortKey::Text(format!("{}:{:05}", c.src_name, c.sock.src.port())),
+        ),
+        derived(
+            "dst",
+            "Dest",
+            |c| format!("{}:{}", c.dst_name, c.sock.dst.port()),
+            |c| SortKey::Text(format!("{}:{:05}", c.dst_name, c.sock.dst.port())),
+        ),
+        derived(
+            "state",
+            "State",
+            |c| TCP_STATE::from_u8(c.info.tcpi_state).to_string(),
+            |c| SortKey::Num(c.info.tcpi_state as f64),
+        ),
+        derived(
+            "send",
+            "Send",
+            |c| friendly_transfer_str(c.history.send_bps[0]),
+            |c| SortKey::Num(c.history.send_bps[0] as f64),
+        )
+        .with_raw(|c| c.history.send_bps[0].to_string()),
+        derived(
+            "recv",
+            "Recv",
+            |c| friendly_transfer_str(c.history.recv_bps[0]),
+            |c| SortKey::Num(c.history.recv_bps[0] as f64),
+        )
+        .with_raw(|c| c.history.recv_bps[0].to_string()),
+        derived(
+            "send_spark",
+            "Send trend",
+            |c| sparkline(&c.history.send_bps, c.peaks.map(|peaks| peaks.send)),
+            |c| SortKey::Num(mean(&c.history.send_bps)),
+        ),
+        derived(
+            "recv_spark",
+            "Recv trend",
+            |c| sparkline(&c.history.recv_bps, c.peaks.map(|peaks| peaks.recv)),
+            |c| SortKey::Num(mean(&c.history.recv_bps)),
+        ),
+        derived(
+            "loss",
+            "Loss",
+            |c| format!("{}%", c.history.packet_loss[0]),
+            |c| SortKey::Num(c.history.packet_loss[0] as f64),
+        )
+        .with_raw(|c| c.history.packet_loss[0].to_string()),
+        derived(
+            "rtt_ms",
+            "RTT ms",
+            |c| format!("{:.2}", c.info.tcpi_rtt as f64 / 1000.0),
+            |c| SortKey::Num(c.info.tcpi_rtt as f64),
+        ),
+        derived(
+            "rtt_spark",
+            "RTT trend",
+            |c| sparkline(&c.history.rtt, c.peaks.map(|peaks| peaks.rtt)),
+            |c| SortKey::Num(mean(&c.history.rtt)),
+        ),
+        derived(
+            "rttvar_ms",
+            "RTTvar ms",
+            |c| format!("{:.2}", c.info.tcpi_rttvar as f64 / 1000.0),
+            |c| SortKey::Num(c.info.tcpi_rttvar as f64),
+        ),
+        derived(
+            "min_rtt_ms",
+            "MinRTT ms",
+            |c| format!("{:.2}", c.info.tcpi_min_rtt as f64 / 1000.0),
+            |c| SortKey::Num(c.info.tcpi_min_rtt as f64),
+        ),
+        derived(
+            "bdp",
+            "BDP",
+            |c| fields::human_bytes(bdp(c.info)),
+            |c| SortKey::Num(bdp(c.info) as f64),
+        )
+        .with_raw(|c| bdp(c.info).to_string()),
+        derived(
+            "pid",
+            "PID",
+            |c| c.sock.process.as_ref().map(|p| p.pid.to_string()).unwrap_or_default(),
+            |c| SortKey::Num(c.sock.process.as_ref().map(|p| p.pid as f64).unwrap_or(-1.0)),
+        ),
+        derived(
+            "comm",
+            "Process",
+            |c| c.sock.process.as_ref().map(|p| p.comm.clone()).unwrap_or_default(),
+            |c| SortKey::Text(c.sock.process.as_ref().map(|p| p.comm.clone()).unwrap_or_default()),
+        ),
+        derived(
+            "cc",
+            "CC",
+            |c| c.sock.cc.clone().unwrap_or_default(),
+            |c| SortKey::Text(c.sock.cc.clone().unwrap_or_default()),
+        ),
+        derived(
+            "inode",
+            "Inode",
+            |c| c.sock.inode.to_string(),
+            |c| SortKey::Num(c.sock.inode as f64),
+        ),
+        derived(
+            "send_q",
+            "Send-Q",
+            |c| fields::human_bytes(c.sock.wqueue as u64),
+            |c| SortKey::Num(c.sock.wqueue as f64),
+        )
+        .with_raw(|c| c.sock.wqueue.to_string()),
+        derived(
+            "recv_q",
+            "Recv-Q",
+            |c| fields::human_bytes(c.sock.rqueue as u64),
+            |c| SortKey::Num(c.sock.rqueue as f64),
+        )
+        .with_raw(|c| c.sock.rqueue.to_string()),
+        derived("host", "Host", |c| c.host.to_string(), |c| SortKey::Text(c.host.to_string())),
+        derived(
+            "peer_host",
+            "Peer host",
+            |c| c.peer.map(|peer| peer.host.to_string()).unwrap_or_default(),
+            |c| SortKey::Text(c.peer.map(|peer| peer.host.to_string()).unwrap_or_default()),
+        ),
+        derived(
+            "peer_rtt_ms",
+            "Peer RTT ms",
+            |c| {
+                let rtt = c.peer.as_ref().and_then(Peer::info).map(|info| info.tcpi_rtt as f64 / 1000.0);
+                rtt.map(|rtt| format!("{:.2}", rtt)).unwrap_or_default()
+            },
+            |c| peer_key(c, |peer| peer.info().map(|info| info.tcpi_rtt as f64)),
+        ),
+        derived(
+            "peer_total_retrans",
+            "Peer retransmits",
+            |c| c.peer.as_ref().and_then(Peer::info).map(|info| info.tcpi_total_retrans.to_string()).unwrap_or_default(),
+            |c| peer_key(c, |peer| peer.info().map(|info| info.tcpi_total_retrans as f64)),
+        ),
+        derived(
+            "peer_send_q",
+            "Peer Send-Q",
+            |c| c.peer.map(|peer| fields::human_bytes(peer.sock.wqueue as u64)).unwrap_or_default(),
+            |c| peer_key(c, |peer| Some(peer.sock.wqueue as f64)),
+        )
+        .with_raw(|c| c.peer.map(|peer| peer.sock.wqueue.to_string()).unwrap_or_default()),
+        derived(
+            "peer_recv_q",
+            "Peer Recv-Q",
+            |c| c.peer.map(|peer| fields::human_bytes(peer.sock.rqueue as u64)).unwrap_or_default(),
+            |c| peer_key(c, |peer| Some(peer.sock.rqueue as f64)),
+        )
+        .with_raw(|c| c.peer.map(|peer| peer.sock.rqueue.to_string()).unwrap_or_default()),
+    ];
+    columns.extend(INFO_FIELDS.iter().map(|field| Column {
+        id: field.name,
+        title: field.label,
+        kind: Kind::Info(field),
+        raw: None,
+    }));
+    columns
+}
+
+pub fn position(registry: &[Column], id: &str) -> Option<usize> {
+    registry.iter().position(|column| column.id == id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_registry_ids_are_unique() {
+        let registry = registry();
+        let ids: HashSet<&str> = registry.iter().map(|c| c.id).collect();
+        assert_eq!(ids.len(), registry.len());
+        for id in DEFAULT_COLUMNS.iter().chain(FLEET_COLUMNS.iter()) {
+            assert!(position(&registry, id).is_some());
+        }
+    }
+
+    #[test]
+    fn test_bdp() {
+        let info = TCPInfo { tcpi_delivery_rate: 1_250_000, tcpi_min_rtt: 20_000, ..Default::default() };
+        assert_eq!(bdp(&info), 25_000);
+        let info = TCPInfo { tcpi_delivery_rate: 1_000, tcpi_rtt: 500_000, ..Default::default() };
+        assert_eq!(bdp(&info), 500);
+    }
+
+    #[test]
+    fn test_sparkline() {
+        let ring: VecDeque<u64> = vec![8, 4, 0, 1].into_iter().collect();
+        assert_eq!(sparkline(&ring, None), format!("{}▁ ▄█", " ".repeat(SPARK_WIDTH - 4)));
+        // against a shared peak the same samples sit lower
+        assert_eq!(sparkline(&ring, Some(16)), format!("{}▁ ▂▄", " ".repeat(SPARK_WIDTH - 4)));
+        let idle: VecDeque<u64> = vec![0; 40].into_iter().collect();
+        assert_eq!(sparkline(&idle, None), " ".repeat(SPARK_WIDTH));
+    }
+
+    #[test]
+    fn test_sort_key_compare() {
+        assert_eq!(SortKey::Num(1.0).compare(&SortKey::Num(2.0)), Ordering::Less);
+        assert_eq!(SortKey::Text("b".into()).compare(&SortKey::Text("a".into())), Ordering::Greater);
+    }
+}
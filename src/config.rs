@@ -0,0 +1,547 @@
+use crate::columns::{DEFAULT_COLUMNS, FLEET_COLUMNS};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const SYSTEM_CONFIG: &str = "/etc/tcptop.toml";
+
+// Settings read from /etc/tcptop.toml and ~/.config/tcptop/config.toml, and the
+// shape TCPTOP_* variables and command line flags are turned into so they can
+// all be layered the same way. Everything is optional, a missing file is the
+// same as an empty one.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval: Option<String>,
+    // how far back history goes, for the charts and stepping while paused
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retention: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    // a column id, with ":desc" on the end to sort the other way
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub columns: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dns: Option<bool>,
+    // a name from `ip netns` or a path like /proc/<pid>/ns/net
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+    // tui, or text, json, ndjson or csv to print to stdout instead, see batch.rs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    // how many snapshots to print when not in the tui, 0 for no limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iterations: Option<u64>,
+    // one of keys::PRESETS, with `keys` rebinding actions on top of it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keymap: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keys: Option<BTreeMap<String, KeyList>>,
+    // a shipped theme or one of `themes`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub themes: Option<BTreeMap<String, ThemeConfig>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight: Option<HighlightConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<MetricsConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prometheus: Option<PrometheusConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub influx: Option<InfluxConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub statsd: Option<StatsdConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub otlp: Option<OtlpConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub daemon: Option<DaemonConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agent: Option<AgentConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fleet: Option<FleetConfig>,
+    // a daemon's control socket to show instead of collecting locally
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attach: Option<String>,
+    // a file to write every sample to, see recording.rs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record: Option<String>,
+    // a file written that way to play back instead of collecting
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replay: Option<String>,
+}
+
+// A binding can be a single key or a list of them.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum KeyList {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ThemeConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    // style slots, checked by name when the theme is built
+    #[serde(flatten)]
+    pub slots: BTreeMap<String, StyleConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct StyleConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fg: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bg: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modifiers: Option<Vec<String>>,
+}
+
+// When rows get singled out in the overview, see highlight::Thresholds for the
+// defaults. A threshold of 0 turns that check off.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HighlightConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_ticks: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rtt_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retrans_percent: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zero_window: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backoff: Option<u8>,
+}
+
+// How sockets are grouped and cut down for the exporters, see metrics.rs.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<Vec<String>>,
+    // per kind of series, 0 for no limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_series: Option<usize>,
+    // which series to keep when there are too many: rate, rtt or retransmits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rank: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PrometheusConfig {
+    // where to serve /metrics, setting it runs tcptop as an exporter
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub listen: Option<String>,
+}
+
+// Pushing InfluxDB line protocol somewhere, see push.rs.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct InfluxConfig {
+    // udp://HOST:PORT, tcp://HOST:PORT or file://PATH, setting it turns the push on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    // measurements are called <prefix>_socket and so on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    // added to every line, like host = "web1"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flush: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatsdConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    // metrics are called <prefix>.socket.<field> and so on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    // only DogStatsD has tags
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flush: Option<String>,
+    // labels as tags rather than in the metric name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dogstatsd: Option<bool>,
+}
+
+// Sending to an OpenTelemetry collector, see otlp.rs.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OtlpConfig {
+    // http://HOST:PORT, setting it turns the export on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    // grpc or http/protobuf
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+    // sent with every export, e.g. for auth
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headers: Option<BTreeMap<String, String>>,
+    // extra resource attributes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flush: Option<String>,
+}
+
+// Collecting for TUIs to attach to, see daemon.rs.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DaemonConfig {
+    // the unix socket to listen on, setting it runs tcptop as a daemon
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub listen: Option<String>,
+}
+
+// Serving fleets over TCP, see daemon.rs.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentConfig {
+    // HOST:PORT, setting it runs tcptop as an agent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub listen: Option<String>,
+    // what a fleet has to send before it gets anything
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+// Showing several agents' sockets in one table, see fleet.rs.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FleetConfig {
+    // [NAME=]HOST:PORT for each agent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agents: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    // what a fleet shows in place of the top level `columns`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub columns: Option<Vec<String>>,
+}
+
+fn replace<T>(ours: &mut Option<T>, theirs: Option<T>) {
+    if theirs.is_some() {
+        *ours = theirs;
+    }
+}
+
+impl Config {
+    // What you get with no config at all, spelled out so --print-config can
+    // show every setting.
+    pub fn defaults() -> Config {
+        Config {
+            interval: Some("1s".to_string()),
+            retention: Some("30s".to_string()),
+            filter: Some(String::new()),
+            columns: Some(DEFAULT_COLUMNS.iter().map(|id| id.to_string()).collect()),
+            dns: Some(true),
+            protocol: Some("all".to_string()),
+            output: Some("tui".to_string()),
+            iterations: Some(0),
+            keymap: Some("default".to_string()),
+            theme: Some("dark".to_string()),
+            fleet: Some(FleetConfig {
+                columns: Some(FLEET_COLUMNS.iter().map(|id| id.to_string()).collect()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    // Lays `other` on top: anything it sets wins, and key bindings and themes
+    // are merged one entry at a time rather than replaced wholesale.
+    pub fn merge(&mut self, other: Config) {
+        replace(&mut self.interval, other.interval);
+        replace(&mut self.retention, other.retention);
+        replace(&mut self.filter, other.filter);
+        replace(&mut self.sort, other.sort);
+        replace(&mut self.columns, other.columns);
+        replace(&mut self.dns, other.dns);
+        replace(&mut self.namespace, other.namespace);
+        replace(&mut self.protocol, other.protocol);
+        replace(&mut self.output, other.output);
+        replace(&mut self.iterations, other.iterations);
+        replace(&mut self.keymap, other.keymap);
+        replace(&mut self.theme, other.theme);
+        replace(&mut self.attach, other.attach);
+        replace(&mut self.record, other.record);
+        replace(&mut self.replay, other.replay);
+        if let Some(keys) = other.keys {
+            self.keys.get_or_insert_with(BTreeMap::new).extend(keys);
+        }
+        if let Some(themes) = other.themes {
+            self.themes.get_or_insert_with(BTreeMap::new).extend(themes);
+        }
+        if let Some(highlight) = other.highlight {
+            let ours = self.highlight.get_or_insert_with(HighlightConfig::default);
+            replace(&mut ours.new_ticks, highlight.new_ticks);
+            replace(&mut ours.rtt_ms, highlight.rtt_ms);
+            replace(&mut ours.retrans_percent, highlight.retrans_percent);
+            replace(&mut ours.zero_window, highlight.zero_window);
+            replace(&mut ours.backoff, highlight.backoff);
+        }
+        if let Some(metrics) = other.metrics {
+            let ours = self.metrics.get_or_insert_with(MetricsConfig::default);
+            replace(&mut ours.labels, metrics.labels);
+            replace(&mut ours.max_series, metrics.max_series);
+            replace(&mut ours.rank, metrics.rank);
+        }
+        if let Some(prometheus) = other.prometheus {
+            let ours = self.prometheus.get_or_insert_with(PrometheusConfig::default);
+            replace(&mut ours.listen, prometheus.listen);
+        }
+        if let Some(influx) = other.influx {
+            let ours = self.influx.get_or_insert_with(InfluxConfig::default);
+            replace(&mut ours.target, influx.target);
+            replace(&mut ours.prefix, influx.prefix);
+            replace(&mut ours.flush, influx.flush);
+            if let Some(tags) = influx.tags {
+                ours.tags.get_or_insert_with(BTreeMap::new).extend(tags);
+            }
+        }
+        if let Some(statsd) = other.statsd {
+            let ours = self.statsd.get_or_insert_with(StatsdConfig::default);
+            replace(&mut ours.target, statsd.target);
+            replace(&mut ours.prefix, statsd.prefix);
+            replace(&mut ours.flush, statsd.flush);
+            replace(&mut ours.dogstatsd, statsd.dogstatsd);
+            if let Some(tags) = statsd.tags {
+                ours.tags.get_or_insert_with(BTreeMap::new).extend(tags);
+            }
+        }
+        if let Some(otlp) = other.otlp {
+            let ours = self.otlp.get_or_insert_with(OtlpConfig::default);
+            replace(&mut ours.endpoint, otlp.endpoint);
+            replace(&mut ours.protocol, otlp.protocol);
+            replace(&mut ours.flush, otlp.flush);
+            if let Some(headers) = otlp.headers {
+                ours.headers.get_or_insert_with(BTreeMap::new).extend(headers);
+            }
+            if let Some(resource) = otlp.resource {
+                ours.resource.get_or_insert_with(BTreeMap::new).extend(resource);
+            }
+        }
+        if let Some(daemon) = other.daemon {
+            let ours = self.daemon.get_or_insert_with(DaemonConfig::default);
+            replace(&mut ours.listen, daemon.listen);
+        }
+        if let Some(agent) = other.agent {
+            let ours = self.agent.get_or_insert_with(AgentConfig::default);
+            replace(&mut ours.listen, agent.listen);
+            replace(&mut ours.token, agent.token);
+        }
+        if let Some(fleet) = other.fleet {
+            let ours = self.fleet.get_or_insert_with(FleetConfig::default);
+            replace(&mut ours.agents, fleet.agents);
+            replace(&mut ours.token, fleet.token);
+            replace(&mut ours.columns, fleet.columns);
+        }
+    }
+
+    // TCPTOP_<SETTING> for each of the plain settings, plus TCPTOP_PROMETHEUS_LISTEN,
+    // TCPTOP_INFLUX_TARGET, TCPTOP_STATSD_TARGET, TCPTOP_OTLP_ENDPOINT,
+    // TCPTOP_DAEMON_LISTEN, TCPTOP_AGENT_LISTEN, TCPTOP_AGENT_TOKEN, TCPTOP_FLEET
+    // and TCPTOP_FLEET_TOKEN. Columns and fleet agents are comma separated.
+    pub fn from_env<F: Fn(&str) -> Option<String>>(var: F) -> Result<Config, String> {
+        let dns = match var("TCPTOP_DNS").as_deref() {
+            None => None,
+            Some("1") | Some("true") | Some("yes") | Some("on") => Some(true),
+            Some("0") | Some("false") | Some("no") | Some("off") => Some(false),
+            Some(other) => return Err(format!("TCPTOP_DNS should be true or false, not '{}'", other)),
+        };
+        let iterations = match var("TCPTOP_ITERATIONS") {
+            None => None,
+            Some(n) => Some(n.parse().map_err(|_| format!("TCPTOP_ITERATIONS should be a number, not '{}'", n))?),
+        };
+        let list = |text: String| text.split(',').map(|item| item.trim().to_string()).collect::<Vec<_>>();
+        let agent = match (var("TCPTOP_AGENT_LISTEN"), var("TCPTOP_AGENT_TOKEN")) {
+            (None, None) => None,
+            (listen, token) => Some(AgentConfig { listen, token }),
+        };
+        let fleet = match (var("TCPTOP_FLEET").map(list), var("TCPTOP_FLEET_TOKEN")) {
+            (None, None) => None,
+            (agents, token) => Some(FleetConfig { agents, token, ..Default::default() }),
+        };
+        Ok(Config {
+            interval: var("TCPTOP_INTERVAL"),
+            retention: var("TCPTOP_RETENTION"),
+            filter: var("TCPTOP_FILTER"),
+            sort: var("TCPTOP_SORT"),
+            columns: var("TCPTOP_COLUMNS").map(list),
+            dns,
+            namespace: var("TCPTOP_NAMESPACE"),
+            protocol: var("TCPTOP_PROTOCOL"),
+            output: var("TCPTOP_OUTPUT"),
+            iterations,
+            keymap: var("TCPTOP_KEYMAP"),
+            theme: var("TCPTOP_THEME"),
+            prometheus: var("TCPTOP_PROMETHEUS_LISTEN").map(|listen| PrometheusConfig { listen: Some(listen) }),
+            influx: var("TCPTOP_INFLUX_TARGET").map(|target| InfluxConfig { target: Some(target), ..Default::default() }),
+            statsd: var("TCPTOP_STATSD_TARGET").map(|target| StatsdConfig { target: Some(target), ..Default::default() }),
+            otlp: var("TCPTOP_OTLP_ENDPOINT").map(|endpoint| OtlpConfig { endpoint: Some(endpoint), ..Default::default() }),
+            daemon: var("TCPTOP_DAEMON_LISTEN").map(|listen| DaemonConfig { listen: Some(listen) }),
+            attach: var("TCPTOP_ATTACH"),
+            record: var("TCPTOP_RECORD"),
+            replay: var("TCPTOP_REPLAY"),
+            agent,
+            fleet,
+            ..Default::default()
+        })
+    }
+
+    // The config as a TOML table with the tokens and OTLP header values swapped
+    // for <redacted>, for --print-config.
+    pub fn redacted(&self) -> Result<toml::Table, Box<dyn Error>> {
+        let mut table = toml::Table::try_from(self)?;
+        let redacted = || toml::Value::String("<redacted>".to_string());
+        for section in &["agent", "fleet"] {
+            if let Some(token) = table.get_mut(*section).and_then(|section| section.get_mut("token")) {
+                *token = redacted();
+            }
+        }
+        if let Some(toml::Value::Table(headers)) = table.get_mut("otlp").and_then(|otlp| otlp.get_mut("headers")) {
+            for (_, value) in headers.iter_mut() {
+                *value = redacted();
+            }
+        }
+        Ok(table)
+    }
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("tcptop").join("config.toml"))
+}
+
+fn read(path: &Path) -> Result<Config, Box<dyn Error>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => toml::from_str(&contents)
+            .map_err(|err| format!("{}: {}", path.display(), err).into()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(err) => Err(format!("{}: {}", path.display(), err).into()),
+    }
+}
+
+// Defaults, then the system file, the user's file (or `user` instead when given)
+// and the environment. Flags go on top of this in main.
+pub fn load(user: Option<&Path>) -> Result<Config, Box<dyn Error>> {
+    let mut config = Config::defaults();
+    config.merge(read(Path::new(SYSTEM_CONFIG))?);
+    if let Some(path) = user.map(Path::to_path_buf).or_else(config_path) {
+        config.merge(read(&path)?);
+    }
+    config.merge(Config::from_env(|name| env::var(name).ok())?);
+    Ok(config)
+}
+
+// Writes the column layout back to the config file. Only that one key is
+// edited, so the other keys and any comments and formatting stay as they were.
+// `section` is the table the columns go under, e.g. "fleet", or None for the
+// top level.
+pub fn save_columns(path: &Path, section: Option<&str>, columns: &[&str]) -> Result<(), Box<dyn Error>> {
+    let mut document = match fs::read_to_string(path) {
+        Ok(contents) => contents.parse::<toml_edit::DocumentMut>()?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => toml_edit::DocumentMut::new(),
+        Err(err) => return Err(err.into()),
+    };
+    let table = match section {
+        Some(name) => document
+            .entry(name)
+            .or_insert_with(toml_edit::table)
+            .as_table_like_mut()
+            .ok_or_else(|| format!("`{}` in the config file isn't a table", name))?,
+        None => document.as_table_mut(),
+    };
+    let columns: toml_edit::Array = columns.iter().copied().collect();
+    table.insert("columns", toml_edit::value(columns));
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, document.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layering() {
+        let mut config = Config::defaults();
+        config.merge(toml::from_str("interval = \"2s\"\ntheme = \"light\"\n[keys]\nquit = \"x\"").unwrap());
+        config.merge(toml::from_str("interval = \"5s\"\n[keys]\nhelp = \"h\"\n[agent]\nlisten = \":9900\"").unwrap());
+        let env = Config::from_env(|name| match name {
+            "TCPTOP_THEME" => Some("high-contrast".to_string()),
+            "TCPTOP_COLUMNS" => Some("src, dst".to_string()),
+            "TCPTOP_DNS" => Some("off".to_string()),
+            "TCPTOP_ITERATIONS" => Some("3".to_string()),
+            "TCPTOP_AGENT_TOKEN" => Some("secret".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        config.merge(env);
+
+        assert_eq!(config.interval.as_deref(), Some("5s"));
+        assert_eq!(config.theme.as_deref(), Some("high-contrast"));
+        assert_eq!(config.columns, Some(vec!["src".to_string(), "dst".to_string()]));
+        assert_eq!(config.dns, Some(false));
+        assert_eq!(config.iterations, Some(3));
+        assert_eq!(config.retention.as_deref(), Some("30s"));
+        assert_eq!(config.keys.as_ref().map(|keys| keys.len()), Some(2));
+        let agent = config.agent.as_ref().unwrap();
+        assert_eq!((agent.listen.as_deref(), agent.token.as_deref()), (Some(":9900"), Some("secret")));
+        assert!(Config::from_env(|name| (name == "TCPTOP_DNS").then(|| "maybe".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_save_columns() {
+        let path = std::env::temp_dir().join(format!("tcptop-test-{}-columns.toml", std::process::id()));
+        fs::write(&path, "# refresh slowly\ninterval = \"2s\"\n\n[fleet]\nagents = [\"web1:9900\"] # the web tier\n").unwrap();
+        save_columns(&path, Some("fleet"), &["host", "src"]).unwrap();
+        save_columns(&path, None, &["src", "dst"]).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        let config: Config = toml::from_str(&contents).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(contents.starts_with("# refresh slowly\n"));
+        assert!(contents.contains("# the web tier"));
+
+        assert_eq!(config.interval.as_deref(), Some("2s"));
+        assert_eq!(config.columns, Some(vec!["src".to_string(), "dst".to_string()]));
+        let fleet = config.fleet.unwrap();
+        assert_eq!(fleet.agents, Some(vec!["web1:9900".to_string()]));
+        assert_eq!(fleet.columns, Some(vec!["host".to_string(), "src".to_string()]));
+    }
+
+    #[test]
+    fn test_redacted() {
+        let mut config = Config::defaults();
+        config.merge(toml::from_str("[agent]\ntoken = \"a\"\n[fleet]\ntoken = \"b\"\n[otlp]\nheaders = { authorization = \"c\" }").unwrap());
+        let printed = toml::to_string(&config.redacted().unwrap()).unwrap();
+
+        assert!(!printed.contains("\"a\"") && !printed.contains("\"b\"") && !printed.contains("\"c\""));
+        assert_eq!(printed.matches("<redacted>").count(), 3);
+        assert!(printed.contains("authorization"));
+    }
+}
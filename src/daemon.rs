@@ -0,0 +1,578 @@
+use crate::json::{self, Snapshot, SCHEMA_VERSION};
+use crate::table::{self, ClosedSocket, Options, SocketHistory, StatefulTable};
+use crate::tcpdiag::{DiagWithInode, TCPInfo};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// One collector per host that keeps hours of history, with TUIs attaching to it
+// when someone wants a look. It talks newline delimited JSON over a unix
+// socket: a client writes a request per line and gets a reply per line, except
+// for "subscribe" and "follow" which keep going until the client hangs up.
+//
+//   {"request":"hello"}      {"type":"hello", ...Hello}
+//   {"request":"snapshot"}   {"type":"snapshot", ...} the same as --output json
+//   {"request":"history"}    {"type":"history", "sockets":[Entry, ...]}
+//   {"request":"subscribe"}  {"type":"state", ...State}, then a
+//                            {"type":"sample", ...Sample} after every refresh
+//   {"request":"follow"}     {"type":"hello", ...Hello}, then the samples
+//                            without any history
+//
+// As an agent it speaks the same over TCP, for a fleet to connect to, but only
+// after {"request":"auth","token":"..."} has been answered with a hello.
+//
+// Anything else gets {"type":"error","message":"..."}. Times are milliseconds,
+// `time_ms` since the Unix epoch and `offset_ms` on the daemon's monotonic clock
+// since it started, which is what sample ages are measured against.
+
+// Bumped whenever a message changes in a way an older client would get wrong.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// How many samples a subscriber can fall behind by before it gets dropped.
+const BACKLOG: usize = 64;
+
+// How long an agent waits for a new connection to authenticate.
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The longest auth request an agent reads before giving up on a connection, and
+// how many connections it serves at once, authenticated or not.
+const MAX_AUTH_LINE: u64 = 4096;
+const MAX_AGENT_CONNECTIONS: usize = 64;
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "request", rename_all = "lowercase")]
+pub enum Request {
+    Auth { token: String },
+    Hello,
+    Snapshot,
+    History,
+    Subscribe,
+    Follow,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Hello {
+    pub version: u32,
+    pub interval_ms: u64,
+    pub retention_ms: u64,
+    pub namespace: Option<String>,
+}
+
+// Every socket from one refresh, listeners included, before any filter.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Sample {
+    pub time_ms: u64,
+    pub offset_ms: u64,
+    pub snapshot: Vec<DiagWithInode>,
+    pub listeners: Vec<DiagWithInode>,
+}
+
+// A socket's rings, newest first. `age_ms` says how long before the message's
+// `offset_ms` each sample was taken.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct History {
+    pub age_ms: Vec<u64>,
+    pub send_bps: VecDeque<u64>,
+    pub recv_bps: VecDeque<u64>,
+    pub send_bytes: VecDeque<u64>,
+    pub recv_bytes: VecDeque<u64>,
+    pub packet_loss: VecDeque<u32>,
+    pub congestion_window: VecDeque<u64>,
+    pub slow_start_threshold: VecDeque<u64>,
+    pub rtt: VecDeque<u64>,
+    pub rtt_variance: VecDeque<u64>,
+    pub min_rtt: VecDeque<u64>,
+    pub retransmits: VecDeque<u64>,
+    pub pacing_rate: VecDeque<u64>,
+    pub delivery_rate: VecDeque<u64>,
+    pub previous: TCPInfo,
+    pub current: TCPInfo,
+    pub fresh: bool,
+    pub samples: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Entry {
+    pub socket: DiagWithInode,
+    pub history: History,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Closed {
+    pub seq: u64,
+    pub time_ms: u64,
+    pub socket: DiagWithInode,
+    pub history: History,
+}
+
+// What an attaching TUI starts from: the latest sample, the history of every
+// socket in it, and the Closed tab.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct State {
+    #[serde(flatten)]
+    pub hello: Hello,
+    #[serde(flatten)]
+    pub sample: Sample,
+    pub history: Vec<Entry>,
+    pub closed: Vec<Closed>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Reply {
+    Hello(Hello),
+    State(State),
+    Sample(Sample),
+    History { sockets: Vec<Entry> },
+    Error { message: String },
+}
+
+// `json::Snapshot` only goes one way, so it gets its tag stuck on separately.
+#[derive(Serialize)]
+struct Tagged<T> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(flatten)]
+    body: T,
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis() as u64
+}
+
+fn unix_ms(at: SystemTime) -> u64 {
+    millis(at.duration_since(UNIX_EPOCH).unwrap_or_default())
+}
+
+impl History {
+    fn of(history: &SocketHistory, taken: Instant) -> History {
+        History {
+            age_ms: history.sampled_at.iter().map(|at| millis(taken.saturating_duration_since(*at))).collect(),
+            send_bps: history.send_bps.clone(),
+            recv_bps: history.recv_bps.clone(),
+            send_bytes: history.send_bytes.clone(),
+            recv_bytes: history.recv_bytes.clone(),
+            packet_loss: history.packet_loss.clone(),
+            congestion_window: history.congestion_window.clone(),
+            slow_start_threshold: history.slow_start_threshold.clone(),
+            rtt: history.rtt.clone(),
+            rtt_variance: history.rtt_variance.clone(),
+            min_rtt: history.min_rtt.clone(),
+            retransmits: history.retransmits.clone(),
+            pacing_rate: history.pacing_rate.clone(),
+            delivery_rate: history.delivery_rate.clone(),
+            previous: history.previous.clone(),
+            current: history.current.clone(),
+            fresh: history.fresh,
+            samples: history.samples,
+        }
+    }
+
+    // Back into rings, with the ages counted back from `taken` on our clock.
+    fn into_history(self, taken: Instant) -> SocketHistory {
+        SocketHistory {
+            sampled_at: self
+                .age_ms
+                .iter()
+                .map(|age| taken.checked_sub(Duration::from_millis(*age)).unwrap_or(taken))
+                .collect(),
+            send_bps: self.send_bps,
+            recv_bps: self.recv_bps,
+            send_bytes: self.send_bytes,
+            recv_bytes: self.recv_bytes,
+            packet_loss: self.packet_loss,
+            congestion_window: self.congestion_window,
+            slow_start_threshold: self.slow_start_threshold,
+            rtt: self.rtt,
+            rtt_variance: self.rtt_variance,
+            min_rtt: self.min_rtt,
+            retransmits: self.retransmits,
+            pacing_rate: self.pacing_rate,
+            delivery_rate: self.delivery_rate,
+            previous: self.previous,
+            current: self.current,
+            fresh: self.fresh,
+            samples: self.samples,
+        }
+    }
+}
+
+// What the daemon is and how it's set up, shared with the client threads.
+struct Shared {
+    table: Arc<Mutex<StatefulTable>>,
+    subscribers: Mutex<Vec<SyncSender<Arc<String>>>>,
+    started: Instant,
+    interval: Duration,
+    namespace: Option<String>,
+}
+
+impl Shared {
+    fn hello(&self, table: &StatefulTable) -> Hello {
+        Hello {
+            version: PROTOCOL_VERSION,
+            interval_ms: millis(self.interval),
+            retention_ms: millis(table.options.retention),
+            namespace: self.namespace.clone(),
+        }
+    }
+
+    fn sample(&self, table: &StatefulTable) -> Sample {
+        let (at, taken) = table.captured_at();
+        Sample {
+            time_ms: unix_ms(at),
+            offset_ms: millis(taken.saturating_duration_since(self.started)),
+            snapshot: table.snapshot.clone(),
+            listeners: table.listeners.clone(),
+        }
+    }
+
+    // The open sockets and their histories. Only the Arcs are cloned, which is
+    // quick enough to do with the table locked.
+    fn held(&self, table: &StatefulTable) -> Held {
+        let live = table
+            .snapshot
+            .iter()
+            .filter_map(|sock| Some((sock.clone(), table.history.get(&sock.id())?.clone())))
+            .collect();
+        Held { taken: table.captured_at().1, live, closed: table.closed.iter().cloned().collect() }
+    }
+}
+
+// What the history and state replies are made from, taken while the table is
+// locked. The histories are shared with the table, and only copied out into
+// replies once it's been let go, so collection isn't held up by a big one.
+struct Held {
+    taken: Instant,
+    live: Vec<(DiagWithInode, Arc<SocketHistory>)>,
+    closed: Vec<ClosedSocket>,
+}
+
+fn entries(taken: Instant, live: Vec<(DiagWithInode, Arc<SocketHistory>)>) -> Vec<Entry> {
+    live.into_iter().map(|(socket, history)| Entry { socket, history: History::of(&history, taken) }).collect()
+}
+
+impl Held {
+    fn state(self, hello: Hello, sample: Sample) -> State {
+        let taken = self.taken;
+        let closed = self
+            .closed
+            .into_iter()
+            .map(|closed| Closed {
+                seq: closed.seq,
+                time_ms: unix_ms(closed.at),
+                history: History::of(&closed.history, taken),
+                socket: closed.socket,
+            })
+            .collect();
+        State { hello, sample, history: entries(taken, self.live), closed }
+    }
+}
+
+pub fn write_line<W: Write, T: Serialize>(out: &mut W, message: &T) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, message)?;
+    out.write_all(b"\n")?;
+    out.flush()
+}
+
+// Compares without stopping at the first difference, so how long it takes
+// doesn't give away how much of a guess was right.
+fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len() && given.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+// Answers one client's requests. With a `token` nothing but auth is answered
+// until it's been given, and a wrong one ends the connection.
+fn serve<R: Read, W: Write>(input: R, mut out: W, shared: &Shared, mut token: Option<&str>) -> io::Result<()> {
+    for line in BufReader::new(input).lines() {
+        let request = match serde_json::from_str::<Request>(&line?) {
+            Ok(request) => request,
+            Err(err) => {
+                write_line(&mut out, &Reply::Error { message: format!("bad request: {}", err) })?;
+                continue;
+            }
+        };
+        let request = match (token, request) {
+            (None, Request::Auth { .. }) => Request::Hello,
+            (None, request) => request,
+            (Some(expected), Request::Auth { token: given }) if same_token(&given, expected) => {
+                token = None;
+                Request::Hello
+            }
+            (Some(_), Request::Auth { .. }) => {
+                return write_line(&mut out, &Reply::Error { message: "wrong token".to_string() });
+            }
+            (Some(_), _) => {
+                return write_line(&mut out, &Reply::Error { message: "authenticate first".to_string() });
+            }
+        };
+        let table = shared.table.lock().unwrap();
+        match request {
+            Request::Auth { .. } | Request::Hello => write_line(&mut out, &Reply::Hello(shared.hello(&table)))?,
+            Request::Snapshot => {
+                let sample = json::Sample::of(&table, shared.started, shared.namespace.as_deref());
+                let snapshot = Snapshot { version: SCHEMA_VERSION, sample, sockets: json::sockets(&table) };
+                write_line(&mut out, &Tagged { kind: "snapshot", body: snapshot })?;
+            }
+            Request::History => {
+                let held = shared.held(&table);
+                drop(table);
+                write_line(&mut out, &Reply::History { sockets: entries(held.taken, held.live) })?;
+            }
+            Request::Subscribe | Request::Follow => {
+                // signed up while the table is locked, so no sample is missed or
+                // sent twice between the first reply and the first sample
+                let (sender, samples) = mpsc::sync_channel(BACKLOG);
+                shared.subscribers.lock().unwrap().push(sender);
+                let hello = shared.hello(&table);
+                let state = match request {
+                    Request::Subscribe => Some((shared.sample(&table), shared.held(&table))),
+                    _ => None,
+                };
+                drop(table);
+                let first = match state {
+                    Some((sample, held)) => Reply::State(held.state(hello, sample)),
+                    None => Reply::Hello(hello),
+                };
+                write_line(&mut out, &first)?;
+                return stream_samples(&mut out, samples);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn stream_samples<W: Write>(out: &mut W, samples: Receiver<Arc<String>>) -> io::Result<()> {
+    for line in samples {
+        out.write_all(line.as_bytes())?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+// Binds `path`, taking it over if it's a socket a previous daemon left behind.
+// Anything else that's there is left alone. Only the owner gets to connect
+// until someone widens it.
+fn listen(path: &Path) -> io::Result<UnixListener> {
+    let listener = match UnixListener::bind(path) {
+        Err(err) if err.kind() == io::ErrorKind::AddrInUse => {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(io::ErrorKind::AddrInUse, "another daemon is already listening there"));
+            }
+            if !fs::symlink_metadata(path)?.file_type().is_socket() {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists, "something other than a socket is there"));
+            }
+            fs::remove_file(path)?;
+            UnixListener::bind(path)?
+        }
+        result => result?,
+    };
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+// An agent's connection, which gets a while and a line of limited length to
+// authenticate, and then as long as it likes.
+fn serve_agent(stream: TcpStream, shared: &Shared, token: &str) -> io::Result<()> {
+    stream.set_read_timeout(Some(AUTH_TIMEOUT))?;
+    let mut input = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    (&mut input).take(MAX_AUTH_LINE).read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        return match line.len() as u64 {
+            MAX_AUTH_LINE => write_line(&mut &stream, &Reply::Error { message: "request too long".to_string() }),
+            // hung up before saying anything
+            _ => Ok(()),
+        };
+    }
+    stream.set_read_timeout(None)?;
+    let out = io::BufWriter::new(stream);
+    serve(io::Cursor::new(line).chain(input), out, shared, Some(token))
+}
+
+pub struct Daemon {
+    shared: Arc<Shared>,
+}
+
+impl Daemon {
+    pub fn new(table: Arc<Mutex<StatefulTable>>, interval: Duration, namespace: Option<&str>) -> Daemon {
+        let shared = Arc::new(Shared {
+            table,
+            subscribers: Mutex::new(Vec::new()),
+            started: Instant::now(),
+            interval,
+            namespace: namespace.map(str::to_string),
+        });
+        Daemon { shared }
+    }
+
+    // Serves the control socket at `path`, for TUIs on this host.
+    pub fn listen(&self, path: &Path) -> io::Result<()> {
+        let listener = listen(path)?;
+        let shared = self.shared.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let shared = shared.clone();
+                // a client hanging up is its own business
+                thread::spawn(move || {
+                    let out = io::BufWriter::new(stream.try_clone()?);
+                    serve(stream, out, &shared, None)
+                });
+            }
+        });
+        Ok(())
+    }
+
+    // Serves fleets on `addr`, as long as they know `token`.
+    pub fn listen_agent(&self, addr: &str, token: String) -> io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        let shared = self.shared.clone();
+        let token: Arc<str> = token.into();
+        let open = Arc::new(AtomicUsize::new(0));
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // past the limit a connection is just closed again
+                if open.fetch_add(1, Ordering::SeqCst) >= MAX_AGENT_CONNECTIONS {
+                    open.fetch_sub(1, Ordering::SeqCst);
+                    continue;
+                }
+                let (shared, token, open) = (shared.clone(), token.clone(), open.clone());
+                thread::spawn(move || {
+                    let served = serve_agent(stream, &shared, &token);
+                    open.fetch_sub(1, Ordering::SeqCst);
+                    served
+                });
+            }
+        });
+        Ok(())
+    }
+
+    // Hands the latest refresh to every subscriber, dropping any that have
+    // gone away or fallen too far behind.
+    pub fn publish(&self, table: &StatefulTable) {
+        let mut subscribers = self.shared.subscribers.lock().unwrap();
+        if subscribers.is_empty() {
+            return;
+        }
+        let line = match serde_json::to_string(&Reply::Sample(self.shared.sample(table))) {
+            Ok(line) => Arc::new(line + "\n"),
+            Err(_) => return,
+        };
+        subscribers.retain(|subscriber| match subscriber.try_send(line.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => false,
+        });
+    }
+}
+
+pub fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Subscribes to the daemon at `path` and builds a table from what it's kept so
+// far, which then follows along with its refreshes. Also returns the daemon's
+// refresh interval.
+pub fn attach(path: &Path, options: Options) -> io::Result<(StatefulTable, Duration)> {
+    let mut stream = UnixStream::connect(path)?;
+    write_line(&mut stream, &Request::Subscribe)?;
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let state = match serde_json::from_str(&line)? {
+        Reply::State(state) => state,
+        Reply::Error { message } => return Err(invalid(message)),
+        _ => return Err(invalid("expected the daemon's state".to_string())),
+    };
+    if state.hello.version != PROTOCOL_VERSION {
+        return Err(invalid(format!(
+            "the daemon speaks protocol version {}, this tcptop speaks {}",
+            state.hello.version, PROTOCOL_VERSION
+        )));
+    }
+
+    // the daemon's monotonic clock mapped onto ours, from when the state was taken
+    let (base, base_offset) = (Instant::now(), state.sample.offset_ms);
+    let local = move |offset_ms: u64| match offset_ms.checked_sub(base_offset) {
+        Some(ahead) => base + Duration::from_millis(ahead),
+        None => base.checked_sub(Duration::from_millis(base_offset - offset_ms)).unwrap_or(base),
+    };
+    let sample = move |sample: Sample| table::Sample {
+        at: UNIX_EPOCH + Duration::from_millis(sample.time_ms),
+        taken: local(sample.offset_ms),
+        snapshot: sample.snapshot,
+        listeners: sample.listeners,
+        down: Vec::new(),
+    };
+
+    let history: HashMap<_, _> = state
+        .history
+        .into_iter()
+        .map(|entry| (entry.socket.id(), Arc::new(entry.history.into_history(base))))
+        .collect();
+    let closed = state
+        .closed
+        .into_iter()
+        .map(|closed| ClosedSocket {
+            seq: closed.seq,
+            at: UNIX_EPOCH + Duration::from_millis(closed.time_ms),
+            socket: closed.socket,
+            history: Arc::new(closed.history.into_history(base)),
+        })
+        .collect();
+    let options = Options { retention: Duration::from_millis(state.hello.retention_ms), ..options };
+    let interval = Duration::from_millis(state.hello.interval_ms);
+    let latest = sample(state.sample);
+
+    let (sender, feed) = mpsc::channel();
+    thread::spawn(move || {
+        for line in reader.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if let Ok(Reply::Sample(next)) = serde_json::from_str(&line) {
+                if sender.send(sample(next)).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    Ok((StatefulTable::attached(options, feed, latest, history, closed), interval))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_round_trip() {
+        let mut history = SocketHistory::new(&TCPInfo::default(), false, Instant::now());
+        let taken = Instant::now();
+        history.sampled_at = VecDeque::from(vec![taken - Duration::from_secs(1), taken - Duration::from_secs(3600)]);
+        history.send_bps = VecDeque::from(vec![100, 50]);
+        let line = serde_json::to_string(&History::of(&history, taken)).unwrap();
+        assert!(line.contains("\"age_ms\":[1000,3600000]"));
+
+        let later = taken + Duration::from_secs(10);
+        let back = serde_json::from_str::<History>(&line).unwrap().into_history(later);
+        assert_eq!(back.sampled_at[1], later - Duration::from_secs(3600));
+        assert_eq!(back.send_bps, history.send_bps);
+    }
+
+    #[test]
+    fn test_requests() {
+        assert!(matches!(serde_json::from_str(r#"{"request":"subscribe"}"#), Ok(Request::Subscribe)));
+        assert!(serde_json::from_str::<Request>(r#"{"request":"shutdown"}"#).is_err());
+        let reply = serde_json::to_string(&Reply::Error { message: "nope".to_string() }).unwrap();
+        assert_eq!(reply, r#"{"type":"error","message":"nope"}"#);
+    }
+}
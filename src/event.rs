@@ -1,17 +1,14 @@
 use std::io;
-use std::sync::mpsc;
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
-};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::thread;
 use std::time::Duration;
 
-use termion::event::Key;
+use termion::event::{self, Key, MouseEvent};
 use termion::input::TermRead;
 
 pub enum Event<I> {
     Input(I),
+    Mouse(MouseEvent),
     Tick,
 }
 
@@ -19,63 +16,68 @@ pub enum Event<I> {
 /// type is handled in its own thread and returned to a common `Receiver`
 pub struct Events {
     rx: mpsc::Receiver<Event<Key>>,
+    #[allow(dead_code)]
     input_handle: thread::JoinHandle<()>,
-    ignore_exit_key: Arc<AtomicBool>,
+    #[allow(dead_code)]
     tick_handle: thread::JoinHandle<()>,
+    tick_rate: mpsc::Sender<Duration>,
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct Config {
-    pub exit_key: Key,
     pub tick_rate: Duration,
 }
 
 impl Default for Config {
     fn default() -> Config {
         Config {
-            exit_key: Key::Char('q'),
             tick_rate: Duration::from_millis(1000),
         }
     }
 }
 
 impl Events {
-    pub fn new() -> Events {
-        Events::with_config(Config::default())
-    }
-
     pub fn with_config(config: Config) -> Events {
         let (tx, rx) = mpsc::channel();
-        let ignore_exit_key = Arc::new(AtomicBool::new(false));
         let input_handle = {
             let tx = tx.clone();
-            let ignore_exit_key = ignore_exit_key.clone();
+            // every key goes to the main loop, which decides what quits
             thread::spawn(move || {
                 let stdin = io::stdin();
-                for evt in stdin.keys() {
-                    if let Ok(key) = evt {
-                        if let Err(err) = tx.send(Event::Input(key)) {
-                            eprintln!("{}", err);
-                            return;
-                        }
-                        if !ignore_exit_key.load(Ordering::Relaxed) && key == config.exit_key {
-                            return;
-                        }
+                for input in stdin.events().flatten() {
+                    let event = match input {
+                        event::Event::Key(key) => Event::Input(key),
+                        event::Event::Mouse(mouse) => Event::Mouse(mouse),
+                        event::Event::Unsupported(_) => continue,
+                    };
+                    if let Err(err) = tx.send(event) {
+                        eprintln!("{}", err);
+                        return;
                     }
                 }
             })
         };
+        // waits on the rate channel rather than sleeping, so a new rate takes
+        // effect straight away instead of after the old one runs out
+        let (rate_tx, rate_rx) = mpsc::channel();
         let tick_handle = {
-            thread::spawn(move || loop {
+            thread::spawn(move || {
+                let mut tick_rate = config.tick_rate;
                 tx.send(Event::Tick).unwrap();
-                thread::sleep(config.tick_rate);
+                loop {
+                    match rate_rx.recv_timeout(tick_rate) {
+                        Ok(rate) => tick_rate = rate,
+                        Err(RecvTimeoutError::Timeout) => tx.send(Event::Tick).unwrap(),
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
             })
         };
         Events {
             rx,
-            ignore_exit_key,
             input_handle,
             tick_handle,
+            tick_rate: rate_tx,
         }
     }
 
@@ -83,11 +85,98 @@ impl Events {
         self.rx.recv()
     }
 
-    pub fn disable_exit_key(&mut self) {
-        self.ignore_exit_key.store(true, Ordering::Relaxed);
+    pub fn set_tick_rate(&self, rate: Duration) {
+        let _ = self.tick_rate.send(rate);
+    }
+}
+
+// The steps `+` and `-` move through, from MIN_INTERVAL to MAX_INTERVAL.
+const INTERVALS: [u64; 12] = [100, 200, 250, 500, 1000, 2000, 3000, 5000, 10000, 15000, 30000, 60000];
+pub const MIN_INTERVAL: Duration = Duration::from_millis(INTERVALS[0]);
+pub const MAX_INTERVAL: Duration = Duration::from_millis(INTERVALS[INTERVALS.len() - 1]);
+
+pub fn longer_interval(interval: Duration) -> Duration {
+    INTERVALS
+        .iter()
+        .map(|ms| Duration::from_millis(*ms))
+        .find(|step| *step > interval)
+        .unwrap_or(MAX_INTERVAL)
+}
+
+pub fn shorter_interval(interval: Duration) -> Duration {
+    INTERVALS
+        .iter()
+        .rev()
+        .map(|ms| Duration::from_millis(*ms))
+        .find(|step| *step < interval)
+        .unwrap_or(MIN_INTERVAL)
+}
+
+// "250ms", "2s", "1.5s", "1m", or a bare number of seconds.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let (number, scale) = match text {
+        _ if text.ends_with("ms") => (&text[..text.len() - 2], 0.001),
+        _ if text.ends_with('s') => (&text[..text.len() - 1], 1.0),
+        _ if text.ends_with('m') => (&text[..text.len() - 1], 60.0),
+        _ if text.ends_with('h') => (&text[..text.len() - 1], 3600.0),
+        _ => (text, 1.0),
+    };
+    // try_from_secs_f64 also turns away negatives, NaN and ones too big to hold
+    number
+        .parse::<f64>()
+        .ok()
+        .and_then(|n| Duration::try_from_secs_f64(n * scale).ok())
+        .ok_or(format!("'{}' isn't a duration like 500ms, 2s or 1h", text))
+}
+
+pub fn parse_interval(text: &str) -> Result<Duration, String> {
+    let interval = parse_duration(text)?;
+    if interval < MIN_INTERVAL || interval > MAX_INTERVAL {
+        return Err(format!("interval has to be between {} and {}", format_interval(MIN_INTERVAL), format_interval(MAX_INTERVAL)));
+    }
+    Ok(interval)
+}
+
+pub fn format_interval(interval: Duration) -> String {
+    match interval.as_millis() {
+        ms if ms >= 3_600_000 && ms % 3_600_000 == 0 => format!("{}h", ms / 3_600_000),
+        ms if ms >= 60_000 && ms % 60_000 == 0 => format!("{}m", ms / 60_000),
+        ms if ms % 1000 == 0 => format!("{}s", ms / 1000),
+        ms if ms > 1000 => format!("{:.1}s", ms as f64 / 1000.0),
+        ms => format!("{}ms", ms),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_interval("2s"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_interval("1.5"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_interval("1m"), Ok(Duration::from_secs(60)));
+        assert!(parse_interval("50ms").is_err());
+        assert!(parse_interval("2h").is_err());
+        // retention goes past the interval limits
+        assert_eq!(parse_duration("10m"), Ok(Duration::from_secs(600)));
+        assert_eq!(parse_duration("6h"), Ok(Duration::from_secs(6 * 3600)));
+        assert!(parse_interval("fast").is_err());
+        assert!(parse_duration("1e30h").is_err());
+        assert!(parse_interval("1e300").is_err());
+        assert!(parse_duration("-1s").is_err());
     }
 
-    pub fn enable_exit_key(&mut self) {
-        self.ignore_exit_key.store(false, Ordering::Relaxed);
+    #[test]
+    fn test_interval_steps() {
+        assert_eq!(longer_interval(Duration::from_secs(1)), Duration::from_secs(2));
+        assert_eq!(longer_interval(Duration::from_millis(1200)), Duration::from_secs(2));
+        assert_eq!(longer_interval(MAX_INTERVAL), MAX_INTERVAL);
+        assert_eq!(shorter_interval(Duration::from_millis(1200)), Duration::from_secs(1));
+        assert_eq!(shorter_interval(MIN_INTERVAL), MIN_INTERVAL);
+        assert_eq!(format_interval(Duration::from_millis(1500)), "1.5s");
+        assert_eq!(format_interval(Duration::from_millis(250)), "250ms");
+        assert_eq!(format_interval(Duration::from_secs(24 * 3600)), "24h");
     }
 }
@@ -0,0 +1,106 @@
+use crate::json::rfc3339;
+use crate::table::{SocketHistory, StatefulTable};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+// Quotes a field only when it has to, per RFC 4180.
+fn field(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+pub fn write_row<W: Write, S: AsRef<str>>(out: &mut W, row: &[S]) -> io::Result<()> {
+    let fields: Vec<String> = row.iter().map(|value| field(value.as_ref())).collect();
+    writeln!(out, "{}", fields.join(","))
+}
+
+// The socket table's header, by column id, behind a time column.
+pub fn table_header(table: &StatefulTable) -> Vec<String> {
+    std::iter::once("time".to_string())
+        .chain(table.layout_ids().into_iter().map(str::to_string))
+        .collect()
+}
+
+// Every shown socket, with plain values rather than what's on screen.
+pub fn write_table<W: Write>(out: &mut W, table: &StatefulTable) -> io::Result<()> {
+    let time = rfc3339(table.captured_at().0);
+    for row in table.raw_rows() {
+        let row: Vec<String> = std::iter::once(time.clone()).chain(row).collect();
+        write_row(out, &row)?;
+    }
+    Ok(())
+}
+
+const HISTORY_HEADER: [&str; 15] = [
+    "time",
+    "offset_ms",
+    "send_bps",
+    "recv_bps",
+    "send_bytes",
+    "recv_bytes",
+    "loss_percent",
+    "snd_cwnd",
+    "snd_ssthresh",
+    "rtt",
+    "rttvar",
+    "min_rtt",
+    "retransmits",
+    "pacing_rate",
+    "delivery_rate",
+];
+
+// Every ring in a socket's history, oldest sample first. `now` pins the
+// monotonic sample times to the wall clock.
+pub fn write_history<W: Write>(out: &mut W, history: &SocketHistory, now: (SystemTime, Instant)) -> io::Result<()> {
+    write_row(out, &HISTORY_HEADER)?;
+    let oldest = history.sampled_at.back().copied().unwrap_or(now.1);
+    for i in (0..history.sampled_at.len()).rev() {
+        let at = history.sampled_at[i];
+        let row = [
+            rfc3339(now.0 - now.1.saturating_duration_since(at)),
+            at.saturating_duration_since(oldest).as_millis().to_string(),
+            history.send_bps[i].to_string(),
+            history.recv_bps[i].to_string(),
+            history.send_bytes[i].to_string(),
+            history.recv_bytes[i].to_string(),
+            history.packet_loss[i].to_string(),
+            history.congestion_window[i].to_string(),
+            history.slow_start_threshold[i].to_string(),
+            history.rtt[i].to_string(),
+            history.rtt_variance[i].to_string(),
+            history.min_rtt[i].to_string(),
+            history.retransmits[i].to_string(),
+            history.pacing_rate[i].to_string(),
+            history.delivery_rate[i].to_string(),
+        ];
+        write_row(out, &row)?;
+    }
+    Ok(())
+}
+
+// A name in the current directory that says what's in it and when it was taken.
+pub fn file_name(what: &str, stamp: &str) -> PathBuf {
+    PathBuf::from(format!("tcptop-{}-{}.csv", what, stamp))
+}
+
+pub fn save<F: FnOnce(&mut BufWriter<File>) -> io::Result<()>>(path: &Path, write: F) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write(&mut out)?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_row() {
+        let mut out = Vec::new();
+        write_row(&mut out, &["plain", "with,comma", "say \"hi\"", ""]).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "plain,\"with,comma\",\"say \"\"hi\"\"\",\n");
+    }
+}
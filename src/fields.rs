@@ -0,0 +1,280 @@
+use crate::tcpdiag::{TCPInfo, TCP_STATE};
+
+// Describes every field of `TCPInfo` we know how to show, what unit the kernel
+// reports it in and how to pull it out as a plain number. The column registry
+// and the detail view are both built from this table.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Unit {
+    Count,
+    Micros,
+    Millis,
+    Bytes,
+    BytesPerSec,
+    // segment counts where the kernel uses INT_MAX to mean "not set yet"
+    Threshold,
+    State,
+    CaState,
+    Options,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Group {
+    State,
+    Timing,
+    Windows,
+    Counters,
+    Rates,
+    Limits,
+}
+
+impl Group {
+    pub const ALL: [Group; 6] = [
+        Group::State,
+        Group::Timing,
+        Group::Windows,
+        Group::Counters,
+        Group::Rates,
+        Group::Limits,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Group::State => "State",
+            Group::Timing => "Timing",
+            Group::Windows => "Windows",
+            Group::Counters => "Counters",
+            Group::Rates => "Rates",
+            Group::Limits => "Limits",
+        }
+    }
+}
+
+pub struct InfoField {
+    pub name: &'static str,
+    pub label: &'static str,
+    pub unit: Unit,
+    pub group: Group,
+    pub get: fn(&TCPInfo) -> u64,
+}
+
+impl InfoField {
+    pub fn value(&self, info: &TCPInfo) -> u64 {
+        (self.get)(info)
+    }
+
+    pub fn format(&self, info: &TCPInfo) -> String {
+        human(self.unit, self.value(info))
+    }
+
+    // How much the field moved between two samples, in the field's own units.
+    // Fields that aren't quantities (state, options) just say what they were.
+    pub fn delta(&self, previous: &TCPInfo, current: &TCPInfo) -> Option<String> {
+        let (before, after) = (self.value(previous), self.value(current));
+        if before == after {
+            return None;
+        }
+        let sign = if after > before { "+" } else { "-" };
+        let diff = after.abs_diff(before);
+        match self.unit {
+            Unit::State | Unit::CaState | Unit::Options | Unit::Threshold => {
+                Some(format!("was {}", human(self.unit, before)))
+            }
+            unit => Some(format!("{}{}", sign, human(unit, diff))),
+        }
+    }
+}
+
+macro_rules! field {
+    ($group:ident, $name:expr, $label:expr, $unit:ident, $field:ident) => {
+        InfoField { name: $name, label: $label, unit: Unit::$unit, group: Group::$group, get: |i| i.$field as u64 }
+    };
+    ($group:ident, $name:expr, $label:expr, $unit:ident, $get:expr) => {
+        InfoField { name: $name, label: $label, unit: Unit::$unit, group: Group::$group, get: $get }
+    };
+}
+
+pub const INFO_FIELDS: &[InfoField] = &[
+    field!(State, "tcp_state", "State", State, tcpi_state),
+    field!(State, "ca_state", "CA state", CaState, tcpi_ca_state),
+    field!(State, "retransmits", "Retransmits (current)", Count, tcpi_retransmits),
+    field!(State, "probes", "Probes", Count, tcpi_probes),
+    field!(State, "backoff", "Backoff", Count, tcpi_backoff),
+    field!(State, "options", "Options", Options, tcpi_options),
+    field!(State, "snd_wscale", "Send wscale", Count, |i| (i._bitfield_1[0] & 0x0f) as u64),
+    field!(State, "rcv_wscale", "Recv wscale", Count, |i| (i._bitfield_1[0] >> 4) as u64),
+    field!(State, "app_limited", "App limited", Count, |i| (i._bitfield_1[1] & 0x01) as u64),
+    field!(Timing, "rto", "RTO", Micros, tcpi_rto),
+    field!(Timing, "ato", "ATO", Micros, tcpi_ato),
+    field!(Windows, "snd_mss", "Send MSS", Bytes, tcpi_snd_mss),
+    field!(Windows, "rcv_mss", "Recv MSS", Bytes, tcpi_rcv_mss),
+    field!(Counters, "unacked", "Unacked", Count, tcpi_unacked),
+    field!(Counters, "sacked", "SACKed", Count, tcpi_sacked),
+    field!(Counters, "lost", "Lost", Count, tcpi_lost),
+    field!(Counters, "retrans", "Retrans (in flight)", Count, tcpi_retrans),
+    field!(Counters, "fackets", "FACKets", Count, tcpi_fackets),
+    field!(Timing, "last_data_sent", "Last data sent", Millis, tcpi_last_data_sent),
+    field!(Timing, "last_ack_sent", "Last ack sent", Millis, tcpi_last_ack_sent),
+    field!(Timing, "last_data_recv", "Last data recv", Millis, tcpi_last_data_recv),
+    field!(Timing, "last_ack_recv", "Last ack recv", Millis, tcpi_last_ack_recv),
+    field!(Windows, "pmtu", "Path MTU", Bytes, tcpi_pmtu),
+    field!(Windows, "rcv_ssthresh", "Recv ssthresh", Bytes, tcpi_rcv_ssthresh),
+    field!(Timing, "rtt", "RTT", Micros, tcpi_rtt),
+    field!(Timing, "rttvar", "RTT variance", Micros, tcpi_rttvar),
+    field!(Windows, "snd_ssthresh", "Send ssthresh", Threshold, tcpi_snd_ssthresh),
+    field!(Windows, "snd_cwnd", "Congestion window", Count, tcpi_snd_cwnd),
+    field!(Windows, "advmss", "Advertised MSS", Bytes, tcpi_advmss),
+    field!(Windows, "reordering", "Reordering", Count, tcpi_reordering),
+    field!(Timing, "rcv_rtt", "Recv RTT", Micros, tcpi_rcv_rtt),
+    field!(Windows, "rcv_space", "Recv space", Bytes, tcpi_rcv_space),
+    field!(Counters, "total_retrans", "Total retransmits", Count, tcpi_total_retrans),
+    field!(Rates, "pacing_rate", "Pacing rate", BytesPerSec, tcpi_pacing_rate),
+    field!(Rates, "max_pacing_rate", "Max pacing rate", BytesPerSec, tcpi_max_pacing_rate),
+    field!(Counters, "bytes_acked", "Bytes acked", Bytes, tcpi_bytes_acked),
+    field!(Counters, "bytes_received", "Bytes received", Bytes, tcpi_bytes_received),
+    field!(Counters, "segs_out", "Segments out", Count, tcpi_segs_out),
+    field!(Counters, "segs_in", "Segments in", Count, tcpi_segs_in),
+    field!(Counters, "notsent_bytes", "Not sent", Bytes, tcpi_notsent_bytes),
+    field!(Timing, "min_rtt", "Min RTT", Micros, tcpi_min_rtt),
+    field!(Counters, "data_segs_in", "Data segments in", Count, tcpi_data_segs_in),
+    field!(Counters, "data_segs_out", "Data segments out", Count, tcpi_data_segs_out),
+    field!(Rates, "delivery_rate", "Delivery rate", BytesPerSec, tcpi_delivery_rate),
+    field!(Limits, "busy_time", "Busy time", Micros, tcpi_busy_time),
+    field!(Limits, "rwnd_limited", "Recv window limited", Micros, tcpi_rwnd_limited),
+    field!(Limits, "sndbuf_limited", "Send buffer limited", Micros, tcpi_sndbuf_limited),
+    field!(Counters, "delivered", "Delivered", Count, tcpi_delivered),
+    field!(Counters, "delivered_ce", "Delivered CE", Count, tcpi_delivered_ce),
+    field!(Counters, "bytes_sent", "Bytes sent", Bytes, tcpi_bytes_sent),
+    field!(Counters, "bytes_retrans", "Bytes retransmitted", Bytes, tcpi_bytes_retrains),
+    field!(Counters, "dsack_dups", "DSACK duplicates", Count, tcpi_dsack_dups),
+    field!(Counters, "reord_seen", "Reordering seen", Count, tcpi_reord_seen),
+    field!(Counters, "rcv_ooopack", "Out of order recv", Count, tcpi_rcv_ooopack),
+    field!(Windows, "snd_wnd", "Peer recv window", Bytes, tcpi_snd_wnd),
+];
+
+pub fn human(unit: Unit, value: u64) -> String {
+    match unit {
+        Unit::Count => value.to_string(),
+        Unit::Micros => human_micros(value),
+        Unit::Millis => human_micros(value.saturating_mul(1000)),
+        Unit::Bytes => human_bytes(value),
+        Unit::BytesPerSec if value == u64::MAX => "unlimited".to_string(),
+        Unit::BytesPerSec => human_bitrate(value),
+        Unit::Threshold if value >= 0x7fff_ffff => "infinite".to_string(),
+        Unit::Threshold => value.to_string(),
+        Unit::State => TCP_STATE::from_u8(value as u8).to_string(),
+        Unit::CaState => ca_state_name(value as u8).to_string(),
+        Unit::Options => options_names(value as u8),
+    }
+}
+
+pub fn human_micros(us: u64) -> String {
+    match us {
+        n if n < 1000 => format!("{} us", n),
+        n if n < 1_000_000 => format!("{:.3} ms", n as f64 / 1000.0),
+        n => format!("{:.2} s", n as f64 / 1_000_000.0),
+    }
+}
+
+pub fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.2} {}", value, UNITS[unit]),
+    }
+}
+
+// The kernel reports rates in bytes per second, but link speeds are usually
+// thought about in bits so that's what we show.
+pub fn human_bitrate(bytes_per_sec: u64) -> String {
+    let bits = bytes_per_sec as f64 * 8.0;
+    match bits {
+        n if n < 1000.0 => format!("{} bit/s", n),
+        n if n < 1_000_000.0 => format!("{:.2} Kbit/s", n / 1000.0),
+        n if n < 1_000_000_000.0 => format!("{:.2} Mbit/s", n / 1_000_000.0),
+        n => format!("{:.2} Gbit/s", n / 1_000_000_000.0),
+    }
+}
+
+fn ca_state_name(state: u8) -> &'static str {
+    match state {
+        0 => "Open",
+        1 => "Disorder",
+        2 => "CWR",
+        3 => "Recovery",
+        4 => "Loss",
+        _ => "Unknown",
+    }
+}
+
+fn options_names(options: u8) -> String {
+    const NAMES: [(u8, &str); 6] = [
+        (0x01, "ts"),
+        (0x02, "sack"),
+        (0x04, "wscale"),
+        (0x08, "ecn"),
+        (0x10, "ecn_seen"),
+        (0x20, "syn_data"),
+    ];
+    let names: Vec<&str> = NAMES
+        .iter()
+        .filter(|(bit, _)| options & bit != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    match names.len() {
+        0 => "-".to_string(),
+        _ => names.join(" "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(name: &str) -> Option<&'static InfoField> {
+        INFO_FIELDS.iter().find(|field| field.name == name)
+    }
+
+    #[test]
+    fn test_human_units() {
+        assert_eq!(human_micros(850), "850 us");
+        assert_eq!(human_micros(12_345), "12.345 ms");
+        assert_eq!(human_micros(2_500_000), "2.50 s");
+        assert_eq!(human_bytes(512), "512 B");
+        assert_eq!(human_bytes(64 * 1024), "64.00 KiB");
+        assert_eq!(human_bytes(3 * 1024 * 1024 / 2), "1.50 MiB");
+        assert_eq!(human_bitrate(100), "800 bit/s");
+        assert_eq!(human_bitrate(125), "1.00 Kbit/s");
+        assert_eq!(human_bitrate(1_250_000), "10.00 Mbit/s");
+        assert_eq!(human(Unit::Options, 0x07), "ts sack wscale");
+        assert_eq!(human(Unit::CaState, 3), "Recovery");
+        assert_eq!(human(Unit::BytesPerSec, u64::MAX), "unlimited");
+        assert_eq!(human(Unit::Threshold, 0x7fff_ffff), "infinite");
+    }
+
+    #[test]
+    fn test_field_lookup() {
+        let info = TCPInfo { tcpi_snd_cwnd: 10, _bitfield_1: [0x97, 0x01], ..Default::default() };
+        assert_eq!(find("snd_cwnd").unwrap().value(&info), 10);
+        assert_eq!(find("snd_wscale").unwrap().value(&info), 7);
+        assert_eq!(find("rcv_wscale").unwrap().value(&info), 9);
+        assert_eq!(find("app_limited").unwrap().value(&info), 1);
+        assert!(find("bogus").is_none());
+    }
+
+    #[test]
+    fn test_delta() {
+        let before = TCPInfo { tcpi_rtt: 20_000, tcpi_bytes_sent: 4096, tcpi_ca_state: 0, ..Default::default() };
+        let after = TCPInfo { tcpi_rtt: 19_500, tcpi_bytes_sent: 6144, tcpi_ca_state: 4, ..Default::default() };
+        assert_eq!(find("rtt").unwrap().delta(&before, &after), Some("-500 us".to_string()));
+        assert_eq!(find("bytes_sent").unwrap().delta(&before, &after), Some("+2.00 KiB".to_string()));
+        assert_eq!(find("ca_state").unwrap().delta(&before, &after), Some("was Open".to_string()));
+        assert_eq!(find("snd_cwnd").unwrap().delta(&before, &after), None);
+    }
+}
@@ -0,0 +1,527 @@
+use crate::tcpdiag::{DiagWithInode, TCP_STATE};
+use std::fmt;
+use std::net::IpAddr;
+
+// Small expression language used to narrow the socket list, e.g.
+//
+//   dport == 443 and (rtt > 50ms or retrans > 0)
+//   dst == 10.0.0.0/8 && !state == TIME_WAIT
+//   comm ~ nginx
+//
+// Parsing is done up front so the UI can point at the offending token while the
+// user is still typing, evaluation is then just walking the tree per socket.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterError {
+    pub start: usize,
+    pub end: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at column {})", self.msg, self.start + 1)
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Sport,
+    Dport,
+    Src,
+    Dst,
+    State,
+    Rtt,
+    Retrans,
+    Pid,
+    Comm,
+    Cc,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Field> {
+        match name.to_ascii_lowercase().as_str() {
+            "sport" => Some(Field::Sport),
+            "dport" => Some(Field::Dport),
+            "src" => Some(Field::Src),
+            "dst" => Some(Field::Dst),
+            "state" => Some(Field::State),
+            "rtt" => Some(Field::Rtt),
+            "retrans" => Some(Field::Retrans),
+            "pid" => Some(Field::Pid),
+            "comm" => Some(Field::Comm),
+            "cc" => Some(Field::Cc),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Match,
+    NotMatch,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Net {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Net {
+    fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_match(&net.octets(), &ip.octets(), self.prefix)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_match(&net.octets(), &ip.octets(), self.prefix)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn prefix_match(net: &[u8], ip: &[u8], prefix: u8) -> bool {
+    let full = (prefix / 8) as usize;
+    if net[..full] != ip[..full] {
+        return false;
+    }
+    let rest = prefix % 8;
+    if rest == 0 {
+        return true;
+    }
+    let mask = 0xffu8 << (8 - rest);
+    net[full] & mask == ip[full] & mask
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Num(f64),
+    Net(Net),
+    State(u8),
+    Str(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Cmp(Field, Op, Value),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Word(String),
+    Quoted(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+struct Token {
+    tok: Tok,
+    start: usize,
+    end: usize,
+}
+
+fn is_word_char(c: char) -> bool {
+    !c.is_whitespace() && !"!=<>~()&|\"'".contains(c)
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, FilterError> {
+    let mut tokens = Vec::new();
+    let chars: Vec<(usize, char)> = input.char_indices().collect();
+    let mut i = 0;
+    let err = |start: usize, end: usize, msg: &str| FilterError { start, end, msg: msg.to_string() };
+
+    while i < chars.len() {
+        let (pos, c) = chars[i];
+        let next = chars.get(i + 1).map(|(_, c)| *c);
+        let (tok, len) = match (c, next) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('(', _) => (Tok::LParen, 1),
+            (')', _) => (Tok::RParen, 1),
+            ('&', Some('&')) => (Tok::And, 2),
+            ('|', Some('|')) => (Tok::Or, 2),
+            ('=', Some('=')) => (Tok::Op(Op::Eq), 2),
+            ('=', _) => (Tok::Op(Op::Eq), 1),
+            ('!', Some('=')) => (Tok::Op(Op::Ne), 2),
+            ('!', Some('~')) => (Tok::Op(Op::NotMatch), 2),
+            ('!', _) => (Tok::Not, 1),
+            ('<', Some('=')) => (Tok::Op(Op::Le), 2),
+            ('<', _) => (Tok::Op(Op::Lt), 1),
+            ('>', Some('=')) => (Tok::Op(Op::Ge), 2),
+            ('>', _) => (Tok::Op(Op::Gt), 1),
+            ('~', _) => (Tok::Op(Op::Match), 1),
+            ('&', _) | ('|', _) => {
+                return Err(err(pos, pos + 1, &format!("expected '{}{}'", c, c)));
+            }
+            ('"', _) | ('\'', _) => {
+                let close = chars[i + 1..].iter().position(|(_, q)| *q == c);
+                match close {
+                    Some(offset) => {
+                        let end = i + 1 + offset;
+                        let text: String = chars[i + 1..end].iter().map(|(_, c)| c).collect();
+                        tokens.push(Token { tok: Tok::Quoted(text), start: pos, end: chars[end].0 + 1 });
+                        i = end + 1;
+                        continue;
+                    }
+                    None => return Err(err(pos, input.len(), "unterminated string")),
+                }
+            }
+            _ => {
+                let mut j = i;
+                while j < chars.len() && is_word_char(chars[j].1) {
+                    j += 1;
+                }
+                let end = chars.get(j).map(|(p, _)| *p).unwrap_or_else(|| input.len());
+                let word = &input[pos..end];
+                let tok = match word.to_ascii_lowercase().as_str() {
+                    "and" => Tok::And,
+                    "or" => Tok::Or,
+                    "not" => Tok::Not,
+                    _ => Tok::Word(word.to_string()),
+                };
+                tokens.push(Token { tok, start: pos, end });
+                i = j;
+                continue;
+            }
+        };
+        tokens.push(Token { tok, start: pos, end: pos + len });
+        i += len;
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    input: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn error_here(&self, msg: &str) -> FilterError {
+        match self.peek() {
+            Some(token) => FilterError { start: token.start, end: token.end, msg: msg.to_string() },
+            None => FilterError { start: self.input.len(), end: self.input.len(), msg: msg.to_string() },
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, FilterError> {
+        let mut lhs = self.parse_and()?;
+        while let Some(Tok::Or) = self.peek().map(|t| &t.tok) {
+            self.pos += 1;
+            let rhs = self.parse_and()?;
+            lhs = Expr::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, FilterError> {
+        let mut lhs = self.parse_not()?;
+        while let Some(Tok::And) = self.peek().map(|t| &t.tok) {
+            self.pos += 1;
+            let rhs = self.parse_not()?;
+            lhs = Expr::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, FilterError> {
+        if let Some(Tok::Not) = self.peek().map(|t| &t.tok) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_atom()
+    }
+
+    fn parse_atom(&mut self) -> Result<Expr, FilterError> {
+        let token = match self.tokens.get(self.pos) {
+            Some(token) => token,
+            None => return Err(self.error_here("expected a comparison")),
+        };
+        match &token.tok {
+            Tok::LParen => {
+                self.pos += 1;
+                let expr = self.parse_or()?;
+                match self.peek().map(|t| &t.tok) {
+                    Some(Tok::RParen) => {
+                        self.pos += 1;
+                        Ok(expr)
+                    }
+                    _ => Err(self.error_here("expected ')'")),
+                }
+            }
+            Tok::Word(name) => {
+                let field = match Field::from_name(name) {
+                    Some(field) => field,
+                    None => return Err(self.error_here(&format!("unknown field '{}'", name))),
+                };
+                self.pos += 1;
+                let op = match self.peek().map(|t| &t.tok) {
+                    Some(Tok::Op(op)) => *op,
+                    _ => return Err(self.error_here("expected an operator")),
+                };
+                let op_token = self.pos;
+                self.pos += 1;
+                let value_token = match self.tokens.get(self.pos) {
+                    Some(token) => token,
+                    None => return Err(self.error_here("expected a value")),
+                };
+                let text = match &value_token.tok {
+                    Tok::Word(text) | Tok::Quoted(text) => text.clone(),
+                    _ => return Err(self.error_here("expected a value")),
+                };
+                let value = parse_value(field, &text).map_err(|msg| FilterError {
+                    start: value_token.start,
+                    end: value_token.end,
+                    msg,
+                })?;
+                if !op_allowed(field, op) {
+                    let token = &self.tokens[op_token];
+                    return Err(FilterError {
+                        start: token.start,
+                        end: token.end,
+                        msg: "operator not supported for this field".to_string(),
+                    });
+                }
+                self.pos += 1;
+                Ok(Expr::Cmp(field, op, value))
+            }
+            _ => Err(self.error_here("expected a field name")),
+        }
+    }
+}
+
+fn op_allowed(field: Field, op: Op) -> bool {
+    match field {
+        Field::Sport | Field::Dport | Field::Rtt | Field::Retrans | Field::Pid => {
+            op != Op::Match && op != Op::NotMatch
+        }
+        Field::Src | Field::Dst | Field::State => op == Op::Eq || op == Op::Ne,
+        Field::Comm | Field::Cc => matches!(op, Op::Eq | Op::Ne | Op::Match | Op::NotMatch),
+    }
+}
+
+fn parse_number(text: &str) -> Result<f64, String> {
+    text.parse::<f64>().map_err(|_| format!("'{}' is not a number", text))
+}
+
+fn parse_value(field: Field, text: &str) -> Result<Value, String> {
+    match field {
+        Field::Sport | Field::Dport | Field::Retrans | Field::Pid => parse_number(text).map(Value::Num),
+        Field::Rtt => {
+            // rtt is compared in milliseconds unless told otherwise
+            let (number, scale) = if let Some(n) = text.strip_suffix("us") {
+                (n, 0.001)
+            } else if let Some(n) = text.strip_suffix("ms") {
+                (n, 1.0)
+            } else if let Some(n) = text.strip_suffix('s') {
+                (n, 1000.0)
+            } else {
+                (text, 1.0)
+            };
+            parse_number(number).map(|n| Value::Num(n * scale))
+        }
+        Field::Src | Field::Dst => {
+            let (addr, prefix) = match text.split_once('/') {
+                Some((addr, prefix)) => (addr, Some(prefix)),
+                None => (text, None),
+            };
+            let addr: IpAddr = addr.parse().map_err(|_| format!("'{}' is not an address", addr))?;
+            let max = if addr.is_ipv4() { 32 } else { 128 };
+            let prefix = match prefix {
+                Some(p) => match p.parse::<u8>() {
+                    Ok(p) if p <= max => p,
+                    _ => return Err(format!("'{}' is not a valid prefix length", p)),
+                },
+                None => max,
+            };
+            Ok(Value::Net(Net { addr, prefix }))
+        }
+        Field::State => match TCP_STATE::from_name(text) {
+            Some(state) => Ok(Value::State(state)),
+            None => Err(format!("unknown state '{}'", text)),
+        },
+        Field::Comm | Field::Cc => Ok(Value::Str(text.to_string())),
+    }
+}
+
+fn compare_num(lhs: f64, op: Op, rhs: f64) -> bool {
+    match op {
+        Op::Eq => lhs == rhs,
+        Op::Ne => lhs != rhs,
+        Op::Lt => lhs < rhs,
+        Op::Le => lhs <= rhs,
+        Op::Gt => lhs > rhs,
+        Op::Ge => lhs >= rhs,
+        Op::Match | Op::NotMatch => false,
+    }
+}
+
+fn compare_str(lhs: Option<&str>, op: Op, rhs: &str) -> bool {
+    let lhs = lhs.unwrap_or("");
+    match op {
+        Op::Eq => lhs == rhs,
+        Op::Ne => lhs != rhs,
+        Op::Match => lhs.contains(rhs),
+        Op::NotMatch => !lhs.contains(rhs),
+        _ => false,
+    }
+}
+
+fn eval(expr: &Expr, sock: &DiagWithInode) -> bool {
+    match expr {
+        Expr::And(lhs, rhs) => eval(lhs, sock) && eval(rhs, sock),
+        Expr::Or(lhs, rhs) => eval(lhs, sock) || eval(rhs, sock),
+        Expr::Not(inner) => !eval(inner, sock),
+        Expr::Cmp(field, op, value) => {
+            let info = sock.info.as_ref();
+            match (field, value) {
+                (Field::Sport, Value::Num(n)) => compare_num(sock.src.port() as f64, *op, *n),
+                (Field::Dport, Value::Num(n)) => compare_num(sock.dst.port() as f64, *op, *n),
+                (Field::Rtt, Value::Num(n)) => match info {
+                    Some(info) => compare_num(info.tcpi_rtt as f64 / 1000.0, *op, *n),
+                    None => false,
+                },
+                (Field::Retrans, Value::Num(n)) => match info {
+                    Some(info) => compare_num(info.tcpi_total_retrans as f64, *op, *n),
+                    None => false,
+                },
+                (Field::Pid, Value::Num(n)) => match &sock.process {
+                    Some(process) => compare_num(process.pid as f64, *op, *n),
+                    None => false,
+                },
+                (Field::Src, Value::Net(net)) => (*op == Op::Eq) == net.contains(&sock.src.ip()),
+                (Field::Dst, Value::Net(net)) => (*op == Op::Eq) == net.contains(&sock.dst.ip()),
+                (Field::State, Value::State(state)) => (*op == Op::Eq) == (sock.state == *state),
+                (Field::Comm, Value::Str(s)) => {
+                    compare_str(sock.process.as_ref().map(|p| p.comm.as_str()), *op, s)
+                }
+                (Field::Cc, Value::Str(s)) => compare_str(sock.cc.as_deref(), *op, s),
+                _ => false,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Filter {
+    pub source: String,
+    expr: Option<Expr>,
+}
+
+impl Filter {
+    pub fn parse(input: &str) -> Result<Filter, FilterError> {
+        let tokens = tokenize(input)?;
+        if tokens.is_empty() {
+            // an empty filter matches everything
+            return Ok(Filter { source: String::new(), expr: None });
+        }
+        let mut parser = Parser { tokens, pos: 0, input };
+        let expr = parser.parse_or()?;
+        if parser.peek().is_some() {
+            return Err(parser.error_here("unexpected input"));
+        }
+        Ok(Filter { source: input.trim().to_string(), expr: Some(expr) })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.expr.is_none()
+    }
+
+    pub fn matches(&self, sock: &DiagWithInode) -> bool {
+        match &self.expr {
+            Some(expr) => eval(expr, sock),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Filter;
+    use crate::tcpdiag::tests::socket;
+    use crate::tcpdiag::{DiagWithInode, TCPInfo};
+
+    fn sock(src: &str, dst: &str, state: u8, rtt_us: u32) -> DiagWithInode {
+        let info = TCPInfo {
+            tcpi_state: state,
+            tcpi_rtt: rtt_us,
+            tcpi_total_retrans: 3,
+            ..Default::default()
+        };
+        DiagWithInode { state, info: Some(info), ..socket(src, dst) }
+    }
+
+    #[test]
+    fn test_ports_and_booleans() {
+        let s = sock("10.0.0.1:5000", "192.168.1.1:443", 1, 20_000);
+        assert!(Filter::parse("dport == 443").unwrap().matches(&s));
+        assert!(Filter::parse("dport = 443 and sport > 1024").unwrap().matches(&s));
+        assert!(!Filter::parse("dport == 80 || sport < 1024").unwrap().matches(&s));
+        assert!(Filter::parse("not (dport == 80)").unwrap().matches(&s));
+        assert!(Filter::parse("!dport == 80 && (retrans >= 3)").unwrap().matches(&s));
+    }
+
+    #[test]
+    fn test_addresses() {
+        let s = sock("10.0.0.1:5000", "192.168.1.1:443", 1, 20_000);
+        assert!(Filter::parse("src == 10.0.0.0/8").unwrap().matches(&s));
+        assert!(Filter::parse("dst != 10.0.0.0/8").unwrap().matches(&s));
+        assert!(Filter::parse("dst == 192.168.1.1").unwrap().matches(&s));
+        assert!(Filter::parse("dst == 192.168.0.0/23").unwrap().matches(&s));
+        assert!(!Filter::parse("dst == 192.168.2.0/23").unwrap().matches(&s));
+        assert!(!Filter::parse("dst == ::1").unwrap().matches(&s));
+
+        let s6 = sock("[::1]:5000", "[2001:db8::1]:443", 1, 0);
+        assert!(Filter::parse("dst == 2001:db8::/32").unwrap().matches(&s6));
+    }
+
+    #[test]
+    fn test_state_rtt_and_strings() {
+        let s = sock("10.0.0.1:5000", "192.168.1.1:443", 1, 20_000);
+        assert!(Filter::parse("state == established").unwrap().matches(&s));
+        assert!(Filter::parse("state != TIME-WAIT").unwrap().matches(&s));
+        assert!(Filter::parse("rtt > 10 and rtt < 30ms").unwrap().matches(&s));
+        assert!(Filter::parse("rtt >= 20000us").unwrap().matches(&s));
+        assert!(!Filter::parse("rtt > 1s").unwrap().matches(&s));
+        assert!(Filter::parse("comm ~ ngi && cc == \"cubic\" && pid == 42").unwrap().matches(&s));
+        assert!(Filter::parse("comm !~ sshd").unwrap().matches(&s));
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = Filter::parse("dport == 443 and bogus > 1").unwrap_err();
+        assert_eq!((err.start, err.end), (17, 22));
+        let err = Filter::parse("src > 10.0.0.0/8").unwrap_err();
+        assert_eq!((err.start, err.end), (4, 5));
+        let err = Filter::parse("dst == 10.0.0.0/33").unwrap_err();
+        assert_eq!((err.start, err.end), (7, 18));
+        assert!(Filter::parse("(dport == 1").is_err());
+        assert!(Filter::parse("dport == 1 dport").is_err());
+        assert!(Filter::parse("state == nope").is_err());
+        assert!(Filter::parse("comm == 'open").is_err());
+        assert!(Filter::parse("   ").unwrap().is_empty());
+    }
+}
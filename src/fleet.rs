@@ -0,0 +1,312 @@
+use crate::daemon::{self, Hello, Reply, Request, PROTOCOL_VERSION};
+use crate::table::{self, Options, StatefulTable};
+use crate::tcpdiag::{DiagWithInode, SocketId};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Several agents' sockets in one table, so both ends of a connection can be
+// looked at side by side. Each agent gets a thread that follows it, and their
+// samples are merged in rounds: a round goes out once every agent that's up
+// has sent something new, or when the slowest has kept the others waiting for
+// two of its intervals. Rates come from the change between rounds, so one that
+// went out early repeats the late agents' last sample and they read as idle
+// for that round.
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// how long to wait before trying an agent that's gone away again
+const RETRY: Duration = Duration::from_secs(5);
+
+pub struct Agent {
+    pub name: String,
+    pub addr: String,
+}
+
+impl Agent {
+    // [NAME=]HOST:PORT, named after its address when there's no name.
+    pub fn parse(spec: &str) -> Result<Agent, String> {
+        let (name, addr) = spec.split_once('=').unwrap_or((spec, spec));
+        if name.is_empty() || !addr.contains(':') {
+            return Err(format!("fleet agent '{}' should be [NAME=]HOST:PORT", spec));
+        }
+        Ok(Agent { name: name.to_string(), addr: addr.to_string() })
+    }
+}
+
+pub enum Update {
+    Up(usize, Duration),
+    Sample(usize, daemon::Sample),
+    Down(usize, String),
+}
+
+fn read_hello<R: BufRead>(reader: &mut R) -> io::Result<Hello> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the agent hung up"));
+    }
+    match serde_json::from_str(&line)? {
+        Reply::Hello(hello) if hello.version == PROTOCOL_VERSION => Ok(hello),
+        Reply::Hello(hello) => Err(daemon::invalid(format!(
+            "the agent speaks protocol version {}, this tcptop speaks {}",
+            hello.version, PROTOCOL_VERSION
+        ))),
+        Reply::Error { message } => Err(daemon::invalid(message)),
+        _ => Err(daemon::invalid("expected a hello".to_string())),
+    }
+}
+
+// Hands everything agent `host` sends on to `updates` until it goes away. Only
+// comes back Ok once nobody's listening any more.
+fn follow(host: usize, agent: &Agent, token: &str, updates: &Sender<Update>) -> io::Result<()> {
+    let addr = agent.addr.to_socket_addrs()?.next().ok_or_else(|| daemon::invalid("no address".to_string()))?;
+    let mut stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    daemon::write_line(&mut stream, &Request::Auth { token: token.to_string() })?;
+    read_hello(&mut reader)?;
+    daemon::write_line(&mut stream, &Request::Follow)?;
+    let interval = Duration::from_millis(read_hello(&mut reader)?.interval_ms);
+    // a sample that's this overdue means the agent has hung
+    stream.set_read_timeout(Some((interval * 5).max(Duration::from_secs(10))))?;
+    if updates.send(Update::Up(host, interval)).is_err() {
+        return Ok(());
+    }
+    for line in reader.lines() {
+        let update = match serde_json::from_str(&line?) {
+            Ok(Reply::Sample(sample)) => Update::Sample(host, sample),
+            Ok(Reply::Error { message }) => return Err(daemon::invalid(message)),
+            _ => continue,
+        };
+        if updates.send(update).is_err() {
+            return Ok(());
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the agent hung up"))
+}
+
+struct Host {
+    latest: Option<daemon::Sample>,
+    // `latest` hasn't gone out in a round yet
+    fresh: bool,
+    interval: Duration,
+    down: Option<String>,
+}
+
+struct Merger {
+    names: Vec<String>,
+    hosts: Vec<Host>,
+    // when the first new sample of the next round came in
+    round: Option<Instant>,
+    // an agent went down, which is worth a round of its own if nothing else is coming
+    changed: bool,
+}
+
+impl Merger {
+    fn new(names: Vec<String>) -> Merger {
+        let hosts = names
+            .iter()
+            .map(|_| Host { latest: None, fresh: false, interval: Duration::ZERO, down: Some("not connected yet".to_string()) })
+            .collect();
+        Merger { names, hosts, round: None, changed: false }
+    }
+
+    fn update(&mut self, update: Update, now: Instant) {
+        match update {
+            Update::Up(i, interval) => {
+                self.hosts[i].interval = interval;
+                self.hosts[i].down = None;
+            }
+            Update::Sample(i, sample) => {
+                self.hosts[i].latest = Some(sample);
+                self.hosts[i].fresh = true;
+                self.round.get_or_insert(now);
+            }
+            Update::Down(i, why) => {
+                self.hosts[i] = Host { latest: None, fresh: false, interval: Duration::ZERO, down: Some(why) };
+                self.changed = true;
+            }
+        }
+    }
+
+    // When the round goes out whether everyone's in or not.
+    fn deadline(&self) -> Option<Instant> {
+        let slowest = self.hosts.iter().map(|host| host.interval).max().unwrap_or_default();
+        self.round.map(|start| start + (slowest * 2).max(Duration::from_secs(1)))
+    }
+
+    fn ready(&self, now: Instant) -> bool {
+        let mut reporting = self.hosts.iter().filter(|host| host.latest.is_some()).peekable();
+        if reporting.peek().is_none() {
+            return self.changed;
+        }
+        self.round.is_some() && (reporting.all(|host| host.fresh) || self.deadline().is_some_and(|at| now >= at))
+    }
+
+    fn merge(&mut self, now: Instant) -> table::Sample {
+        let mut sample = table::Sample {
+            at: SystemTime::now(),
+            taken: now,
+            snapshot: Vec::new(),
+            listeners: Vec::new(),
+            down: Vec::new(),
+        };
+        let mut newest = None;
+        for (i, (host, name)) in self.hosts.iter_mut().zip(&self.names).enumerate() {
+            if let Some(why) = &host.down {
+                sample.down.push(format!("{}: {}", name, why));
+            }
+            if let Some(latest) = &host.latest {
+                let tagged = |sock: &DiagWithInode| DiagWithInode { host: i, ..sock.clone() };
+                sample.snapshot.extend(latest.snapshot.iter().map(tagged));
+                sample.listeners.extend(latest.listeners.iter().map(tagged));
+                newest = newest.max(Some(latest.time_ms));
+            }
+            host.fresh = false;
+        }
+        if let Some(time_ms) = newest {
+            sample.at = UNIX_EPOCH + Duration::from_millis(time_ms);
+        }
+        self.round = None;
+        self.changed = false;
+        sample
+    }
+}
+
+// Starts following every agent and hands back a table that fills in as they
+// answer. Agents that can't be reached are tried again every few seconds.
+pub fn connect(agents: Vec<Agent>, token: &str, options: Options) -> StatefulTable {
+    let (updates, incoming) = mpsc::channel();
+    let names: Vec<String> = agents.iter().map(|agent| agent.name.clone()).collect();
+    for (host, agent) in agents.into_iter().enumerate() {
+        let (updates, token) = (updates.clone(), token.to_string());
+        thread::spawn(move || loop {
+            let why = match follow(host, &agent, &token, &updates) {
+                Ok(()) => return,
+                Err(err) => err.to_string(),
+            };
+            if updates.send(Update::Down(host, why)).is_err() {
+                return;
+            }
+            thread::sleep(RETRY);
+        });
+    }
+
+    let (samples, feed) = mpsc::channel();
+    let mut merger = Merger::new(names.clone());
+    thread::spawn(move || loop {
+        let wait = match merger.deadline() {
+            Some(at) => at.saturating_duration_since(Instant::now()),
+            None => Duration::from_secs(60),
+        };
+        match incoming.recv_timeout(wait) {
+            Ok(update) => merger.update(update, Instant::now()),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        if merger.ready(Instant::now()) && samples.send(merger.merge(Instant::now())).is_err() {
+            return;
+        }
+    });
+    StatefulTable::fleet(options, feed, names)
+}
+
+// Both ends of a connection as seen from either side, whatever the address
+// family of the socket that accepted it.
+fn plain(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V6(v6) => match v6.ip().to_ipv4_mapped() {
+            Some(v4) => SocketAddr::new(v4.into(), v6.port()),
+            None => addr,
+        },
+        v4 => v4,
+    }
+}
+
+// Where in `snapshot` the other end of each socket is, for those that have one
+// there. A connection over loopback never leaves its host, anything else is
+// looked for on the other hosts first.
+pub fn pair(snapshot: &[DiagWithInode]) -> HashMap<SocketId, usize> {
+    let mut by_ends: HashMap<(SocketAddr, SocketAddr), Vec<usize>> = HashMap::new();
+    for (i, sock) in snapshot.iter().enumerate() {
+        by_ends.entry((plain(sock.src), plain(sock.dst))).or_default().push(i);
+    }
+    let mut peers = HashMap::new();
+    for sock in snapshot {
+        let candidates = match by_ends.get(&(plain(sock.dst), plain(sock.src))) {
+            Some(candidates) => candidates,
+            None => continue,
+        };
+        let here = candidates.iter().find(|i| snapshot[**i].host == sock.host);
+        let elsewhere = candidates.iter().find(|i| snapshot[**i].host != sock.host);
+        let peer = match plain(sock.dst).ip().is_loopback() {
+            true => here,
+            false => elsewhere.or(here),
+        };
+        if let Some(i) = peer {
+            peers.insert(sock.id(), *i);
+        }
+    }
+    peers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tcpdiag::tests::socket;
+
+    fn on(host: usize, src: &str, dst: &str) -> DiagWithInode {
+        DiagWithInode { host, ..socket(src, dst) }
+    }
+
+    #[test]
+    fn test_pair() {
+        let snapshot = vec![
+            on(0, "10.0.0.1:5000", "10.0.0.2:443"),
+            on(1, "[::ffff:10.0.0.2]:443", "[::ffff:10.0.0.1]:5000"),
+            on(0, "127.0.0.1:6000", "127.0.0.1:6379"),
+            on(1, "127.0.0.1:6379", "127.0.0.1:6000"),
+            on(1, "127.0.0.1:6000", "127.0.0.1:6379"),
+        ];
+        let peers = pair(&snapshot);
+        assert_eq!(peers[&snapshot[0].id()], 1);
+        assert_eq!(peers[&snapshot[1].id()], 0);
+        // loopback on another host is a different connection
+        assert!(!peers.contains_key(&snapshot[2].id()));
+        assert_eq!(peers[&snapshot[3].id()], 4);
+    }
+
+    #[test]
+    fn test_rounds() {
+        let sample = |time_ms| daemon::Sample {
+            time_ms,
+            offset_ms: 0,
+            snapshot: vec![on(0, "10.0.0.1:5000", "10.0.0.2:443")],
+            listeners: Vec::new(),
+        };
+        let start = Instant::now();
+        let mut merger = Merger::new(vec!["a".to_string(), "b".to_string()]);
+        merger.update(Update::Up(0, Duration::from_secs(1)), start);
+        merger.update(Update::Up(1, Duration::from_secs(1)), start);
+        merger.update(Update::Sample(0, sample(1000)), start);
+        merger.update(Update::Sample(1, sample(2000)), start);
+        assert!(merger.ready(start));
+        let merged = merger.merge(start);
+        assert_eq!(merged.snapshot.iter().map(|sock| sock.host).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(merged.at, UNIX_EPOCH + Duration::from_secs(2));
+
+        // b is late, so the round waits for it until the deadline
+        merger.update(Update::Sample(0, sample(3000)), start);
+        assert!(!merger.ready(start + Duration::from_secs(1)));
+        assert!(merger.ready(start + Duration::from_secs(2)));
+        merger.merge(start);
+
+        merger.update(Update::Down(1, "wrong token".to_string()), start);
+        merger.update(Update::Sample(0, sample(4000)), start);
+        let merged = merger.merge(start);
+        assert_eq!(merged.snapshot.len(), 1);
+        assert_eq!(merged.down, vec!["b: wrong token".to_string()]);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::config::Config;
+use crate::table::SocketHistory;
+use crate::tcpdiag::{DiagWithInode, TCP_STATE};
+
+// How a row in the overview stands out, worst first. A socket that is closing
+// and slow only shows as the one that matters more.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Highlight {
+    Alert,
+    Closing,
+    New,
+    Normal,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Thresholds {
+    // how many refreshes a new socket stays highlighted for
+    pub new_ticks: usize,
+    pub rtt_ms: f64,
+    // share of the segments sent since the last refresh that were retransmits
+    pub retrans_percent: f64,
+    pub zero_window: bool,
+    pub backoff: u8,
+}
+
+impl Default for Thresholds {
+    fn default() -> Thresholds {
+        Thresholds {
+            new_ticks: 5,
+            rtt_ms: 250.0,
+            retrans_percent: 5.0,
+            zero_window: true,
+            backoff: 1,
+        }
+    }
+}
+
+impl Thresholds {
+    pub fn from_config(config: &Config) -> Thresholds {
+        let defaults = Thresholds::default();
+        match &config.highlight {
+            None => defaults,
+            Some(highlight) => Thresholds {
+                new_ticks: highlight.new_ticks.unwrap_or(defaults.new_ticks),
+                rtt_ms: highlight.rtt_ms.unwrap_or(defaults.rtt_ms),
+                retrans_percent: highlight.retrans_percent.unwrap_or(defaults.retrans_percent),
+                zero_window: highlight.zero_window.unwrap_or(defaults.zero_window),
+                backoff: highlight.backoff.unwrap_or(defaults.backoff),
+            },
+        }
+    }
+
+    // Works off the history's own samples rather than anything kept on the side,
+    // so a paused table highlights rows the way they were at the time.
+    pub fn classify(&self, sock: &DiagWithInode, history: &SocketHistory) -> Highlight {
+        if self.crossed(sock, history) {
+            return Highlight::Alert;
+        }
+        match TCP_STATE::from_u8(history.current.tcpi_state) {
+            TCP_STATE::FIN_WAIT1
+            | TCP_STATE::FIN_WAIT2
+            | TCP_STATE::CLOSE_WAIT
+            | TCP_STATE::LAST_ACK
+            | TCP_STATE::CLOSING
+            | TCP_STATE::CLOSE => return Highlight::Closing,
+            _ => {}
+        }
+        // the first sample is the baseline laid down when the socket turned up
+        if history.fresh && history.samples <= self.new_ticks + 1 {
+            return Highlight::New;
+        }
+        Highlight::Normal
+    }
+
+    fn crossed(&self, sock: &DiagWithInode, history: &SocketHistory) -> bool {
+        let info = &history.current;
+        if self.rtt_ms > 0.0 && info.tcpi_rtt as f64 / 1000.0 >= self.rtt_ms {
+            return true;
+        }
+        if self.backoff > 0 && info.tcpi_backoff >= self.backoff {
+            return true;
+        }
+        // the peer has stopped taking data, which older kernels don't say
+        let established = matches!(TCP_STATE::from_u8(info.tcpi_state), TCP_STATE::ESTABLISHED);
+        if self.zero_window && established && sock.has_snd_wnd() && info.tcpi_snd_wnd == 0 {
+            return true;
+        }
+        let sent = info.tcpi_data_segs_out.saturating_sub(history.previous.tcpi_data_segs_out);
+        let retransmits = history.retransmits.front().copied().unwrap_or(0);
+        self.retrans_percent > 0.0 && sent > 0 && retransmits as f64 * 100.0 / sent as f64 >= self.retrans_percent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::SocketHistory;
+    use crate::tcpdiag::tests::socket;
+    use crate::tcpdiag::TCPInfo;
+    use std::time::Instant;
+
+    fn sock() -> DiagWithInode {
+        socket("10.0.0.1:443", "10.0.0.2:50000")
+    }
+
+    fn history(info: &TCPInfo, fresh: bool, ticks: usize) -> SocketHistory {
+        let mut history = SocketHistory::new(info, fresh, Instant::now());
+        for _ in 0..ticks {
+            history.samples += 1;
+            history.sampled_at.push_front(history.sampled_at[0]);
+            history.retransmits.push_front(0);
+        }
+        history
+    }
+
+    fn established() -> TCPInfo {
+        TCPInfo { tcpi_state: 1, tcpi_snd_wnd: 65535, tcpi_rtt: 1000, ..Default::default() }
+    }
+
+    #[test]
+    fn test_new_sockets() {
+        let thresholds = Thresholds::default();
+        assert_eq!(thresholds.classify(&sock(), &history(&established(), true, 1)), Highlight::New);
+        assert_eq!(thresholds.classify(&sock(), &history(&established(), true, 5)), Highlight::New);
+        assert_eq!(thresholds.classify(&sock(), &history(&established(), true, 6)), Highlight::Normal);
+        // already open when we started
+        assert_eq!(thresholds.classify(&sock(), &history(&established(), false, 1)), Highlight::Normal);
+    }
+
+    #[test]
+    fn test_closing_sockets() {
+        let thresholds = Thresholds::default();
+        let info = TCPInfo { tcpi_state: 8, ..established() };
+        assert_eq!(thresholds.classify(&sock(), &history(&info, true, 1)), Highlight::Closing);
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let thresholds = Thresholds::default();
+        let slow = TCPInfo { tcpi_rtt: 300_000, tcpi_state: 8, ..established() };
+        assert_eq!(thresholds.classify(&sock(), &history(&slow, false, 1)), Highlight::Alert);
+        let stalled = TCPInfo { tcpi_snd_wnd: 0, ..established() };
+        assert_eq!(thresholds.classify(&sock(), &history(&stalled, false, 1)), Highlight::Alert);
+        let off = Thresholds { zero_window: false, ..thresholds };
+        assert_eq!(off.classify(&sock(), &history(&stalled, false, 1)), Highlight::Normal);
+        // a kernel from before tcpi_snd_wnd leaves it zeroed
+        let old = DiagWithInode { info_len: 192, ..sock() };
+        assert_eq!(thresholds.classify(&old, &history(&stalled, false, 1)), Highlight::Normal);
+
+        let mut lossy = history(&established(), false, 1);
+        lossy.current.tcpi_data_segs_out = 100;
+        lossy.retransmits[0] = 4;
+        assert_eq!(thresholds.classify(&sock(), &lossy), Highlight::Normal);
+        lossy.retransmits[0] = 5;
+        assert_eq!(thresholds.classify(&sock(), &lossy), Highlight::Alert);
+    }
+}
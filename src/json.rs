@@ -0,0 +1,176 @@
+use crate::fields::INFO_FIELDS;
+use crate::procs::Process;
+use crate::table::{SocketHistory, StatefulTable};
+use crate::tcpdiag::{DiagWithInode, TCPInfo, TCP_STATE};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+// Bumped whenever a field is renamed, removed or changes meaning. New fields
+// can turn up without a bump, so consumers should ignore ones they don't know.
+pub const SCHEMA_VERSION: u32 = 1;
+
+// `--output json`, every shown socket from one refresh in a single document.
+#[derive(serde::Serialize)]
+pub struct Snapshot<'a> {
+    pub version: u32,
+    #[serde(flatten)]
+    pub sample: Sample<'a>,
+    pub sockets: Vec<Socket<'a>>,
+}
+
+// `--output ndjson`, one of these per socket per refresh.
+#[derive(serde::Serialize)]
+pub struct Record<'a> {
+    pub version: u32,
+    #[serde(flatten)]
+    pub sample: Sample<'a>,
+    #[serde(flatten)]
+    pub socket: Socket<'a>,
+}
+
+// When a refresh happened. `offset_ms` is measured on the monotonic clock from
+// when tcptop started, so it keeps counting steadily if the wall clock jumps.
+#[derive(Clone, serde::Serialize)]
+pub struct Sample<'a> {
+    pub time: String,
+    pub offset_ms: u64,
+    pub namespace: Option<&'a str>,
+}
+
+#[derive(serde::Serialize)]
+pub struct Socket<'a> {
+    // the agent it came from, in a fleet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    pub family: &'static str,
+    pub src: Endpoint,
+    pub dst: Endpoint,
+    pub state: String,
+    pub inode: u32,
+    pub cc: Option<&'a str>,
+    pub process: Option<&'a Process>,
+    pub send_bps: u64,
+    pub recv_bps: u64,
+    pub loss_percent: u32,
+    // retransmits since the previous refresh
+    pub retransmits: u64,
+    pub info: Info<'a>,
+}
+
+#[derive(serde::Serialize)]
+pub struct Endpoint {
+    pub address: IpAddr,
+    pub port: u16,
+    // only there when a reverse lookup has come back with something
+    pub name: Option<String>,
+}
+
+// Every tcp_info field, named the same as the columns.
+pub struct Info<'a>(&'a TCPInfo);
+
+impl Serialize for Info<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(INFO_FIELDS.len()))?;
+        for field in INFO_FIELDS {
+            map.serialize_entry(field.name, &field.value(self.0))?;
+        }
+        map.end()
+    }
+}
+
+impl<'a> Sample<'a> {
+    pub fn of(table: &StatefulTable, started: Instant, namespace: Option<&'a str>) -> Sample<'a> {
+        let (at, taken) = table.captured_at();
+        Sample {
+            time: rfc3339(at),
+            offset_ms: taken.saturating_duration_since(started).as_millis() as u64,
+            namespace,
+        }
+    }
+}
+
+fn endpoint(table: &StatefulTable, addr: SocketAddr) -> Endpoint {
+    let name = table.resolve_name(addr.ip());
+    Endpoint {
+        address: addr.ip(),
+        port: addr.port(),
+        name: Some(name).filter(|name| *name != addr.ip().to_string()),
+    }
+}
+
+pub fn socket<'a>(table: &StatefulTable, sock: &'a DiagWithInode, history: &SocketHistory) -> Socket<'a> {
+    let info = sock.info.as_ref().unwrap();
+    Socket {
+        host: Some(table.host_name(sock.host)).filter(|host| !host.is_empty()).map(str::to_string),
+        family: match sock.src {
+            SocketAddr::V4(_) => "ipv4",
+            SocketAddr::V6(_) => "ipv6",
+        },
+        src: endpoint(table, sock.src),
+        dst: endpoint(table, sock.dst),
+        state: TCP_STATE::from_u8(sock.state).to_string(),
+        inode: sock.inode,
+        cc: sock.cc.as_deref(),
+        process: sock.process.as_ref(),
+        send_bps: history.send_bps[0],
+        recv_bps: history.recv_bps[0],
+        loss_percent: history.packet_loss[0],
+        retransmits: history.retransmits[0],
+        info: Info(info),
+    }
+}
+
+// The sockets the table is showing, in its order.
+pub fn sockets(table: &StatefulTable) -> Vec<Socket<'_>> {
+    let history = table.shown().1;
+    table.sockets
+        .iter()
+        .map(|sock| socket(table, sock, &history[&sock.id()]))
+        .collect()
+}
+
+// UTC, to the millisecond, e.g. 2026-10-18T10:53:07.123Z
+pub fn rfc3339(at: SystemTime) -> String {
+    let since = at.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs();
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // civil_from_days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year, month, day, rem / 3600, rem / 60 % 60, rem % 60, since.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(rfc3339(UNIX_EPOCH + Duration::from_millis(951_827_696_789)), "2000-02-29T12:34:56.789Z");
+        assert_eq!(rfc3339(UNIX_EPOCH + Duration::from_secs(1_792_321_987)), "2026-10-18T11:13:07.000Z");
+    }
+
+    #[test]
+    fn test_info_fields() {
+        let info = TCPInfo { tcpi_snd_cwnd: 10, tcpi_rtt: 1500, ..Default::default() };
+        let value = serde_json::to_value(Info(&info)).unwrap();
+        assert_eq!(value["snd_cwnd"], 10);
+        assert_eq!(value["rtt"], 1500);
+        assert_eq!(value.as_object().unwrap().len(), INFO_FIELDS.len());
+    }
+}
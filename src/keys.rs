@@ -0,0 +1,413 @@
+use crate::config::{Config, KeyList};
+use std::collections::HashMap;
+use termion::event::Key;
+
+// Everything a key can be bound to. The names are what the config file uses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    SelectNext,
+    SelectPrev,
+    PageDown,
+    PageUp,
+    HalfPageDown,
+    HalfPageUp,
+    FirstRow,
+    LastRow,
+    OpenDetail,
+    CloseDetail,
+    Filter,
+    Find,
+    FindNext,
+    FindPrev,
+    SortNext,
+    SortReverse,
+    Columns,
+    SparkScale,
+    CycleChart,
+    TileCharts,
+    ZoomIn,
+    ZoomOut,
+    Pause,
+    StepBack,
+    StepForward,
+    SeekBack,
+    SeekForward,
+    Slower,
+    Faster,
+    NextTab,
+    PrevTab,
+    SocketsTab,
+    ListenersTab,
+    HostsTab,
+    ProcessesTab,
+    ClosedTab,
+    ExportHistory,
+    ExportTable,
+    Help,
+}
+
+// Which screen an action does something on, so help only lists what's useful.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum View {
+    Table,
+    Detail,
+    Both,
+}
+
+impl Action {
+    pub const ALL: [Action; 40] = [
+        Action::SelectNext,
+        Action::SelectPrev,
+        Action::PageDown,
+        Action::PageUp,
+        Action::HalfPageDown,
+        Action::HalfPageUp,
+        Action::FirstRow,
+        Action::LastRow,
+        Action::OpenDetail,
+        Action::CloseDetail,
+        Action::Filter,
+        Action::Find,
+        Action::FindNext,
+        Action::FindPrev,
+        Action::SortNext,
+        Action::SortReverse,
+        Action::Columns,
+        Action::SparkScale,
+        Action::CycleChart,
+        Action::TileCharts,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::Pause,
+        Action::StepBack,
+        Action::StepForward,
+        Action::SeekBack,
+        Action::SeekForward,
+        Action::Slower,
+        Action::Faster,
+        Action::NextTab,
+        Action::PrevTab,
+        Action::SocketsTab,
+        Action::ListenersTab,
+        Action::HostsTab,
+        Action::ProcessesTab,
+        Action::ClosedTab,
+        Action::ExportHistory,
+        Action::ExportTable,
+        Action::Help,
+        Action::Quit,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        self.info().0
+    }
+
+    pub fn description(&self) -> &'static str {
+        self.info().1
+    }
+
+    pub fn view(&self) -> View {
+        self.info().2
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.iter().copied().find(|action| action.name() == name)
+    }
+
+    fn info(&self) -> (&'static str, &'static str, View) {
+        match self {
+            Action::Quit => ("quit", "quit", View::Both),
+            Action::SelectNext => ("select_next", "next row / scroll down", View::Both),
+            Action::SelectPrev => ("select_prev", "previous row / scroll up", View::Both),
+            Action::PageDown => ("page_down", "down a page", View::Both),
+            Action::PageUp => ("page_up", "up a page", View::Both),
+            Action::HalfPageDown => ("half_page_down", "down half a page", View::Both),
+            Action::HalfPageUp => ("half_page_up", "up half a page", View::Both),
+            Action::FirstRow => ("first_row", "go to the top", View::Both),
+            Action::LastRow => ("last_row", "go to the bottom", View::Both),
+            Action::OpenDetail => ("open_detail", "detail for selected row", View::Table),
+            Action::CloseDetail => ("close_detail", "back to table view", View::Detail),
+            Action::Filter => ("filter", "filter sockets", View::Table),
+            Action::Find => ("find", "find a row", View::Table),
+            Action::FindNext => ("find_next", "next match", View::Table),
+            Action::FindPrev => ("find_prev", "previous match", View::Table),
+            Action::SortNext => ("sort_next", "sort by next column", View::Table),
+            Action::SortReverse => ("sort_reverse", "reverse sort", View::Table),
+            Action::Columns => ("columns", "pick columns", View::Table),
+            Action::SparkScale => ("spark_scale", "sparklines: own / shared scale", View::Table),
+            Action::CycleChart => ("cycle_chart", "show next chart", View::Detail),
+            Action::TileCharts => ("tile_charts", "tile / single chart", View::Detail),
+            Action::ZoomIn => ("zoom_in", "zoom charts in", View::Detail),
+            Action::ZoomOut => ("zoom_out", "zoom charts out", View::Detail),
+            Action::Pause => ("pause", "pause / resume", View::Both),
+            Action::StepBack => ("step_back", "step back while paused", View::Both),
+            Action::StepForward => ("step_forward", "step forward while paused", View::Both),
+            Action::SeekBack => ("seek_back", "jump back through a replay", View::Both),
+            Action::SeekForward => ("seek_forward", "jump forward through a replay", View::Both),
+            Action::Slower => ("slower", "refresh less often / play slower", View::Both),
+            Action::Faster => ("faster", "refresh more often / play faster", View::Both),
+            Action::NextTab => ("next_tab", "next tab", View::Both),
+            Action::PrevTab => ("prev_tab", "previous tab", View::Both),
+            Action::SocketsTab => ("sockets_tab", "sockets tab", View::Both),
+            Action::ListenersTab => ("listeners_tab", "listeners tab", View::Both),
+            Action::HostsTab => ("hosts_tab", "remote hosts tab", View::Both),
+            Action::ProcessesTab => ("processes_tab", "processes tab", View::Both),
+            Action::ClosedTab => ("closed_tab", "recently closed tab", View::Both),
+            Action::ExportHistory => ("export_history", "save socket history as CSV", View::Both),
+            Action::ExportTable => ("export_table", "save table as CSV", View::Table),
+            Action::Help => ("help", "show this help", View::Both),
+        }
+    }
+}
+
+const DEFAULT: [(Action, &str); 49] = [
+    (Action::Quit, "q"),
+    (Action::SelectNext, "j"),
+    (Action::SelectNext, "Down"),
+    (Action::SelectPrev, "k"),
+    (Action::SelectPrev, "Up"),
+    (Action::PageDown, "PageDown"),
+    (Action::PageUp, "PageUp"),
+    (Action::HalfPageDown, "C-d"),
+    (Action::HalfPageUp, "C-u"),
+    (Action::FirstRow, "g"),
+    (Action::FirstRow, "Home"),
+    (Action::LastRow, "G"),
+    (Action::LastRow, "End"),
+    (Action::OpenDetail, "Enter"),
+    (Action::CloseDetail, "b"),
+    (Action::CloseDetail, "Esc"),
+    (Action::Filter, "/"),
+    (Action::Find, "f"),
+    (Action::FindNext, "n"),
+    (Action::FindPrev, "N"),
+    (Action::SortNext, "s"),
+    (Action::SortReverse, "S"),
+    (Action::Columns, "C"),
+    (Action::SparkScale, "w"),
+    (Action::CycleChart, "c"),
+    (Action::TileCharts, "t"),
+    (Action::ZoomIn, "["),
+    (Action::ZoomOut, "]"),
+    (Action::Pause, "p"),
+    (Action::StepBack, "Left"),
+    (Action::StepForward, "Right"),
+    (Action::SeekBack, "<"),
+    (Action::SeekForward, ">"),
+    (Action::Help, "?"),
+    (Action::Help, "F1"),
+    (Action::SelectNext, "C-n"),
+    (Action::SelectPrev, "C-p"),
+    (Action::Slower, "+"),
+    (Action::Slower, "="),
+    (Action::Faster, "-"),
+    (Action::NextTab, "Tab"),
+    (Action::PrevTab, "BackTab"),
+    (Action::SocketsTab, "1"),
+    (Action::ListenersTab, "2"),
+    (Action::HostsTab, "3"),
+    (Action::ProcessesTab, "4"),
+    (Action::ClosedTab, "5"),
+    (Action::ExportHistory, "e"),
+    (Action::ExportTable, "E"),
+];
+
+// Layered on top of the defaults.
+const VIM: [(Action, &str); 6] = [
+    (Action::PageDown, "C-f"),
+    (Action::PageUp, "C-b"),
+    (Action::StepBack, "h"),
+    (Action::StepForward, "l"),
+    (Action::CloseDetail, "C-o"),
+    (Action::Filter, ":"),
+];
+
+const EMACS: [(Action, &str); 12] = [
+    (Action::Quit, "C-c"),
+    (Action::PageDown, "C-v"),
+    (Action::PageUp, "M-v"),
+    (Action::FirstRow, "M-<"),
+    (Action::LastRow, "M->"),
+    (Action::CloseDetail, "C-g"),
+    (Action::Find, "C-s"),
+    (Action::FindNext, "M-n"),
+    (Action::FindPrev, "M-p"),
+    (Action::StepBack, "C-b"),
+    (Action::StepForward, "C-f"),
+    (Action::Help, "C-h"),
+];
+
+pub const PRESETS: [&str; 3] = ["default", "vim", "emacs"];
+
+pub struct Keymap {
+    bindings: HashMap<Key, Action>,
+}
+
+impl Keymap {
+    pub fn preset(name: &str) -> Result<Keymap, String> {
+        let extra: &[(Action, &str)] = match name {
+            "default" => &[],
+            "vim" => &VIM,
+            "emacs" => &EMACS,
+            _ => return Err(format!("unknown keymap '{}', expected one of {}", name, PRESETS.join(", "))),
+        };
+        let mut keymap = Keymap { bindings: HashMap::new() };
+        for (action, key) in DEFAULT.iter().chain(extra.iter()) {
+            keymap.bindings.insert(parse_key(key).unwrap(), *action);
+        }
+        Ok(keymap)
+    }
+
+    // Starts from the preset named by `keymap` and applies `[keys]` on top. An
+    // action listed there loses its preset keys, and a key taken by it stops
+    // doing whatever it did before.
+    pub fn from_config(config: &Config) -> Result<Keymap, String> {
+        let mut keymap = Keymap::preset(config.keymap.as_deref().unwrap_or("default"))?;
+        let keys = match &config.keys {
+            Some(keys) => keys,
+            None => return Ok(keymap),
+        };
+        for (name, list) in keys {
+            let action = Action::from_name(name).ok_or(format!("unknown action '{}' in [keys]", name))?;
+            let names = match list {
+                KeyList::One(key) => vec![key.clone()],
+                KeyList::Many(keys) => keys.clone(),
+            };
+            keymap.bindings.retain(|_, bound| *bound != action);
+            for key in names {
+                let key = parse_key(&key).map_err(|err| format!("{} for '{}' in [keys]", err, name))?;
+                keymap.bindings.insert(key, action);
+            }
+        }
+        Ok(keymap)
+    }
+
+    pub fn action(&self, key: Key) -> Option<Action> {
+        self.bindings.get(&key).copied()
+    }
+
+    // Every key bound to an action, sorted so the short names come first.
+    fn bound_to(&self, action: Action) -> Vec<Key> {
+        let mut keys: Vec<(String, Key)> = self.bindings
+            .iter()
+            .filter(|(_, bound)| **bound == action)
+            .map(|(key, _)| (key_name(*key), *key))
+            .collect();
+        keys.sort_by(|a, b| a.0.len().cmp(&b.0.len()).then(a.0.cmp(&b.0)));
+        keys.into_iter().map(|(_, key)| key).collect()
+    }
+
+    pub fn keys_for(&self, action: Action) -> Vec<String> {
+        self.bound_to(action).into_iter().map(key_name).collect()
+    }
+}
+
+pub fn parse_key(name: &str) -> Result<Key, String> {
+    let key = match name {
+        "Enter" => Key::Char('\n'),
+        "Space" => Key::Char(' '),
+        "Tab" => Key::Char('\t'),
+        "Esc" => Key::Esc,
+        "Backspace" => Key::Backspace,
+        "Delete" => Key::Delete,
+        "Insert" => Key::Insert,
+        "Up" => Key::Up,
+        "Down" => Key::Down,
+        "Left" => Key::Left,
+        "Right" => Key::Right,
+        "Home" => Key::Home,
+        "End" => Key::End,
+        "PageUp" => Key::PageUp,
+        "PageDown" => Key::PageDown,
+        "BackTab" => Key::BackTab,
+        _ => {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next(), chars.next(), chars.next()) {
+                (Some(c), None, _, _) => Key::Char(c),
+                (Some('C'), Some('-'), Some(c), None) => Key::Ctrl(c),
+                (Some('M'), Some('-'), Some(c), None) => Key::Alt(c),
+                (Some('F'), Some(_), _, _) => match name[1..].parse::<u8>() {
+                    Ok(n) if (1..=12).contains(&n) => Key::F(n),
+                    _ => return Err(format!("unknown key '{}'", name)),
+                },
+                _ => return Err(format!("unknown key '{}'", name)),
+            }
+        }
+    };
+    Ok(key)
+}
+
+pub fn key_name(key: Key) -> String {
+    match key {
+        Key::Char('\n') => "Enter".to_string(),
+        Key::Char(' ') => "Space".to_string(),
+        Key::Char('\t') => "Tab".to_string(),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("C-{}", c),
+        Key::Alt(c) => format!("M-{}", c),
+        Key::F(n) => format!("F{}", n),
+        Key::Esc => "Esc".to_string(),
+        Key::Backspace => "Backspace".to_string(),
+        Key::Delete => "Delete".to_string(),
+        Key::Insert => "Insert".to_string(),
+        Key::Up => "Up".to_string(),
+        Key::Down => "Down".to_string(),
+        Key::Left => "Left".to_string(),
+        Key::Right => "Right".to_string(),
+        Key::Home => "Home".to_string(),
+        Key::End => "End".to_string(),
+        Key::PageUp => "PageUp".to_string(),
+        Key::PageDown => "PageDown".to_string(),
+        Key::BackTab => "BackTab".to_string(),
+        _ => "?".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key() {
+        for name in ["j", "Enter", "C-d", "M-v", "F1", "F12", "PageDown", "?", "Space", "<"].iter() {
+            assert_eq!(key_name(parse_key(name).unwrap()), *name);
+        }
+        assert!(parse_key("F13").is_err());
+        assert!(parse_key("Ctrl-d").is_err());
+        assert!(parse_key("").is_err());
+    }
+
+    #[test]
+    fn test_presets() {
+        let default = Keymap::preset("default").unwrap();
+        assert_eq!(default.action(Key::Char('j')), Some(Action::SelectNext));
+        assert_eq!(default.action(Key::Ctrl('v')), None);
+        let emacs = Keymap::preset("emacs").unwrap();
+        assert_eq!(emacs.action(Key::Ctrl('v')), Some(Action::PageDown));
+        assert_eq!(emacs.action(Key::Char('j')), Some(Action::SelectNext));
+        assert_eq!(Keymap::preset("vim").unwrap().action(Key::Char('h')), Some(Action::StepBack));
+        assert!(Keymap::preset("nano").is_err());
+    }
+
+    #[test]
+    fn test_rebind() {
+        let config: Config = toml::from_str(
+            r#"
+            keymap = "vim"
+            [keys]
+            open_detail = ["o", "Enter"]
+            quit = "x"
+            "#,
+        )
+        .unwrap();
+        let keymap = Keymap::from_config(&config).unwrap();
+        assert_eq!(keymap.action(Key::Char('o')), Some(Action::OpenDetail));
+        assert_eq!(keymap.action(Key::Char('x')), Some(Action::Quit));
+        assert_eq!(keymap.action(Key::Char('q')), None);
+        assert_eq!(keymap.keys_for(Action::OpenDetail), vec!["o", "Enter"]);
+
+        let bad: Config = toml::from_str("[keys]\nopen_detial = \"o\"").unwrap();
+        assert!(Keymap::from_config(&bad).is_err());
+    }
+}
@@ -2,19 +2,55 @@ mod event;
 mod tcpdiag;
 mod cli;
 mod table;
+mod filter;
+mod procs;
+mod fields;
+mod columns;
+mod config;
+mod picker;
+mod charts;
+mod keys;
+mod theme;
+mod highlight;
+mod views;
+mod batch;
+mod json;
+mod export;
+mod metrics;
+mod prometheus;
+mod push;
+mod otlp;
+mod daemon;
+mod fleet;
+mod recording;
 
+use clap::Parser;
+use batch::{Batch, Format};
 use cli::CLI;
+use config::{AgentConfig, Config, DaemonConfig, FleetConfig, InfluxConfig, OtlpConfig, PrometheusConfig, StatsdConfig};
 use event::{Event, Events};
-use std::{error::Error, io};
-use termion::{event::Key, input::MouseTerminal, raw::IntoRawMode, screen::AlternateScreen};
-use std::panic::{self, PanicInfo};
+use keys::{Action, Keymap};
+use theme::Theme;
+use highlight::Thresholds;
+use metrics::Aggregation;
+use otlp::Otlp;
+use push::{Push, Sink};
+use views::Tab;
+use filter::Filter;
+use table::{Options, StatefulTable};
+use tcpdiag::Protocol;
+use std::{error::Error, io, process, thread, time::Duration};
+use std::sync::{Arc, Mutex};
+use std::path::{Path, PathBuf};
+use termion::{input::MouseTerminal, raw::IntoRawMode, screen::AlternateScreen};
+use std::panic::{self, PanicHookInfo};
 use backtrace::Backtrace;
 use ratatui::{
     backend::TermionBackend,
     Terminal,
 };
 
-fn panic_hook(info: &PanicInfo<'_>) {
+fn panic_hook(info: &PanicHookInfo<'_>) {
     if cfg!(debug_assertions) {
         let location = info.location().unwrap();
 
@@ -38,7 +74,432 @@ fn panic_hook(info: &PanicInfo<'_>) {
     }
 }
 
+// Every flag has a matching config setting and TCPTOP_ variable, see config.rs.
+#[derive(Parser)]
+#[command(name = "tcptop", version, about = "Watch TCP sockets like top")]
+struct Args {
+    /// Only show sockets matching this expression, e.g. 'dport == 443'
+    #[arg(short, long, value_name = "EXPRESSION")]
+    filter: Option<String>,
+    /// How often to refresh, from 100ms to 60s
+    #[arg(short, long, value_name = "DURATION")]
+    interval: Option<String>,
+    /// How much history to keep for the charts and pausing, from 10s to 10m, or 24h as a daemon or agent
+    #[arg(long, value_name = "DURATION")]
+    retention: Option<String>,
+    /// Column to sort by, with :desc for largest first
+    #[arg(short, long, value_name = "COLUMN[:desc]")]
+    sort: Option<String>,
+    /// Columns to show, in order
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',')]
+    columns: Option<Vec<String>>,
+    /// Look up host names for addresses
+    #[arg(long, overrides_with = "no_dns")]
+    dns: bool,
+    /// Show addresses as they are
+    #[arg(long, overrides_with = "dns")]
+    no_dns: bool,
+    /// Network namespace, by name or path
+    #[arg(short, long, value_name = "NAME")]
+    namespace: Option<String>,
+    /// ipv4, ipv6 or all
+    #[arg(long, value_name = "PROTOCOL")]
+    protocol: Option<String>,
+    /// Colour theme
+    #[arg(long, value_name = "NAME")]
+    theme: Option<String>,
+    /// Key binding preset: default, vim or emacs
+    #[arg(long, value_name = "NAME")]
+    keymap: Option<String>,
+    /// tui, or text, json, ndjson or csv to print to stdout instead
+    #[arg(long, value_name = "MODE")]
+    output: Option<String>,
+    /// Print the table to stdout every interval, the same as --output text
+    #[arg(short, long)]
+    batch: bool,
+    /// How many times to print the table outside the tui, 0 for no limit
+    #[arg(long, value_name = "N")]
+    iterations: Option<u64>,
+    /// Run headless, serving Prometheus metrics at http://ADDR/metrics
+    #[arg(long, value_name = "ADDR")]
+    prometheus_listen: Option<String>,
+    /// Run headless, pushing InfluxDB line protocol to udp://, tcp:// or file://
+    #[arg(long, value_name = "TARGET")]
+    influx_target: Option<String>,
+    /// Run headless, pushing StatsD metrics to udp://, tcp:// or file://
+    #[arg(long, value_name = "TARGET")]
+    statsd_target: Option<String>,
+    /// Run headless, sending OTLP metrics to this http:// collector endpoint
+    #[arg(long, value_name = "URL")]
+    otlp_endpoint: Option<String>,
+    /// Run headless, keeping history for TUIs to attach to over this unix socket
+    #[arg(long, value_name = "PATH")]
+    daemon_listen: Option<String>,
+    /// Show what a daemon is collecting rather than collecting here
+    #[arg(long, value_name = "PATH")]
+    attach: Option<String>,
+    /// Run headless, serving fleets over TCP on this address; needs [agent] token
+    #[arg(long, value_name = "ADDR")]
+    agent_listen: Option<String>,
+    /// Show these agents' sockets in one table; needs [fleet] token
+    #[arg(long, value_name = "[NAME=]HOST:PORT,...", value_delimiter = ',')]
+    fleet: Option<Vec<String>>,
+    /// Write every refresh to this file, adding to it if it's a recording already
+    #[arg(long, value_name = "PATH")]
+    record: Option<String>,
+    /// Play back a file written with --record instead of collecting
+    #[arg(long, value_name = "PATH")]
+    replay: Option<String>,
+    /// Use this file instead of ~/.config/tcptop/config.toml
+    #[arg(short, long, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Show the settings that would be used and exit
+    #[arg(long)]
+    print_config: bool,
+}
+
+impl Args {
+    // The top layer of settings, over the config files and environment.
+    fn overrides(&self) -> Config {
+        Config {
+            interval: self.interval.clone(),
+            retention: self.retention.clone(),
+            filter: self.filter.clone(),
+            sort: self.sort.clone(),
+            columns: self.columns.clone(),
+            dns: match (self.dns, self.no_dns) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            },
+            namespace: self.namespace.clone(),
+            protocol: self.protocol.clone(),
+            output: match self.batch {
+                true => Some("text".to_string()),
+                false => self.output.clone(),
+            },
+            iterations: self.iterations,
+            keymap: self.keymap.clone(),
+            theme: self.theme.clone(),
+            prometheus: self.prometheus_listen.clone().map(|listen| PrometheusConfig { listen: Some(listen) }),
+            influx: self.influx_target.clone().map(|target| InfluxConfig { target: Some(target), ..Default::default() }),
+            statsd: self.statsd_target.clone().map(|target| StatsdConfig { target: Some(target), ..Default::default() }),
+            otlp: self.otlp_endpoint.clone().map(|endpoint| OtlpConfig { endpoint: Some(endpoint), ..Default::default() }),
+            daemon: self.daemon_listen.clone().map(|listen| DaemonConfig { listen: Some(listen) }),
+            attach: self.attach.clone(),
+            record: self.record.clone(),
+            replay: self.replay.clone(),
+            agent: self.agent_listen.clone().map(|listen| AgentConfig { listen: Some(listen), ..Default::default() }),
+            fleet: self.fleet.clone().map(|agents| FleetConfig { agents: Some(agents), ..Default::default() }),
+            ..Default::default()
+        }
+    }
+}
+
+fn print_config(config: &Config, user: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let user = user.map(Path::to_path_buf).or_else(config::config_path);
+    println!("# defaults < {} < {} < TCPTOP_* < flags",
+        config::SYSTEM_CONFIG,
+        user.as_ref().map(|path| path.display().to_string()).unwrap_or_else(|| "(no user config)".to_string()));
+    print!("{}", toml::to_string(&config.redacted()?)?);
+    Ok(())
+}
+
+fn retention(text: &str, max: Duration) -> Result<Duration, String> {
+    let retention = event::parse_duration(text)?;
+    if retention < table::MIN_RETENTION || retention > max {
+        return Err(format!("retention has to be between {} and {}",
+            event::format_interval(table::MIN_RETENTION), event::format_interval(max)));
+    }
+    Ok(retention)
+}
+
+enum Output {
+    Tui,
+    Batch(Format),
+}
+
+// Everything that's read from the config before the terminal is taken over, so
+// a mistake gets reported on a normal screen.
+struct Settings {
+    interval: Duration,
+    output: Output,
+    filter: Filter,
+    options: Options,
+    keymap: Keymap,
+    theme: Theme,
+    aggregation: Aggregation,
+    pushes: Vec<Push>,
+    otlp: Option<Otlp>,
+    daemon: Option<String>,
+    // where to listen and the token to expect
+    agent: Option<(String, String)>,
+    // the agents and the token they expect
+    fleet: Option<(Vec<fleet::Agent>, String)>,
+}
+
+fn settings(config: &Config) -> Result<Settings, String> {
+    let interval = event::parse_interval(config.interval.as_deref().unwrap_or("1s"))
+        .map_err(|err| format!("invalid interval: {}", err))?;
+    let daemon = config.daemon.as_ref().and_then(|daemon| daemon.listen.clone());
+    let agent = match config.agent.as_ref().map(|agent| (&agent.listen, &agent.token)) {
+        Some((Some(listen), Some(token))) if !token.is_empty() => Some((listen.clone(), token.clone())),
+        Some((Some(_), _)) => return Err("an agent needs a token, set [agent] token or TCPTOP_AGENT_TOKEN".to_string()),
+        _ => None,
+    };
+    let fleet = match config.fleet.as_ref().map(|fleet| (&fleet.agents, &fleet.token)) {
+        Some((Some(agents), token)) if !agents.is_empty() => {
+            let agents = agents.iter().map(|agent| fleet::Agent::parse(agent)).collect::<Result<_, _>>()?;
+            match token {
+                Some(token) if !token.is_empty() => Some((agents, token.clone())),
+                _ => return Err("a fleet needs the agents' token, set [fleet] token or TCPTOP_FLEET_TOKEN".to_string()),
+            }
+        }
+        _ => None,
+    };
+    if (daemon.is_some() || agent.is_some()) && (config.attach.is_some() || fleet.is_some()) {
+        return Err("a daemon or agent collects its own sockets, it can't show another's".to_string());
+    }
+    if config.attach.is_some() && fleet.is_some() {
+        return Err("attach and fleet can't be used together".to_string());
+    }
+    if config.replay.is_some() && (config.attach.is_some() || fleet.is_some() || daemon.is_some() || agent.is_some()) {
+        return Err("a replay shows a recording, it can't attach, join a fleet or serve anyone".to_string());
+    }
+    if config.replay.is_some() && config.record.is_some() {
+        return Err("a replay can't be recorded again".to_string());
+    }
+    let max_retention = match daemon.is_some() || agent.is_some() {
+        true => table::MAX_DAEMON_RETENTION,
+        false => table::MAX_RETENTION,
+    };
+    let retention = retention(config.retention.as_deref().unwrap_or("30s"), max_retention)
+        .map_err(|err| format!("invalid retention: {}", err))?;
+    let expression = config.filter.as_deref().unwrap_or("");
+    let filter = Filter::parse(expression)
+        .map_err(|err| format!("invalid filter '{}': {}", expression, err))?;
+    let protocol = Protocol::parse(config.protocol.as_deref().unwrap_or("all"))?;
+    let output = match config.output.as_deref().unwrap_or("tui") {
+        "tui" => Output::Tui,
+        "text" => Output::Batch(Format::Text),
+        "json" => Output::Batch(Format::Json),
+        "ndjson" => Output::Batch(Format::Ndjson),
+        "csv" => Output::Batch(Format::Csv),
+        other => return Err(format!("unknown output mode '{}', expected tui, text, json, ndjson or csv", other)),
+    };
+    let pushes = push::configured(config.influx.as_ref(), config.statsd.as_ref())?;
+    let otlp = Otlp::from_config(config.otlp.as_ref())?;
+    let exporting = config.prometheus.as_ref().is_some_and(|prometheus| prometheus.listen.is_some())
+        || !pushes.is_empty()
+        || otlp.is_some();
+    if config.replay.is_some() && (exporting || !matches!(output, Output::Tui)) {
+        return Err("a replay only plays in the tui".to_string());
+    }
+    // those run headless until killed, and the table would never get printed
+    if !matches!(output, Output::Tui) && (exporting || daemon.is_some() || agent.is_some()) {
+        return Err("--output and --batch print the table, they can't be used while exporting metrics or serving as a daemon or agent".to_string());
+    }
+    Ok(Settings {
+        interval,
+        output,
+        filter,
+        options: Options { retention, dns: config.dns.unwrap_or(true), protocol },
+        keymap: Keymap::from_config(config)?,
+        theme: Theme::from_config(config)?,
+        aggregation: Aggregation::from_config(config.metrics.as_ref())?,
+        pushes,
+        otlp,
+        daemon,
+        agent,
+        fleet,
+    })
+}
+
+// Where the headless loop hands each refresh.
+struct Exporters {
+    server: Option<prometheus::Server>,
+    sinks: Vec<Sink>,
+    otlp: Option<otlp::Exporter>,
+    daemon: Option<daemon::Daemon>,
+}
+
+// Runs without a terminal until killed, handing every refresh to the Prometheus
+// server, the OTLP exporter, whichever pushes are on and the daemon's
+// subscribers. A push that fails is tried again on its next flush.
+fn export(table: &Mutex<StatefulTable>, aggregation: &Aggregation, interval: Duration, mut exporters: Exporters) -> ! {
+    if let Some(server) = &exporters.server {
+        let table = table.lock().unwrap();
+        server.publish(&aggregation.collect(&table.sockets, table.shown().1));
+    }
+    loop {
+        thread::sleep(interval);
+        let (metrics, at) = {
+            let mut table = table.lock().unwrap();
+            table.refresh();
+            if let Some(err) = table.record_error.take() {
+                eprintln!("tcptop: recording stopped: {}", err);
+            }
+            if let Some(daemon) = &exporters.daemon {
+                daemon.publish(&table);
+            }
+            (aggregation.collect(&table.sockets, table.shown().1), table.captured_at())
+        };
+        if let Some(server) = &exporters.server {
+            server.publish(&metrics);
+        }
+        for sink in &mut exporters.sinks {
+            if let Err(err) = sink.flush(&metrics, at) {
+                eprintln!("tcptop: {} to {}: {}", sink.push.protocol.name(), sink.push.target, err);
+            }
+        }
+        if let Some(exporter) = &mut exporters.otlp {
+            if let Err(err) = exporter.flush(&metrics, at) {
+                eprintln!("tcptop: otlp to {}: {}", exporter.otlp, err);
+            }
+        }
+    }
+}
+
+// The parts of the config that need the column registry to check.
+fn arrange(table: &mut StatefulTable, config: &Config) -> Result<(), String> {
+    let columns = match table.hosts.is_empty() {
+        true => config.columns.as_ref(),
+        false => config.fleet.as_ref().and_then(|fleet| fleet.columns.as_ref()),
+    };
+    if let Some(columns) = columns {
+        table.set_layout(columns).map_err(|column| format!("unknown column '{}' in config", column))?;
+    }
+    if let Some(sort) = &config.sort {
+        table.set_sort(sort)?;
+    }
+    table.set_thresholds(Thresholds::from_config(config));
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    let mut config = match config::load(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("tcptop: {}", err);
+            process::exit(2);
+        }
+    };
+    config.merge(args.overrides());
+    if args.print_config {
+        print_config(&config, args.config.as_deref())?;
+        return Ok(());
+    }
+    let mut settings = match settings(&config) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("tcptop: {}", err);
+            process::exit(2);
+        }
+    };
+    // before any threads start, they'd stay behind in the old namespace
+    if let Some(namespace) = &config.namespace {
+        if let Err(err) = tcpdiag::enter_namespace(namespace) {
+            eprintln!("tcptop: {}", err);
+            process::exit(2);
+        }
+    }
+
+    let mut overview = if let Some((agents, token)) = settings.fleet.take() {
+        fleet::connect(agents, &token, settings.options)
+    } else if let Some(path) = &config.attach {
+        match daemon::attach(Path::new(path), settings.options) {
+            Ok((table, interval)) => {
+                settings.interval = interval;
+                table
+            }
+            Err(err) => {
+                eprintln!("tcptop: attaching to {}: {}", path, err);
+                process::exit(1);
+            }
+        }
+    } else if let Some(path) = &config.replay {
+        match recording::Player::open(Path::new(path)) {
+            Ok(player) => StatefulTable::replay(settings.options, player),
+            Err(err) => {
+                eprintln!("tcptop: replaying {}: {}", path, err);
+                process::exit(1);
+            }
+        }
+    } else {
+        StatefulTable::new(settings.options)
+    };
+    if let Some(path) = &config.record {
+        if let Err(err) = recording::Recorder::open(Path::new(path)).and_then(|recorder| overview.record_to(recorder)) {
+            eprintln!("tcptop: recording to {}: {}", path, err);
+            process::exit(1);
+        }
+    }
+    if let Err(err) = arrange(&mut overview, &config) {
+        eprintln!("tcptop: {}", err);
+        process::exit(2);
+    }
+    overview.set_filter(settings.filter);
+    let listen = config.prometheus.as_ref().and_then(|prometheus| prometheus.listen.as_deref());
+    let headless = listen.is_some() || !settings.pushes.is_empty() || settings.otlp.is_some();
+    if headless || settings.daemon.is_some() || settings.agent.is_some() {
+        let server = match listen.map(prometheus::Server::bind).transpose() {
+            Ok(server) => server,
+            Err(err) => {
+                eprintln!("tcptop: prometheus on {}: {}", listen.unwrap_or_default(), err);
+                process::exit(1);
+            }
+        };
+        let mut sinks = Vec::new();
+        for push in settings.pushes {
+            let (protocol, target) = (push.protocol.name(), push.target.to_string());
+            match Sink::open(push) {
+                Ok(sink) => sinks.push(sink),
+                Err(err) => {
+                    eprintln!("tcptop: {} to {}: {}", protocol, target, err);
+                    process::exit(1);
+                }
+            }
+        }
+        let exporter = match settings.otlp.map(|otlp| otlp::Exporter::open(otlp, config.namespace.as_deref())).transpose() {
+            Ok(exporter) => exporter,
+            Err(err) => {
+                eprintln!("tcptop: otlp: {}", err);
+                process::exit(1);
+            }
+        };
+        let table = Arc::new(Mutex::new(overview));
+        let interval = settings.interval;
+        let daemon = match settings.daemon.is_some() || settings.agent.is_some() {
+            true => Some(daemon::Daemon::new(table.clone(), interval, config.namespace.as_deref())),
+            false => None,
+        };
+        if let (Some(daemon), Some(path)) = (&daemon, &settings.daemon) {
+            if let Err(err) = daemon.listen(Path::new(path)) {
+                eprintln!("tcptop: daemon on {}: {}", path, err);
+                process::exit(1);
+            }
+        }
+        if let (Some(daemon), Some((listen, token))) = (&daemon, settings.agent) {
+            if let Err(err) = daemon.listen_agent(&listen, token) {
+                eprintln!("tcptop: agent on {}: {}", listen, err);
+                process::exit(1);
+            }
+        }
+        let exporters = Exporters { server, sinks, otlp: exporter, daemon };
+        export(&table, &settings.aggregation, interval, exporters);
+    }
+    if let Output::Batch(format) = settings.output {
+        let batch = Batch {
+            format,
+            interval: settings.interval,
+            iterations: config.iterations.unwrap_or(0),
+            namespace: config.namespace.as_deref(),
+        };
+        batch::run(&mut overview, &batch)?;
+        return Ok(());
+    }
+
     panic::set_hook(Box::new(|info| {
         panic_hook(info);
     }));
@@ -51,37 +512,108 @@ fn main() -> Result<(), Box<dyn Error>> {
     let mut terminal = Terminal::new(backend)?;
     terminal.hide_cursor()?;
 
-    let events = Events::new();
-    let mut app = CLI::new();
+    let events = Events::with_config(event::Config { tick_rate: settings.interval });
+    let mut app = CLI::new(overview, settings.keymap, settings.theme);
+    app.interval = settings.interval;
+    if let Some(path) = &args.config {
+        app.config_path = Some(path.clone());
+    }
+
+    let mut tick_rate = settings.interval;
 
     // Input
     loop {
-        terminal.draw(|mut f| app.render(&mut f))?;
+        terminal.draw(|f| app.render(f))?;
 
-        match events.next()? {
-            Event::Input(key) => match key {
-                Key::Char('q') => {
-                    break;
-                }
-                Key::Down | Key::Char('j') => {
-                    app.overview.next();
-                }
-                Key::Up | Key::Char('k') => {
-                    app.overview.previous();
-                }
-                Key::Char('\n') => {
-                    app.enter_detail_view(); 
+        let event = events.next()?;
+        if let Event::Input(_) | Event::Mouse(_) = event {
+            app.clear_status();
+        }
+        match event {
+            Event::Input(key) if app.filter_prompt_active() => {
+                app.on_filter_key(key);
+            }
+            Event::Input(key) if app.help_active() => {
+                app.on_help_key(key);
+            }
+            Event::Input(key) if app.find_prompt_active() => {
+                app.on_find_key(key);
+            }
+            Event::Input(key) if app.column_picker_active() => {
+                app.on_column_picker_key(key);
+            }
+            Event::Input(key) => {
+                if let Some(action) = app.keymap.action(key) {
+                    if !perform(&mut app, action) {
+                        break;
+                    }
                 }
-                Key::Char('b') => {
-                    app.exit_detail_view(); 
+            }
+            Event::Mouse(mouse) => {
+                // clicks on the help bar come back as the action they stand for
+                if let Some(action) = app.on_mouse(mouse) {
+                    if !perform(&mut app, action) {
+                        break;
+                    }
                 }
-                _ => {}
-            },
+            }
             Event::Tick => {
                 app.on_tick();
             }
         };
+
+        if app.interval != tick_rate {
+            tick_rate = app.interval;
+            events.set_tick_rate(tick_rate);
+        }
     }
 
     Ok(())
 }
+
+// Returns false when it's time to quit.
+fn perform(app: &mut CLI, action: Action) -> bool {
+    match action {
+        Action::Quit => return false,
+        Action::SelectNext => app.move_down(),
+        Action::SelectPrev => app.move_up(),
+        Action::PageDown => app.page_down(),
+        Action::PageUp => app.page_up(),
+        Action::HalfPageDown => app.half_page_down(),
+        Action::HalfPageUp => app.half_page_up(),
+        Action::FirstRow => app.go_top(),
+        Action::LastRow => app.go_bottom(),
+        Action::OpenDetail => app.enter_detail_view(),
+        Action::CloseDetail => app.exit_detail_view(),
+        Action::Filter => app.open_filter_prompt(),
+        Action::Find => app.open_find_prompt(),
+        Action::FindNext => app.find_next(true),
+        Action::FindPrev => app.find_next(false),
+        Action::SortNext => app.sort_next(),
+        Action::SortReverse => app.sort_reverse(),
+        Action::Columns => app.open_column_picker(),
+        Action::SparkScale => app.toggle_spark_scale(),
+        Action::CycleChart => app.cycle_chart(),
+        Action::TileCharts => app.toggle_chart_tiling(),
+        Action::ZoomIn => app.zoom_in_charts(),
+        Action::ZoomOut => app.zoom_out_charts(),
+        Action::Pause => app.toggle_pause(),
+        Action::StepBack => app.step_back(),
+        Action::StepForward => app.step_forward(),
+        Action::SeekBack => app.seek_back(),
+        Action::SeekForward => app.seek_forward(),
+        Action::Slower => app.slower(),
+        Action::Faster => app.faster(),
+        Action::NextTab => app.next_tab(),
+        Action::PrevTab => app.previous_tab(),
+        Action::SocketsTab => app.switch_tab(Tab::Sockets),
+        Action::ListenersTab => app.switch_tab(Tab::Listeners),
+        Action::HostsTab => app.switch_tab(Tab::Hosts),
+        Action::ProcessesTab => app.switch_tab(Tab::Processes),
+        Action::ClosedTab => app.switch_tab(Tab::Closed),
+        Action::ExportHistory => app.export_history(),
+        Action::ExportTable => app.export_table(),
+        Action::Help => app.open_help(),
+    }
+    true
+}
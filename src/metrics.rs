@@ -0,0 +1,297 @@
+use crate::config::MetricsConfig;
+use crate::table::SocketHistory;
+use crate::tcpdiag::{DiagWithInode, SocketId, TCP_STATE};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+// Sockets boiled down to numbers for the exporters. A node can have tens of
+// thousands of sockets and each one would be a new time series, so they're
+// grouped by a handful of chosen labels and only the busiest groups are kept.
+
+// What a socket series can be labelled with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Label {
+    Src,
+    SrcPort,
+    Dst,
+    DstPort,
+    State,
+    Pid,
+    Comm,
+    Cc,
+}
+
+impl Label {
+    pub const ALL: [Label; 8] = [
+        Label::Src,
+        Label::SrcPort,
+        Label::Dst,
+        Label::DstPort,
+        Label::State,
+        Label::Pid,
+        Label::Comm,
+        Label::Cc,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Label::Src => "src",
+            Label::SrcPort => "src_port",
+            Label::Dst => "dst",
+            Label::DstPort => "dst_port",
+            Label::State => "state",
+            Label::Pid => "pid",
+            Label::Comm => "comm",
+            Label::Cc => "cc",
+        }
+    }
+
+    fn value(self, sock: &DiagWithInode) -> String {
+        match self {
+            Label::Src => sock.src.ip().to_string(),
+            Label::SrcPort => sock.src.port().to_string(),
+            Label::Dst => sock.dst.ip().to_string(),
+            Label::DstPort => sock.dst.port().to_string(),
+            Label::State => TCP_STATE::from_u8(sock.state).to_string(),
+            Label::Pid => sock.process.as_ref().map(|p| p.pid.to_string()).unwrap_or_default(),
+            Label::Comm => sock.process.as_ref().map(|p| p.comm.clone()).unwrap_or_default(),
+            Label::Cc => sock.cc.clone().unwrap_or_default(),
+        }
+    }
+}
+
+// What decides which groups survive the cut.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rank {
+    // send plus receive rate
+    Rate,
+    Rtt,
+    Retransmits,
+}
+
+// Everything we report for a group of sockets. Bytes and retransmits are the
+// kernel's running totals added up, but only over the sockets open right now,
+// so they go down when one closes; the rest are readings.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Values {
+    pub sockets: u64,
+    // mean over the group
+    pub rtt_seconds: f64,
+    pub cwnd: u64,
+    pub sent_bytes: u64,
+    pub received_bytes: u64,
+    pub retransmits: u64,
+    pub send_rate: u64,
+    pub receive_rate: u64,
+}
+
+impl Values {
+    fn add(&mut self, sock: &DiagWithInode, history: &SocketHistory) {
+        let info = sock.info.as_ref().unwrap();
+        let rtt = info.tcpi_rtt as f64 / 1_000_000.0;
+        self.rtt_seconds += (rtt - self.rtt_seconds) / (self.sockets + 1) as f64;
+        self.sockets += 1;
+        self.cwnd += info.tcpi_snd_cwnd as u64;
+        self.sent_bytes += info.tcpi_bytes_sent;
+        self.received_bytes += info.tcpi_bytes_received;
+        self.retransmits += info.tcpi_total_retrans as u64;
+        self.send_rate += history.send_bps[0];
+        self.receive_rate += history.recv_bps[0];
+    }
+
+    fn rank(&self, rank: Rank) -> f64 {
+        match rank {
+            Rank::Rate => (self.send_rate + self.receive_rate) as f64,
+            Rank::Rtt => self.rtt_seconds,
+            Rank::Retransmits => self.retransmits as f64,
+        }
+    }
+}
+
+// How each of `Values` is reported, for the exporters to name and type them.
+pub struct Field {
+    pub name: &'static str,
+    // a running total rather than a reading, though not one that only goes up
+    pub counter: bool,
+    pub help: &'static str,
+    pub get: fn(&Values) -> f64,
+}
+
+pub const FIELDS: [Field; 8] = [
+    Field { name: "sockets", counter: false, help: "Sockets in the series.", get: |v| v.sockets as f64 },
+    Field { name: "rtt_seconds", counter: false, help: "Mean smoothed round trip time.", get: |v| v.rtt_seconds },
+    Field { name: "cwnd_segments", counter: false, help: "Congestion windows added up.", get: |v| v.cwnd as f64 },
+    Field { name: "sent_bytes", counter: true, help: "Bytes sent by the sockets open in the series.", get: |v| v.sent_bytes as f64 },
+    Field { name: "received_bytes", counter: true, help: "Bytes received by the sockets open in the series.", get: |v| v.received_bytes as f64 },
+    Field { name: "retransmits", counter: true, help: "Segments retransmitted by the sockets open in the series.", get: |v| v.retransmits as f64 },
+    Field { name: "send_bytes_per_second", counter: false, help: "Send rate over the last refresh.", get: |v| v.send_rate as f64 },
+    Field { name: "receive_bytes_per_second", counter: false, help: "Receive rate over the last refresh.", get: |v| v.receive_rate as f64 },
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Series {
+    pub labels: Vec<(&'static str, String)>,
+    pub values: Values,
+}
+
+// Sockets grouped three ways, each cut down to `max_series`.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub sockets: Vec<Series>,
+    pub hosts: Vec<Series>,
+    pub processes: Vec<Series>,
+    // every socket counted, before any cut
+    pub states: Vec<(String, u64)>,
+    // how many groups of each kind didn't make the cut
+    pub dropped: [(&'static str, usize); 3],
+}
+
+impl Metrics {
+    // The three groupings, by the name the exporters give them.
+    pub fn kinds(&self) -> [(&'static str, &[Series]); 3] {
+        [("socket", &self.sockets), ("host", &self.hosts), ("process", &self.processes)]
+    }
+}
+
+pub struct Aggregation {
+    pub labels: Vec<Label>,
+    pub max_series: usize,
+    pub rank: Rank,
+}
+
+impl Default for Aggregation {
+    fn default() -> Aggregation {
+        Aggregation {
+            labels: vec![Label::Src, Label::Dst, Label::State, Label::Comm],
+            max_series: 500,
+            rank: Rank::Rate,
+        }
+    }
+}
+
+impl Aggregation {
+    pub fn from_config(config: Option<&MetricsConfig>) -> Result<Aggregation, String> {
+        let mut aggregation = Aggregation::default();
+        let config = match config {
+            Some(config) => config,
+            None => return Ok(aggregation),
+        };
+        if let Some(labels) = &config.labels {
+            aggregation.labels = labels
+                .iter()
+                .map(|name| {
+                    Label::ALL.iter().copied().find(|label| label.name() == name).ok_or_else(|| {
+                        let names: Vec<&str> = Label::ALL.iter().map(|label| label.name()).collect();
+                        format!("unknown metrics label '{}', expected one of {}", name, names.join(", "))
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(max_series) = config.max_series {
+            aggregation.max_series = max_series;
+        }
+        aggregation.rank = match config.rank.as_deref() {
+            None | Some("rate") => Rank::Rate,
+            Some("rtt") => Rank::Rtt,
+            Some("retransmits") => Rank::Retransmits,
+            Some(other) => return Err(format!("unknown metrics rank '{}', expected rate, rtt or retransmits", other)),
+        };
+        Ok(aggregation)
+    }
+
+    pub fn collect(&self, sockets: &[DiagWithInode], history: &HashMap<SocketId, Arc<SocketHistory>>) -> Metrics {
+        let mut by_labels: BTreeMap<Vec<String>, Values> = BTreeMap::new();
+        let mut by_host: BTreeMap<Vec<String>, Values> = BTreeMap::new();
+        let mut by_process: BTreeMap<Vec<String>, Values> = BTreeMap::new();
+        let mut states: BTreeMap<String, u64> = BTreeMap::new();
+        for sock in sockets {
+            let history = match history.get(&sock.id()) {
+                Some(history) => history,
+                None => continue,
+            };
+            let labels = self.labels.iter().map(|label| label.value(sock)).collect();
+            by_labels.entry(labels).or_default().add(sock, history);
+            by_host.entry(vec![Label::Dst.value(sock)]).or_default().add(sock, history);
+            if sock.process.is_some() {
+                by_process
+                    .entry(vec![Label::Pid.value(sock), Label::Comm.value(sock)])
+                    .or_default()
+                    .add(sock, history);
+            }
+            *states.entry(Label::State.value(sock)).or_default() += 1;
+        }
+
+        let names: Vec<&'static str> = self.labels.iter().map(|label| label.name()).collect();
+        let (sockets, dropped_sockets) = self.top(by_labels, &names);
+        let (hosts, dropped_hosts) = self.top(by_host, &["host"]);
+        let (processes, dropped_processes) = self.top(by_process, &["pid", "comm"]);
+        Metrics {
+            sockets,
+            hosts,
+            processes,
+            states: states.into_iter().collect(),
+            dropped: [("socket", dropped_sockets), ("host", dropped_hosts), ("process", dropped_processes)],
+        }
+    }
+
+    // The `max_series` highest ranked groups, and how many were left out. A
+    // `max_series` of 0 keeps them all.
+    fn top(&self, groups: BTreeMap<Vec<String>, Values>, names: &[&'static str]) -> (Vec<Series>, usize) {
+        let mut series: Vec<Series> = groups
+            .into_iter()
+            .map(|(values, totals)| Series { labels: names.iter().copied().zip(values).collect(), values: totals })
+            .collect();
+        series.sort_by(|a, b| b.values.rank(self.rank).total_cmp(&a.values.rank(self.rank)));
+        if self.max_series == 0 {
+            return (series, 0);
+        }
+        let dropped = series.len().saturating_sub(self.max_series);
+        series.truncate(self.max_series);
+        (series, dropped)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::procs::Process;
+    use crate::tcpdiag;
+    use std::time::Instant;
+
+    // A socket and its history with `sent` bytes per second going out.
+    pub fn socket(src: &str, dst: &str, sent: u64, comm: &str) -> (DiagWithInode, SocketHistory) {
+        let sock = DiagWithInode {
+            process: Some(Process { pid: 42, comm: comm.to_string() }),
+            ..tcpdiag::tests::socket(src, dst)
+        };
+        let mut history = SocketHistory::new(sock.info.as_ref().unwrap(), false, Instant::now());
+        history.send_bps[0] = sent;
+        (sock, history)
+    }
+
+    pub fn collect(aggregation: &Aggregation, sockets: Vec<(DiagWithInode, SocketHistory)>) -> Metrics {
+        let history = sockets.iter().map(|(sock, history)| (sock.id(), Arc::new(history.clone()))).collect();
+        let sockets: Vec<DiagWithInode> = sockets.into_iter().map(|(sock, _)| sock).collect();
+        aggregation.collect(&sockets, &history)
+    }
+
+    #[test]
+    fn test_collect() {
+        let aggregation = Aggregation { labels: vec![Label::Dst, Label::Comm], max_series: 2, rank: Rank::Rate };
+        let metrics = collect(&aggregation, vec![
+            socket("10.0.0.1:5000", "10.0.0.2:443", 100, "curl"),
+            socket("10.0.0.1:5001", "10.0.0.2:443", 50, "curl"),
+            socket("10.0.0.1:5002", "10.0.0.3:443", 10, "curl"),
+            socket("10.0.0.1:5003", "10.0.0.4:443", 500, "wget"),
+        ]);
+        // the two sockets to 10.0.0.2 are one series, and the quietest host is cut
+        assert_eq!(metrics.sockets.len(), 2);
+        assert_eq!(metrics.sockets[0].labels, vec![("dst", "10.0.0.4".to_string()), ("comm", "wget".to_string())]);
+        assert_eq!(metrics.sockets[1].values.sockets, 2);
+        assert_eq!(metrics.sockets[1].values.send_rate, 150);
+        assert_eq!(metrics.sockets[1].values.rtt_seconds, 0.002);
+        assert_eq!(metrics.dropped[0], ("socket", 1));
+        assert_eq!(metrics.processes.len(), 2);
+        assert_eq!(metrics.states, vec![("ESTABLISHED".to_string(), 4)]);
+    }
+}
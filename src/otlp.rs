@@ -0,0 +1,466 @@
+use crate::config::OtlpConfig;
+use crate::event;
+use crate::metrics::{Field, Metrics, Series, FIELDS};
+use opentelemetry_proto::tonic::collector::metrics::v1::metrics_service_client::MetricsServiceClient;
+use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceRequest;
+use opentelemetry_proto::tonic::common::v1::{any_value, AnyValue, InstrumentationScope, KeyValue};
+use opentelemetry_proto::tonic::metrics::v1::{
+    metric, number_data_point, Gauge, Metric, NumberDataPoint, ResourceMetrics, ScopeMetrics,
+};
+use opentelemetry_proto::tonic::resource::v1::Resource;
+use prost::Message;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tonic::metadata::{AsciiMetadataKey, AsciiMetadataValue, MetadataMap};
+use tonic::transport::{Channel, Endpoint};
+
+// The same series as the Prometheus exporter, sent to an OpenTelemetry
+// collector over OTLP. Attributes use the semantic convention names where
+// there is one.
+
+const DEFAULT_FLUSH: Duration = Duration::from_secs(10);
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    Grpc,
+    HttpProtobuf,
+}
+
+// Where to send, checked but not connected yet.
+#[derive(Debug)]
+pub struct Otlp {
+    pub protocol: Protocol,
+    pub endpoint: String,
+    pub headers: Vec<(String, String)>,
+    // on top of service.name, host.name and the namespace
+    pub resource: Vec<(String, String)>,
+    pub flush: Duration,
+}
+
+impl fmt::Display for Otlp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.endpoint)
+    }
+}
+
+impl Otlp {
+    pub fn from_config(config: Option<&OtlpConfig>) -> Result<Option<Otlp>, String> {
+        let config = match config {
+            Some(config) => config,
+            None => return Ok(None),
+        };
+        let endpoint = match &config.endpoint {
+            Some(endpoint) => endpoint,
+            None => return Ok(None),
+        };
+        let protocol = match config.protocol.as_deref() {
+            None | Some("http/protobuf") => Protocol::HttpProtobuf,
+            Some("grpc") => Protocol::Grpc,
+            Some(other) => return Err(format!("unknown otlp protocol '{}', expected grpc or http/protobuf", other)),
+        };
+        if !endpoint.starts_with("http://") {
+            return Err(format!("otlp endpoint '{}' should start with http://, put a collector nearby for TLS", endpoint));
+        }
+        // like OTEL_EXPORTER_OTLP_ENDPOINT, a bare address gets the usual path,
+        // with or without a slash on the end
+        let endpoint = match protocol {
+            Protocol::HttpProtobuf => {
+                let bare = endpoint.trim_end_matches('/');
+                match bare.get("http://".len()..).unwrap_or_default().contains('/') {
+                    true => endpoint.clone(),
+                    false => format!("{}/v1/metrics", bare),
+                }
+            }
+            _ => endpoint.clone(),
+        };
+        let headers: Vec<(String, String)> = config.headers.iter().flatten().map(|(k, v)| (k.clone(), v.clone())).collect();
+        metadata(&headers)?;
+        Ok(Some(Otlp {
+            protocol,
+            endpoint,
+            headers,
+            resource: config.resource.iter().flatten().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            flush: match config.flush.as_deref() {
+                Some(flush) => event::parse_duration(flush).map_err(|err| format!("invalid otlp flush: {}", err))?,
+                None => DEFAULT_FLUSH,
+            },
+        }))
+    }
+}
+
+// Headers as gRPC metadata, which is pickier about what it takes.
+fn metadata(headers: &[(String, String)]) -> Result<MetadataMap, String> {
+    let mut metadata = MetadataMap::new();
+    for (key, value) in headers {
+        let key = AsciiMetadataKey::from_bytes(key.to_ascii_lowercase().as_bytes())
+            .map_err(|_| format!("invalid otlp header name '{}'", key))?;
+        let value = value
+            .parse::<AsciiMetadataValue>()
+            .map_err(|_| format!("invalid otlp header value for '{}'", key))?;
+        metadata.insert(key, value);
+    }
+    Ok(metadata)
+}
+
+fn string(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue { value: Some(any_value::Value::StringValue(value.to_string())) }),
+    }
+}
+
+fn int(key: &str, value: i64) -> KeyValue {
+    KeyValue { key: key.to_string(), value: Some(AnyValue { value: Some(any_value::Value::IntValue(value)) }) }
+}
+
+// A series label as a semantic convention attribute. Empty labels are left out.
+fn attribute(label: &str, value: &str) -> Option<KeyValue> {
+    if value.is_empty() {
+        return None;
+    }
+    Some(match label {
+        "src" => string("network.local.address", value),
+        "src_port" => int("network.local.port", value.parse().ok()?),
+        "dst" | "host" => string("network.peer.address", value),
+        "dst_port" => int("network.peer.port", value.parse().ok()?),
+        "state" => string("network.connection.state", &value.to_ascii_lowercase()),
+        "pid" => int("process.pid", value.parse().ok()?),
+        "comm" => string("process.executable.name", value),
+        "cc" => string("tcptop.congestion_control", value),
+        other => string(other, value),
+    })
+}
+
+fn attributes(labels: &[(&str, String)]) -> Vec<KeyValue> {
+    labels.iter().filter_map(|(label, value)| attribute(label, value)).collect()
+}
+
+// OpenTelemetry names leave the unit off, it goes alongside.
+fn instrument(field: &Field) -> (&'static str, &'static str) {
+    match field.name {
+        "sockets" => ("count", "{socket}"),
+        "rtt_seconds" => ("rtt", "s"),
+        "cwnd_segments" => ("cwnd", "{segment}"),
+        "sent_bytes" => ("sent", "By"),
+        "received_bytes" => ("received", "By"),
+        "retransmits" => ("retransmits", "{segment}"),
+        "send_bytes_per_second" => ("send_rate", "By/s"),
+        "receive_bytes_per_second" => ("receive_rate", "By/s"),
+        other => (other, "1"),
+    }
+}
+
+// Nanoseconds since the epoch, for when the numbers were taken and when
+// tcptop started counting.
+#[derive(Clone, Copy)]
+struct Times {
+    start: u64,
+    now: u64,
+}
+
+fn nanos(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64
+}
+
+fn gauge(name: String, unit: &str, help: &str, points: Vec<NumberDataPoint>) -> Metric {
+    Metric {
+        name,
+        description: help.to_string(),
+        unit: unit.to_string(),
+        data: Some(metric::Data::Gauge(Gauge { data_points: points })),
+        ..Default::default()
+    }
+}
+
+fn point(attributes: Vec<KeyValue>, value: number_data_point::Value, times: Times) -> NumberDataPoint {
+    NumberDataPoint {
+        attributes,
+        start_time_unix_nano: times.start,
+        time_unix_nano: times.now,
+        value: Some(value),
+        ..Default::default()
+    }
+}
+
+// A metric per field, with a data point per series, all of them gauges. The
+// running totals only cover the sockets open in a series right now and go down
+// when one closes, so they can't be monotonic sums.
+fn family(metrics: &mut Vec<Metric>, kind: &str, series: &[Series], times: Times) {
+    for field in FIELDS.iter() {
+        let (name, unit) = instrument(field);
+        let name = format!("tcptop.{}.{}", kind, name);
+        let points = series
+            .iter()
+            .map(|s| {
+                let value = (field.get)(&s.values);
+                let value = match field.counter {
+                    true => number_data_point::Value::AsInt(value as i64),
+                    false => number_data_point::Value::AsDouble(value),
+                };
+                point(attributes(&s.labels), value, times)
+            })
+            .collect();
+        metrics.push(gauge(name, unit, field.help, points));
+    }
+}
+
+fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname").map(|name| name.trim().to_string()).unwrap_or_default()
+}
+
+fn request(otlp: &Otlp, metrics: &Metrics, namespace: Option<&str>, times: Times) -> ExportMetricsServiceRequest {
+    let mut resource = vec![string("service.name", "tcptop"), string("service.version", env!("CARGO_PKG_VERSION"))];
+    let host = hostname();
+    if !host.is_empty() {
+        resource.push(string("host.name", &host));
+    }
+    if let Some(namespace) = namespace {
+        resource.push(string("tcptop.network.namespace", namespace));
+    }
+    resource.extend(otlp.resource.iter().map(|(key, value)| string(key, value)));
+
+    let mut exported = Vec::new();
+    for (kind, series) in metrics.kinds() {
+        family(&mut exported, kind, series, times);
+    }
+    let states = metrics
+        .states
+        .iter()
+        .map(|(state, count)| {
+            let attributes = attributes(&[("state", state.clone())]);
+            point(attributes, number_data_point::Value::AsInt(*count as i64), times)
+        })
+        .collect();
+    exported.push(gauge("tcptop.state.count".to_string(), "{socket}", "Sockets in each TCP state.", states));
+    let dropped = metrics
+        .dropped
+        .iter()
+        .map(|(kind, dropped)| point(vec![string("tcptop.kind", kind)], number_data_point::Value::AsInt(*dropped as i64), times))
+        .collect();
+    exported.push(gauge("tcptop.dropped_series".to_string(), "{series}", "Series left out to stay under max_series.", dropped));
+
+    ExportMetricsServiceRequest {
+        resource_metrics: vec![ResourceMetrics {
+            resource: Some(Resource { attributes: resource, ..Default::default() }),
+            scope_metrics: vec![ScopeMetrics {
+                scope: Some(InstrumentationScope {
+                    name: "tcptop".to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                    ..Default::default()
+                }),
+                metrics: exported,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    }
+}
+
+// A protobuf POST over plain HTTP/1.1, answered with a 2xx if it went in.
+fn post(endpoint: &str, headers: &[(String, String)], body: &[u8]) -> io::Result<()> {
+    let rest = &endpoint["http://".len()..];
+    let (authority, path) = match rest.find('/') {
+        Some(slash) => (&rest[..slash], &rest[slash..]),
+        None => (rest, "/v1/metrics"),
+    };
+    let addr = match authority.contains(':') {
+        true => authority.to_string(),
+        false => format!("{}:80", authority),
+    };
+    let addr = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no address for {}", authority)))?;
+    let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut head = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/x-protobuf\r\nContent-Length: {}\r\nConnection: close\r\n",
+        path, authority, body.len()
+    );
+    for (key, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", key, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+
+    let mut status = String::new();
+    BufReader::new(&stream).read_line(&mut status)?;
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(io::Error::other(format!("collector said {}", status.trim()))),
+    }
+}
+
+struct Grpc {
+    // only there to drive tonic
+    runtime: tokio::runtime::Runtime,
+    client: MetricsServiceClient<Channel>,
+    metadata: MetadataMap,
+}
+
+enum Transport {
+    Grpc(Box<Grpc>),
+    Http,
+}
+
+pub struct Exporter {
+    pub otlp: Otlp,
+    transport: Transport,
+    namespace: Option<String>,
+    started: u64,
+    flushed: Option<Instant>,
+}
+
+impl Exporter {
+    pub fn open(otlp: Otlp, namespace: Option<&str>) -> io::Result<Exporter> {
+        let transport = match otlp.protocol {
+            Protocol::Grpc => {
+                let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+                let endpoint = Endpoint::from_shared(otlp.endpoint.clone())
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?
+                    .connect_timeout(TIMEOUT)
+                    .timeout(TIMEOUT);
+                // connects when the first export goes out, and again whenever it drops
+                let channel = {
+                    let _inside = runtime.enter();
+                    endpoint.connect_lazy()
+                };
+                let metadata = metadata(&otlp.headers).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+                Transport::Grpc(Box::new(Grpc { runtime, client: MetricsServiceClient::new(channel), metadata }))
+            }
+            Protocol::HttpProtobuf => Transport::Http,
+        };
+        Ok(Exporter {
+            otlp,
+            transport,
+            namespace: namespace.map(str::to_string),
+            started: nanos(SystemTime::now()),
+            flushed: None,
+        })
+    }
+
+    // Sends `metrics` if a flush is due. `at` is when they were taken.
+    pub fn flush(&mut self, metrics: &Metrics, at: (SystemTime, Instant)) -> io::Result<()> {
+        if self.flushed.is_some_and(|flushed| at.1.saturating_duration_since(flushed) < self.otlp.flush) {
+            return Ok(());
+        }
+        self.flushed = Some(at.1);
+        let times = Times { start: self.started, now: nanos(at.0) };
+        let request = request(&self.otlp, metrics, self.namespace.as_deref(), times);
+        match &mut self.transport {
+            Transport::Grpc(grpc) => {
+                let mut call = tonic::Request::new(request);
+                *call.metadata_mut() = grpc.metadata.clone();
+                let response = grpc.runtime.block_on(grpc.client.export(call)).map_err(|status| io::Error::other(status.message().to_string()))?;
+                match response.into_inner().partial_success {
+                    Some(partial) if partial.rejected_data_points > 0 => Err(io::Error::other(format!(
+                        "collector rejected {} data points: {}",
+                        partial.rejected_data_points, partial.error_message
+                    ))),
+                    _ => Ok(()),
+                }
+            }
+            Transport::Http => post(&self.otlp.endpoint, &self.otlp.headers, &request.encode_to_vec()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::tests::{collect, socket};
+    use crate::metrics::{Aggregation, Label, Rank};
+    use std::collections::BTreeMap;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn find<'a>(metrics: &'a [Metric], name: &str) -> &'a Metric {
+        metrics.iter().find(|metric| metric.name == name).unwrap()
+    }
+
+    #[test]
+    fn test_endpoint() {
+        let endpoint = |endpoint: &str| {
+            let config = OtlpConfig { endpoint: Some(endpoint.to_string()), ..Default::default() };
+            Otlp::from_config(Some(&config)).unwrap().unwrap().endpoint
+        };
+        assert_eq!(endpoint("http://collector:4318"), "http://collector:4318/v1/metrics");
+        assert_eq!(endpoint("http://collector:4318/"), "http://collector:4318/v1/metrics");
+        assert_eq!(endpoint("http://collector:4318/custom"), "http://collector:4318/custom");
+    }
+
+    #[test]
+    fn test_http_export() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // a collector stand-in that takes one request and hands back the body
+        let collector = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut length = 0;
+            let mut line = String::new();
+            let mut head = Vec::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                head.push(line.trim().to_string());
+                line.clear();
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            (&stream).write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+            (head, body)
+        });
+
+        let config = OtlpConfig {
+            endpoint: Some(format!("http://{}", addr)),
+            headers: Some(BTreeMap::from([("Authorization".to_string(), "Bearer secret".to_string())])),
+            resource: Some(BTreeMap::from([("deployment.environment".to_string(), "test".to_string())])),
+            ..Default::default()
+        };
+        let otlp = Otlp::from_config(Some(&config)).unwrap().unwrap();
+        assert_eq!(otlp.endpoint, format!("http://{}/v1/metrics", addr));
+        let aggregation = Aggregation { labels: vec![Label::Dst, Label::DstPort, Label::Pid], max_series: 0, rank: Rank::Rate };
+        let metrics = collect(&aggregation, vec![socket("10.0.0.1:5000", "10.0.0.2:443", 100, "curl")]);
+        let mut exporter = Exporter::open(otlp, Some("blue")).unwrap();
+        exporter.flush(&metrics, (SystemTime::now(), Instant::now())).unwrap();
+
+        let (head, body) = collector.join().unwrap();
+        assert_eq!(head[0], "POST /v1/metrics HTTP/1.1");
+        assert!(head.contains(&"Authorization: Bearer secret".to_string()));
+        let request = ExportMetricsServiceRequest::decode(body.as_slice()).unwrap();
+        let resource = &request.resource_metrics[0];
+        let resource_keys: Vec<&str> = resource.resource.as_ref().unwrap().attributes.iter().map(|kv| kv.key.as_str()).collect();
+        assert!(resource_keys.contains(&"service.name"));
+        assert!(resource_keys.contains(&"tcptop.network.namespace"));
+        assert!(resource_keys.contains(&"deployment.environment"));
+
+        let exported = &resource.scope_metrics[0].metrics;
+        let rtt = find(exported, "tcptop.socket.rtt");
+        assert_eq!(rtt.unit, "s");
+        let point = match &rtt.data {
+            Some(metric::Data::Gauge(gauge)) => &gauge.data_points[0],
+            other => panic!("expected a gauge, got {:?}", other),
+        };
+        assert_eq!(point.attributes, vec![
+            string("network.peer.address", "10.0.0.2"),
+            int("network.peer.port", 443),
+            int("process.pid", 42),
+        ]);
+        assert_eq!(point.value, Some(number_data_point::Value::AsDouble(0.002)));
+        let sent = match &find(exported, "tcptop.host.sent").data {
+            Some(metric::Data::Gauge(gauge)) => gauge.data_points[0].value,
+            other => panic!("expected a gauge, got {:?}", other),
+        };
+        assert!(matches!(sent, Some(number_data_point::Value::AsInt(_))));
+    }
+}
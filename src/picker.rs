@@ -0,0 +1,131 @@
+use crate::table::StatefulTable;
+use crate::theme::Theme;
+use ratatui::backend::Backend;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::Modifier;
+use ratatui::terminal::Frame;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Clear, List, ListItem, ListState};
+use termion::event::Key;
+
+// Popup for choosing which columns the overview shows. Visible columns are
+// listed first in display order, followed by everything else in the registry.
+pub struct ColumnPicker {
+    state: ListState,
+    // the layout when the picker opened, so it's only saved if it changed
+    opened_with: Vec<usize>,
+}
+
+pub enum PickerResult {
+    Open,
+    Closed,
+}
+
+impl ColumnPicker {
+    pub fn new(table: &StatefulTable) -> ColumnPicker {
+        let mut state = ListState::default();
+        state.select(Some(0));
+        ColumnPicker { state, opened_with: table.layout.clone() }
+    }
+
+    pub fn changed(&self, table: &StatefulTable) -> bool {
+        table.layout != self.opened_with
+    }
+
+    fn entries(table: &StatefulTable) -> Vec<usize> {
+        let mut entries = table.layout.clone();
+        entries.extend((0..table.registry.len()).filter(|i| !table.layout.contains(i)));
+        entries
+    }
+
+    pub fn on_key(&mut self, key: Key, table: &mut StatefulTable) -> PickerResult {
+        let cursor = self.state.selected().unwrap_or(0);
+        let total = table.registry.len();
+        let visible = table.layout.len();
+        match key {
+            Key::Esc | Key::Char('\n') | Key::Char('C') => return PickerResult::Closed,
+            Key::Down | Key::Char('j') => self.state.select(Some((cursor + 1).min(total - 1))),
+            Key::Up | Key::Char('k') => self.state.select(Some(cursor.saturating_sub(1))),
+            Key::Char(' ') => {
+                if cursor < visible {
+                    // always leave at least one column on screen
+                    if visible > 1 {
+                        table.layout.remove(cursor);
+                    }
+                } else {
+                    let column = Self::entries(table)[cursor];
+                    table.layout.push(column);
+                    self.state.select(Some(visible));
+                }
+                table.rebuild_rows();
+            }
+            Key::Char('J') if cursor + 1 < visible => {
+                table.layout.swap(cursor, cursor + 1);
+                self.state.select(Some(cursor + 1));
+                table.rebuild_rows();
+            }
+            Key::Char('K') if cursor > 0 && cursor < visible => {
+                table.layout.swap(cursor, cursor - 1);
+                self.state.select(Some(cursor - 1));
+                table.rebuild_rows();
+            }
+            _ => {}
+        }
+        PickerResult::Open
+    }
+
+    pub fn draw<B: Backend>(&mut self, frame: &mut Frame<B>, area: Rect, table: &StatefulTable, theme: &Theme) {
+        let area = centered_rect(60, 80, area);
+        let items: Vec<ListItem> = Self::entries(table)
+            .iter()
+            .map(|i| {
+                let column = &table.registry[*i];
+                let (mark, style) = match table.layout.contains(i) {
+                    true => ("[x] ", theme.text),
+                    false => ("[ ] ", theme.muted),
+                };
+                ListItem::new(Line::from(vec![
+                    Span::styled(mark, style),
+                    Span::styled(format!("{:<18}", column.id), style.add_modifier(Modifier::BOLD)),
+                    Span::styled(column.title, style),
+                ]))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Columns: <space> toggle  <J/K> move  <ENTER> done")
+                    .title_style(theme.title),
+            )
+            .highlight_style(theme.selected)
+            .highlight_symbol(">> ");
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(list, area, &mut self.state);
+    }
+}
+
+pub fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Percentage((100 - percent_y) / 2),
+                Constraint::Percentage(percent_y),
+                Constraint::Percentage((100 - percent_y) / 2),
+            ]
+            .as_ref(),
+        )
+        .split(area);
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Percentage((100 - percent_x) / 2),
+                Constraint::Percentage(percent_x),
+                Constraint::Percentage((100 - percent_x) / 2),
+            ]
+            .as_ref(),
+        )
+        .split(vertical[1])[1]
+}
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Process {
+    pub pid: u32,
+    pub comm: String,
+}
+
+// sock_diag only gives us the socket inode, so to find out who owns a socket we
+// have to walk /proc/<pid>/fd looking for "socket:[<inode>]" links, same as
+// `ss -p` does. Processes we aren't allowed to look at are silently skipped.
+pub fn socket_owners() -> HashMap<u32, Process> {
+    let mut owners = HashMap::new();
+    let procs = match fs::read_dir("/proc") {
+        Ok(procs) => procs,
+        Err(_) => return owners,
+    };
+
+    for entry in procs.flatten() {
+        let pid = match entry.file_name().to_str().and_then(|s| s.parse::<u32>().ok()) {
+            Some(pid) => pid,
+            None => continue,
+        };
+        let fds = match fs::read_dir(entry.path().join("fd")) {
+            Ok(fds) => fds,
+            Err(_) => continue,
+        };
+        let comm = fs::read_to_string(entry.path().join("comm"))
+            .map(|s| s.trim_end().to_string())
+            .unwrap_or_default();
+
+        for fd in fds.flatten() {
+            let target = match fs::read_link(fd.path()) {
+                Ok(target) => target,
+                Err(_) => continue,
+            };
+            if let Some(inode) = target.to_str().and_then(socket_inode) {
+                owners.entry(inode).or_insert_with(|| Process { pid, comm: comm.clone() });
+            }
+        }
+    }
+    owners
+}
+
+fn socket_inode(link: &str) -> Option<u32> {
+    link.strip_prefix("socket:[")?.strip_suffix(']')?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_socket_inode() {
+        use super::socket_inode;
+
+        assert_eq!(socket_inode("socket:[12345]"), Some(12345));
+        assert_eq!(socket_inode("pipe:[12345]"), None);
+        assert_eq!(socket_inode("/dev/null"), None);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::metrics::{Metrics, Series, FIELDS};
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn labels(labels: &[(&str, String)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let pairs: Vec<String> = labels.iter().map(|(name, value)| format!("{}=\"{}\"", name, escape(value))).collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+// One metric family per field, all of them gauges. The running totals only
+// cover the sockets open in a series right now, so they drop whenever one
+// closes or the series falls out of the top N, and as counters every drop
+// would read as a reset.
+fn family(out: &mut String, kind: &str, series: &[Series]) {
+    for field in FIELDS.iter() {
+        let name = format!("tcptop_{}_{}", kind, field.name);
+        let _ = writeln!(out, "# HELP {} {}", name, field.help);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for s in series {
+            let _ = writeln!(out, "{}{} {}", name, labels(&s.labels), (field.get)(&s.values));
+        }
+    }
+}
+
+// The text exposition format, version 0.0.4.
+pub fn render(metrics: &Metrics) -> String {
+    let mut out = String::new();
+    for (kind, series) in metrics.kinds() {
+        family(&mut out, kind, series);
+    }
+
+    out.push_str("# HELP tcptop_state_sockets Sockets in each TCP state, before any series were dropped.\n");
+    out.push_str("# TYPE tcptop_state_sockets gauge\n");
+    for (state, count) in &metrics.states {
+        let _ = writeln!(out, "tcptop_state_sockets{} {}", labels(&[("state", state.clone())]), count);
+    }
+    out.push_str("# HELP tcptop_dropped_series Series left out to stay under max_series.\n");
+    out.push_str("# TYPE tcptop_dropped_series gauge\n");
+    for (kind, dropped) in &metrics.dropped {
+        let _ = writeln!(out, "tcptop_dropped_series{} {}", labels(&[("kind", kind.to_string())]), dropped);
+    }
+    out
+}
+
+fn respond(mut stream: TcpStream, page: &Mutex<String>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut request = String::new();
+    BufReader::new(&stream).read_line(&mut request)?;
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+    let (status, content_type, body) = match path {
+        "/metrics" => ("200 OK", "text/plain; version=0.0.4", page.lock().unwrap().clone()),
+        "/" => ("200 OK", "text/html", "<a href=\"/metrics\">metrics</a>\n".to_string()),
+        _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body
+    )
+}
+
+// Serves whatever was last published at /metrics, from a thread of its own.
+pub struct Server {
+    page: Arc<Mutex<String>>,
+}
+
+impl Server {
+    pub fn bind(listen: &str) -> io::Result<Server> {
+        let listener = TcpListener::bind(listen)?;
+        let page = Arc::new(Mutex::new(String::new()));
+        let server_page = page.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // a scraper hanging up early isn't our problem
+                let _ = respond(stream, &server_page);
+            }
+        });
+        Ok(Server { page })
+    }
+
+    pub fn publish(&self, metrics: &Metrics) {
+        *self.page.lock().unwrap() = render(metrics);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::tests::{collect, socket};
+    use crate::metrics::{Aggregation, Label, Rank};
+
+    #[test]
+    fn test_render() {
+        let aggregation = Aggregation { labels: vec![Label::Dst, Label::Comm], max_series: 1, rank: Rank::Rate };
+        let metrics = collect(&aggregation, vec![
+            socket("10.0.0.1:5000", "10.0.0.2:443", 100, "say \"hi\""),
+            socket("10.0.0.1:5001", "10.0.0.3:443", 10, "curl"),
+        ]);
+        let text = render(&metrics);
+        assert!(text.contains("# TYPE tcptop_socket_sent_bytes gauge\n"));
+        assert!(!text.contains("_total"));
+        assert!(text.contains("tcptop_socket_send_bytes_per_second{dst=\"10.0.0.2\",comm=\"say \\\"hi\\\"\"} 100\n"));
+        assert!(!text.contains("comm=\"curl\""));
+        assert!(text.contains("tcptop_host_rtt_seconds{host=\"10.0.0.2\"} 0.002\n"));
+        assert!(text.contains("tcptop_dropped_series{kind=\"socket\"} 1\n"));
+        assert!(text.contains("tcptop_state_sockets{state=\"ESTABLISHED\"} 2\n"));
+    }
+}
@@ -0,0 +1,387 @@
+use crate::config::{InfluxConfig, StatsdConfig};
+use crate::event;
+use crate::metrics::{Metrics, FIELDS};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Write as _};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// The same series as the Prometheus exporter, sent to Telegraf or a StatsD
+// daemon every `flush` instead of waiting to be scraped.
+
+const DEFAULT_FLUSH: Duration = Duration::from_secs(10);
+
+// Keeps datagrams under a typical MTU so they don't get fragmented.
+const DATAGRAM: usize = 1432;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    Influx,
+    Statsd,
+    Dogstatsd,
+}
+
+impl Protocol {
+    pub fn name(self) -> &'static str {
+        match self {
+            Protocol::Influx => "influx",
+            Protocol::Statsd => "statsd",
+            Protocol::Dogstatsd => "dogstatsd",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    Udp(String),
+    Tcp(String),
+    File(String),
+}
+
+impl Target {
+    fn parse(target: &str) -> Result<Target, String> {
+        if let Some(addr) = target.strip_prefix("udp://") {
+            Ok(Target::Udp(addr.to_string()))
+        } else if let Some(addr) = target.strip_prefix("tcp://") {
+            Ok(Target::Tcp(addr.to_string()))
+        } else if let Some(path) = target.strip_prefix("file://") {
+            Ok(Target::File(path.to_string()))
+        } else {
+            Err(format!("unknown target '{}', expected udp://HOST:PORT, tcp://HOST:PORT or file://PATH", target))
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::Udp(addr) => write!(f, "udp://{}", addr),
+            Target::Tcp(addr) => write!(f, "tcp://{}", addr),
+            Target::File(path) => write!(f, "file://{}", path),
+        }
+    }
+}
+
+// One configured push, checked but not connected yet.
+#[derive(Debug)]
+pub struct Push {
+    pub protocol: Protocol,
+    pub target: Target,
+    pub prefix: String,
+    pub tags: Vec<(String, String)>,
+    pub flush: Duration,
+}
+
+fn push(
+    protocol: Protocol,
+    target: &str,
+    prefix: Option<&str>,
+    tags: Option<&BTreeMap<String, String>>,
+    flush: Option<&str>,
+) -> Result<Push, String> {
+    let name = protocol.name();
+    Ok(Push {
+        protocol,
+        target: Target::parse(target).map_err(|err| format!("{}: {}", name, err))?,
+        prefix: prefix.unwrap_or("tcptop").to_string(),
+        tags: tags.into_iter().flatten().map(|(key, value)| (key.clone(), value.clone())).collect(),
+        flush: match flush {
+            Some(flush) => event::parse_duration(flush).map_err(|err| format!("invalid {} flush: {}", name, err))?,
+            None => DEFAULT_FLUSH,
+        },
+    })
+}
+
+// Every push with a target set.
+pub fn configured(influx: Option<&InfluxConfig>, statsd: Option<&StatsdConfig>) -> Result<Vec<Push>, String> {
+    let mut pushes = Vec::new();
+    if let Some((config, target)) = influx.and_then(|config| Some((config, config.target.as_deref()?))) {
+        pushes.push(push(Protocol::Influx, target, config.prefix.as_deref(), config.tags.as_ref(), config.flush.as_deref())?);
+    }
+    if let Some((config, target)) = statsd.and_then(|config| Some((config, config.target.as_deref()?))) {
+        let protocol = match config.dogstatsd {
+            Some(true) => Protocol::Dogstatsd,
+            _ if config.tags.as_ref().is_some_and(|tags| !tags.is_empty()) => {
+                return Err("statsd: tags need dogstatsd = true".to_string());
+            }
+            _ => Protocol::Statsd,
+        };
+        pushes.push(push(protocol, target, config.prefix.as_deref(), config.tags.as_ref(), config.flush.as_deref())?);
+    }
+    Ok(pushes)
+}
+
+fn resolve(addr: &str) -> io::Result<SocketAddr> {
+    addr.to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no address for {}", addr)))
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    // connected on the next flush after it drops
+    Tcp(String, Option<TcpStream>),
+    File(File),
+}
+
+pub struct Sink {
+    pub push: Push,
+    connection: Connection,
+    flushed: Option<Instant>,
+    // counter totals from the last flush, StatsD counts how much they went up
+    previous: HashMap<String, f64>,
+}
+
+impl Sink {
+    pub fn open(push: Push) -> io::Result<Sink> {
+        let connection = match &push.target {
+            Target::Udp(addr) => {
+                let addr = resolve(addr)?;
+                let socket = match addr {
+                    SocketAddr::V4(_) => UdpSocket::bind("0.0.0.0:0")?,
+                    SocketAddr::V6(_) => UdpSocket::bind("[::]:0")?,
+                };
+                socket.connect(addr)?;
+                Connection::Udp(socket)
+            }
+            Target::Tcp(addr) => Connection::Tcp(addr.clone(), None),
+            Target::File(path) => Connection::File(OpenOptions::new().create(true).append(true).open(path)?),
+        };
+        Ok(Sink { push, connection, flushed: None, previous: HashMap::new() })
+    }
+
+    // Sends `metrics` if a flush is due. `at` is when they were taken.
+    pub fn flush(&mut self, metrics: &Metrics, at: (SystemTime, Instant)) -> io::Result<()> {
+        if self.flushed.is_some_and(|flushed| at.1.saturating_duration_since(flushed) < self.push.flush) {
+            return Ok(());
+        }
+        self.flushed = Some(at.1);
+        let lines = match self.push.protocol {
+            Protocol::Influx => influx(&self.push, metrics, at.0),
+            Protocol::Statsd | Protocol::Dogstatsd => statsd(&self.push, metrics, &mut self.previous),
+        };
+        match &mut self.connection {
+            Connection::Udp(socket) => {
+                for datagram in datagrams(&lines) {
+                    socket.send(datagram.as_bytes())?;
+                }
+                Ok(())
+            }
+            Connection::Tcp(addr, stream) => {
+                if stream.is_none() {
+                    let connected = TcpStream::connect_timeout(&resolve(addr)?, Duration::from_secs(5))?;
+                    connected.set_write_timeout(Some(Duration::from_secs(5)))?;
+                    *stream = Some(connected);
+                }
+                let written = write_lines(stream.as_mut().unwrap(), &lines);
+                if written.is_err() {
+                    *stream = None;
+                }
+                written
+            }
+            Connection::File(file) => write_lines(file, &lines),
+        }
+    }
+}
+
+fn write_lines<W: Write>(out: &mut W, lines: &[String]) -> io::Result<()> {
+    let mut text = String::new();
+    for line in lines {
+        text.push_str(line);
+        text.push('\n');
+    }
+    out.write_all(text.as_bytes())?;
+    out.flush()
+}
+
+// Lines packed into as few datagrams as fit, a line too long for one goes on
+// its own.
+fn datagrams(lines: &[String]) -> Vec<String> {
+    let mut datagrams: Vec<String> = Vec::new();
+    for line in lines {
+        match datagrams.last_mut() {
+            Some(datagram) if datagram.len() + 1 + line.len() <= DATAGRAM => {
+                datagram.push('\n');
+                datagram.push_str(line);
+            }
+            _ => datagrams.push(line.clone()),
+        }
+    }
+    datagrams
+}
+
+// Backslashes in front of whatever `special` the line protocol would trip on.
+fn escape(value: &str, special: &[char]) -> String {
+    let mut out = String::new();
+    for c in value.chars() {
+        if special.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+// The configured tags then the series labels, leaving out empty ones since
+// neither protocol has a way to say so.
+fn tags<'a>(push: &'a Push, labels: &'a [(&str, String)]) -> impl Iterator<Item = (&'a str, &'a str)> {
+    push.tags
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .chain(labels.iter().map(|(key, value)| (*key, value.as_str())))
+        .filter(|(_, value)| !value.is_empty())
+}
+
+fn influx_line(push: &Push, measurement: &str, labels: &[(&str, String)], fields: &str, time: u128) -> String {
+    let mut line = escape(&format!("{}_{}", push.prefix, measurement), &[',', ' ']);
+    for (key, value) in tags(push, labels) {
+        let _ = write!(line, ",{}={}", escape(key, &[',', '=', ' ']), escape(value, &[',', '=', ' ']));
+    }
+    let _ = write!(line, " {} {}", fields, time);
+    line
+}
+
+// A line per series with every field on it, e.g.
+// tcptop_host,host=10.0.0.2 sockets=2,rtt_seconds=0.002,... 1792321987000000000
+fn influx(push: &Push, metrics: &Metrics, at: SystemTime) -> Vec<String> {
+    let time = at.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    let mut lines = Vec::new();
+    for (kind, series) in metrics.kinds() {
+        for s in series {
+            let fields: Vec<String> = FIELDS.iter().map(|field| format!("{}={}", field.name, (field.get)(&s.values))).collect();
+            lines.push(influx_line(push, kind, &s.labels, &fields.join(","), time));
+        }
+    }
+    for (state, count) in &metrics.states {
+        lines.push(influx_line(push, "state", &[("state", state.clone())], &format!("sockets={}", count), time));
+    }
+    for (kind, dropped) in &metrics.dropped {
+        lines.push(influx_line(push, "dropped", &[("kind", kind.to_string())], &format!("series={}", dropped), time));
+    }
+    lines
+}
+
+// Plain StatsD has no tags, so label values go in the name, one dotted part each.
+fn statsd_part(value: &str) -> String {
+    match value.is_empty() {
+        true => "none".to_string(),
+        false => value.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect(),
+    }
+}
+
+fn statsd_tag(value: &str) -> String {
+    value.replace([',', '|', '#', '\n'], "_")
+}
+
+fn statsd_line(push: &Push, kind: &str, labels: &[(&str, String)], name: &str, value: f64, metric_type: &str) -> String {
+    let mut line = format!("{}.{}", push.prefix, kind);
+    if push.protocol == Protocol::Statsd {
+        for (_, value) in labels {
+            line.push('.');
+            line.push_str(&statsd_part(value));
+        }
+    }
+    let _ = write!(line, ".{}:{}|{}", name, value, metric_type);
+    if push.protocol == Protocol::Dogstatsd {
+        let tags: Vec<String> = tags(push, labels)
+            .map(|(key, value)| format!("{}:{}", statsd_tag(key), statsd_tag(value)))
+            .collect();
+        if !tags.is_empty() {
+            let _ = write!(line, "|#{}", tags.join(","));
+        }
+    }
+    line
+}
+
+// Gauges as they are, counters as how much they went up since the last flush.
+fn statsd(push: &Push, metrics: &Metrics, previous: &mut HashMap<String, f64>) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut totals = HashMap::new();
+    for (kind, series) in metrics.kinds() {
+        for s in series {
+            for field in FIELDS.iter() {
+                let value = (field.get)(&s.values);
+                if !field.counter {
+                    lines.push(statsd_line(push, kind, &s.labels, field.name, value, "g"));
+                    continue;
+                }
+                let key = format!("{}.{}{:?}", kind, field.name, s.labels);
+                // a new series has nothing to count from, and one that lost a
+                // socket can go backwards
+                match previous.get(&key) {
+                    Some(before) if value >= *before => {
+                        lines.push(statsd_line(push, kind, &s.labels, field.name, value - before, "c"));
+                    }
+                    _ => {}
+                }
+                totals.insert(key, value);
+            }
+        }
+    }
+    *previous = totals;
+    for (state, count) in &metrics.states {
+        lines.push(statsd_line(push, "state", &[("state", state.clone())], "sockets", *count as f64, "g"));
+    }
+    for (kind, dropped) in &metrics.dropped {
+        lines.push(statsd_line(push, "dropped", &[("kind", kind.to_string())], "series", *dropped as f64, "g"));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::tests::{collect, socket};
+    use crate::metrics::{Aggregation, Label, Rank};
+
+    fn metrics(sent: u64) -> Metrics {
+        let aggregation = Aggregation { labels: vec![Label::Dst, Label::Comm], max_series: 0, rank: Rank::Rate };
+        let (mut sock, history) = socket("10.0.0.1:5000", "10.0.0.2:443", 100, "my app");
+        sock.info.as_mut().unwrap().tcpi_bytes_sent = sent;
+        collect(&aggregation, vec![(sock, history)])
+    }
+
+    fn configured_push(protocol: Protocol) -> Push {
+        Push {
+            protocol,
+            target: Target::Udp("127.0.0.1:0".to_string()),
+            prefix: "tcptop".to_string(),
+            tags: vec![("host".to_string(), "web 1".to_string())],
+            flush: DEFAULT_FLUSH,
+        }
+    }
+
+    #[test]
+    fn test_influx() {
+        let at = UNIX_EPOCH + Duration::from_secs(1_792_321_987);
+        let lines = influx(&configured_push(Protocol::Influx), &metrics(4000), at);
+        assert_eq!(lines[0], "tcptop_socket,host=web\\ 1,dst=10.0.0.2,comm=my\\ app sockets=1,rtt_seconds=0.002,\
+            cwnd_segments=10,sent_bytes=4000,received_bytes=0,retransmits=0,send_bytes_per_second=100,\
+            receive_bytes_per_second=0 1792321987000000000");
+        assert!(lines.contains(&"tcptop_state,host=web\\ 1,state=ESTABLISHED sockets=1 1792321987000000000".to_string()));
+    }
+
+    #[test]
+    fn test_statsd() {
+        let mut previous = HashMap::new();
+        let plain = Push { tags: Vec::new(), ..configured_push(Protocol::Statsd) };
+        let lines = statsd(&plain, &metrics(4000), &mut previous);
+        assert!(lines.contains(&"tcptop.socket.10_0_0_2.my_app.rtt_seconds:0.002|g".to_string()));
+        // nothing to count from on the first flush
+        assert!(!lines.iter().any(|line| line.contains("sent_bytes")));
+
+        let dog = configured_push(Protocol::Dogstatsd);
+        let lines = statsd(&dog, &metrics(6500), &mut previous);
+        assert!(lines.contains(&"tcptop.socket.sent_bytes:2500|c|#host:web 1,dst:10.0.0.2,comm:my app".to_string()));
+        assert!(lines.contains(&"tcptop.state.sockets:1|g|#host:web 1,state:ESTABLISHED".to_string()));
+    }
+
+    #[test]
+    fn test_datagrams() {
+        let lines = vec!["a".repeat(1000), "b".repeat(400), "c".repeat(100), "d".repeat(2000)];
+        let sizes: Vec<usize> = datagrams(&lines).iter().map(String::len).collect();
+        assert_eq!(sizes, vec![1401, 100, 2000]);
+    }
+}
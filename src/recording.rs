@@ -0,0 +1,367 @@
+use crate::table::Sample;
+use crate::tcpdiag::DiagWithInode;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// A recording is a header line and then a frame per refresh, written as they
+// come so a file cut short by a crash or a full disk still plays up to there:
+//
+//   tcptop-recording 1\n
+//   u32 length, u64 time_ms, then `length` bytes of zlib compressed
+//   {"snapshot":[...],"listeners":[...]}
+//
+// Integers are little endian and `time_ms` is since the Unix epoch. Sockets are
+// kept as the kernel reported them, the same as the daemon sends them, and rates
+// are worked out again on replay. Recording to a file that already has frames
+// adds to the end of it.
+const MAGIC: &[u8] = b"tcptop-recording 1\n";
+
+const FRAME_HEADER: u64 = 12;
+
+// Replays skip over anything quieter than this, like the gap between two runs
+// recorded into the same file.
+const SKIP_GAP: Duration = Duration::from_secs(60);
+
+// How fast a replay can go, as a multiple of how fast it was recorded.
+const SPEEDS: [f64; 9] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0];
+
+#[derive(Deserialize, Serialize)]
+struct Frame<T> {
+    snapshot: T,
+    listeners: T,
+}
+
+// Where a frame is in the file and when it was recorded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Index {
+    offset: u64,
+    length: u32,
+    time_ms: u64,
+}
+
+fn not_recording(path: &Path) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("{} isn't a tcptop recording", path.display()))
+}
+
+fn millis(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+// Reads up to `buf.len()` bytes, fewer only at the end of the file.
+fn read_full<R: Read>(input: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match input.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(read)
+}
+
+// Checks the header and finds every whole frame after it, along with where the
+// last one ends. Anything past that is a frame that never got finished. Times
+// come from the wall clock, which can step back, or from another machine when
+// runs are added to the same file, so a frame earlier than the one before is
+// taken to have come straight after it, keeping them in order for seeking.
+fn scan(file: &mut File, path: &Path) -> io::Result<(Vec<Index>, u64)> {
+    let size = file.metadata()?.len();
+    file.seek(SeekFrom::Start(0))?;
+    let mut magic = vec![0; MAGIC.len()];
+    if read_full(file, &mut magic)? != MAGIC.len() || magic != MAGIC {
+        return Err(not_recording(path));
+    }
+    let mut frames = Vec::new();
+    let mut offset = MAGIC.len() as u64;
+    let mut header = [0; FRAME_HEADER as usize];
+    while offset + FRAME_HEADER <= size {
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut header)?;
+        let length = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let mut time = [0; 8];
+        time.copy_from_slice(&header[4..]);
+        let end = offset + FRAME_HEADER + length as u64;
+        if end > size {
+            break;
+        }
+        let time_ms = u64::from_le_bytes(time).max(frames.last().map_or(0, |frame: &Index| frame.time_ms));
+        frames.push(Index { offset: offset + FRAME_HEADER, length, time_ms });
+        offset = end;
+    }
+    Ok((frames, offset))
+}
+
+pub struct Recorder {
+    file: File,
+}
+
+impl Recorder {
+    // Starts a recording at `path`, or carries on with the one that's there.
+    pub fn open(path: &Path) -> io::Result<Recorder> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        if file.metadata()?.len() == 0 {
+            file.write_all(MAGIC)?;
+        } else {
+            // a frame cut short last time would garble everything written after it
+            let (_, end) = scan(&mut file, path)?;
+            file.set_len(end)?;
+        }
+        file.seek(SeekFrom::End(0))?;
+        Ok(Recorder { file })
+    }
+
+    pub fn write(&mut self, at: SystemTime, snapshot: &[DiagWithInode], listeners: &[DiagWithInode]) -> io::Result<()> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        serde_json::to_writer(&mut encoder, &Frame { snapshot, listeners })?;
+        let body = encoder.finish()?;
+        // in one write, so a crash leaves at most one frame half done
+        let mut frame = Vec::with_capacity(FRAME_HEADER as usize + body.len());
+        frame.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&millis(at).to_le_bytes());
+        frame.extend_from_slice(&body);
+        self.file.write_all(&frame)
+    }
+}
+
+// A recording played back against the clock, for a table to take its samples from.
+pub struct Player {
+    file: File,
+    frames: Vec<Index>,
+    // the next frame to play
+    next: usize,
+    pub playing: bool,
+    speed: usize,
+    // the recording's time and ours when play last started or changed speed
+    anchor: (u64, Instant),
+    // stands in for the time the first frame was recorded, the monotonic clock
+    // the rest are placed on
+    base: Instant,
+    pub error: Option<String>,
+}
+
+impl Player {
+    pub fn open(path: &Path) -> io::Result<Player> {
+        let mut file = File::open(path)?;
+        let (frames, _) = scan(&mut file, path)?;
+        if frames.is_empty() {
+            return Err(io::Error::new(ErrorKind::InvalidData, format!("{} has nothing recorded in it", path.display())));
+        }
+        Ok(Player {
+            file,
+            frames,
+            next: 0,
+            playing: true,
+            speed: SPEEDS.iter().position(|speed| *speed == 1.0).unwrap(),
+            anchor: (0, Instant::now()),
+            base: Instant::now(),
+            error: None,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    // The frame on screen.
+    pub fn position(&self) -> usize {
+        self.next.saturating_sub(1)
+    }
+
+    pub fn time(&self, frame: usize) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.frames[frame].time_ms)
+    }
+
+    pub fn speed(&self) -> f64 {
+        SPEEDS[self.speed]
+    }
+
+    pub fn faster(&mut self) {
+        self.reanchor();
+        self.speed = (self.speed + 1).min(SPEEDS.len() - 1);
+    }
+
+    pub fn slower(&mut self) {
+        self.reanchor();
+        self.speed = self.speed.saturating_sub(1);
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.next >= self.frames.len()
+    }
+
+    pub fn pause(&mut self) {
+        self.reanchor();
+        self.playing = false;
+    }
+
+    pub fn play(&mut self) {
+        self.anchor.1 = Instant::now();
+        self.playing = true;
+    }
+
+    // Where the recording's clock has got to.
+    fn now_ms(&self) -> u64 {
+        match self.playing {
+            true => self.anchor.0 + (self.anchor.1.elapsed().as_secs_f64() * self.speed() * 1000.0) as u64,
+            false => self.anchor.0,
+        }
+    }
+
+    fn reanchor(&mut self) {
+        self.anchor = (self.now_ms(), Instant::now());
+    }
+
+    // How far through the recording `frame` was recorded, from 0 to 1.
+    pub fn fraction(&self, frame: usize) -> f64 {
+        let (first, last) = (self.frames[0].time_ms, self.frames[self.frames.len() - 1].time_ms);
+        match last > first {
+            true => self.frames[frame].time_ms.saturating_sub(first) as f64 / (last - first) as f64,
+            false => 0.0,
+        }
+    }
+
+    // The frame recorded closest to `fraction` of the way through.
+    pub fn frame_at(&self, fraction: f64) -> usize {
+        let (first, last) = (self.frames[0].time_ms, self.frames[self.frames.len() - 1].time_ms);
+        let target = first + (last.saturating_sub(first) as f64 * fraction.clamp(0.0, 1.0)) as u64;
+        let after = self.frames.partition_point(|frame| frame.time_ms < target);
+        match after {
+            0 => 0,
+            n if n == self.frames.len() => n - 1,
+            n if target.saturating_sub(self.frames[n - 1].time_ms) <= self.frames[n].time_ms.saturating_sub(target) => n - 1,
+            n => n,
+        }
+    }
+
+    fn read(&mut self, frame: usize) -> io::Result<Sample> {
+        let index = self.frames[frame];
+        self.file.seek(SeekFrom::Start(index.offset))?;
+        let decoder = ZlibDecoder::new((&mut self.file).take(index.length as u64));
+        let body: Frame<Vec<DiagWithInode>> = serde_json::from_reader(decoder)?;
+        let since_first = Duration::from_millis(index.time_ms.saturating_sub(self.frames[0].time_ms));
+        Ok(Sample {
+            at: UNIX_EPOCH + Duration::from_millis(index.time_ms),
+            taken: self.base + since_first,
+            snapshot: body.snapshot,
+            listeners: body.listeners,
+            down: Vec::new(),
+        })
+    }
+
+    // Reads frames from `from` until the one before `until`, stopping play at the
+    // first that can't be read.
+    fn read_range(&mut self, from: usize, until: usize) -> Vec<Sample> {
+        let mut samples = Vec::new();
+        for frame in from..until {
+            match self.read(frame) {
+                Ok(sample) => samples.push(sample),
+                Err(err) => {
+                    self.error = Some(format!("frame {}: {}", frame + 1, err));
+                    self.playing = false;
+                    self.next = self.frames.len();
+                    break;
+                }
+            }
+        }
+        samples
+    }
+
+    // Whatever has come due since the last call.
+    pub fn due(&mut self) -> Vec<Sample> {
+        if !self.playing || self.at_end() {
+            return Vec::new();
+        }
+        let now = self.now_ms();
+        if self.frames[self.next].time_ms > now + SKIP_GAP.as_millis() as u64 {
+            self.anchor = (self.frames[self.next].time_ms, Instant::now());
+        }
+        let now = self.now_ms();
+        let until = self.next + self.frames[self.next..].partition_point(|frame| frame.time_ms <= now);
+        let samples = self.read_range(self.next, until);
+        self.next = self.next.max(until);
+        if self.at_end() {
+            self.pause();
+        }
+        samples
+    }
+
+    // Moves to `frame`, returning it along with the frames in the `retention`
+    // before it, for the history to be built up again from.
+    pub fn seek(&mut self, frame: usize, retention: Duration) -> Vec<Sample> {
+        let frame = frame.min(self.frames.len() - 1);
+        let to = self.frames[frame].time_ms;
+        let from = self.frames[..frame].partition_point(|index| index.time_ms + (retention.as_millis() as u64) < to);
+        self.anchor = (to, Instant::now());
+        self.next = frame + 1;
+        self.read_range(from, frame + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tcpdiag::tests::socket;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("tcptop-test-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let path = temp_path("round-trip");
+        let sockets = vec![socket("10.0.0.1:5000", "10.0.0.2:443")];
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut recorder = Recorder::open(&path).unwrap();
+        for i in 0..3 {
+            recorder.write(start + Duration::from_secs(i), &sockets, &[]).unwrap();
+        }
+        drop(recorder);
+
+        // a frame cut short is left out, and dropped before any more are added
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[200, 0, 0, 0, 1, 2]).unwrap();
+        drop(file);
+        assert_eq!(Player::open(&path).unwrap().len(), 3);
+        let mut recorder = Recorder::open(&path).unwrap();
+        recorder.write(start + Duration::from_secs(600), &[], &sockets).unwrap();
+        drop(recorder);
+
+        let mut player = Player::open(&path).unwrap();
+        assert_eq!(player.len(), 4);
+        assert_eq!(player.time(3), start + Duration::from_secs(600));
+        let samples = player.seek(2, Duration::from_secs(1));
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[1].snapshot[0].dst, sockets[0].dst);
+        assert_eq!(samples[1].taken.duration_since(samples[0].taken), Duration::from_secs(1));
+        assert_eq!(player.position(), 2);
+        assert_eq!(player.seek(3, Duration::from_secs(30)).len(), 1);
+        assert_eq!(player.frame_at(0.0), 0);
+        assert_eq!(player.frame_at(0.5), 2);
+        assert_eq!(player.frame_at(0.9), 3);
+        assert_eq!(player.frame_at(0.001), 1);
+        assert_eq!(player.fraction(3), 1.0);
+
+        // a clock that stepped back still plays in the order it was recorded
+        let mut recorder = Recorder::open(&path).unwrap();
+        recorder.write(start + Duration::from_secs(300), &sockets, &[]).unwrap();
+        recorder.write(start + Duration::from_secs(900), &sockets, &[]).unwrap();
+        drop(recorder);
+        let player = Player::open(&path).unwrap();
+        assert_eq!(player.time(4), start + Duration::from_secs(600));
+        assert_eq!(player.fraction(4), player.fraction(3));
+        assert_eq!(player.frame_at(1.0), 5);
+
+        std::fs::write(&path, b"something else").unwrap();
+        assert!(Player::open(&path).is_err());
+        assert!(Recorder::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
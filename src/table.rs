@@ -1,56 +1,255 @@
-use crate::tcpdiag::{gather_sockets, DiagWithInode, TCP_STATE, TCPInfo};
+use crate::tcpdiag::{gather_sockets, DiagWithInode, Protocol, SocketId, TCPInfo};
+use crate::filter::Filter;
+use crate::highlight::{Highlight, Thresholds};
+use crate::columns::{self, Column, Peaks, Peer, RowContext, DEFAULT_COLUMNS, FLEET_COLUMNS};
+use crate::fleet;
+use crate::recording::{Player, Recorder};
 use std::vec::Vec;
 use std::collections::VecDeque;
 use ratatui::widgets::TableState;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use trust_dns_resolver::Resolver;
 use trust_dns_resolver::config::*;
 use std::net::IpAddr;
-use std::sync::mpsc::{self, Sender, TryRecvError};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
 use std::sync::{RwLock, Arc};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
+// How far back history goes unless the config says otherwise. It's a time rather
+// than a number of samples so the charts cover the same span whatever the
+// refresh interval is.
+pub const DEFAULT_RETENTION: Duration = Duration::from_secs(30);
+pub const MIN_RETENTION: Duration = Duration::from_secs(10);
+pub const MAX_RETENTION: Duration = Duration::from_secs(600);
+// A daemon can keep a lot more, see daemon.rs. Captures for stepping back while
+// paused are still cut off at MAX_RETENTION, or they'd hold a copy of every
+// socket for every refresh.
+pub const MAX_DAEMON_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
 
-// TODO: seperate config?
-pub const HISTORY_RETENTION: usize = 30;
+// How sockets get collected, from the config file and flags.
+#[derive(Clone, Copy, Debug)]
+pub struct Options {
+    pub retention: Duration,
+    pub dns: bool,
+    pub protocol: Protocol,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options { retention: DEFAULT_RETENTION, dns: true, protocol: Protocol::All }
+    }
+}
 
+#[derive(Clone)]
 pub struct SocketHistory {
+    // when each sample was taken, lined up with the rings below
+    pub sampled_at: VecDeque<Instant>,
     pub send_bps: VecDeque<u64>,
     pub recv_bps: VecDeque<u64>,
     pub send_bytes: VecDeque<u64>,
     pub recv_bytes: VecDeque<u64>,
     pub packet_loss: VecDeque<u32>,
     pub congestion_window: VecDeque<u64>,
+    pub slow_start_threshold: VecDeque<u64>,
+    pub rtt: VecDeque<u64>,
+    pub rtt_variance: VecDeque<u64>,
+    pub min_rtt: VecDeque<u64>,
+    pub retransmits: VecDeque<u64>,
+    pub pacing_rate: VecDeque<u64>,
+    pub delivery_rate: VecDeque<u64>,
+    // the last two samples, used to show what changed between ticks
+    pub previous: TCPInfo,
+    pub current: TCPInfo,
+    // turned up after tcptop started, rather than being there from the beginning
+    pub fresh: bool,
+    // every sample taken, including the ones trimmed off the rings since
+    pub samples: usize,
 }
 
 impl SocketHistory {
-    fn new(size: usize, tci: &TCPInfo) -> SocketHistory {
+    pub fn new(tci: &TCPInfo, fresh: bool, at: Instant) -> SocketHistory {
         let mut history = SocketHistory {
-            send_bps: VecDeque::with_capacity(size),
-            recv_bps: VecDeque::with_capacity(size),
-            send_bytes: VecDeque::with_capacity(size),
-            recv_bytes: VecDeque::with_capacity(size),
-            packet_loss: VecDeque::with_capacity(size),
-            congestion_window: VecDeque::with_capacity(size),
+            sampled_at: VecDeque::new(),
+            send_bps: VecDeque::new(),
+            recv_bps: VecDeque::new(),
+            send_bytes: VecDeque::new(),
+            recv_bytes: VecDeque::new(),
+            packet_loss: VecDeque::new(),
+            congestion_window: VecDeque::new(),
+            slow_start_threshold: VecDeque::new(),
+            rtt: VecDeque::new(),
+            rtt_variance: VecDeque::new(),
+            min_rtt: VecDeque::new(),
+            retransmits: VecDeque::new(),
+            pacing_rate: VecDeque::new(),
+            delivery_rate: VecDeque::new(),
+            previous: tci.clone(),
+            current: tci.clone(),
+            fresh,
+            samples: 1,
         };
 
         // Insert current segment counts to avoid burst rate when first ran
+        history.sampled_at.push_front(at);
         history.send_bps.push_front(0);
         history.recv_bps.push_front(0);
         history.send_bytes.push_front(tci.tcpi_bytes_sent);
         history.recv_bytes.push_front(tci.tcpi_bytes_received);
         history.packet_loss.push_front(0);
         history.congestion_window.push_front(0);
+        history.slow_start_threshold.push_front(tci.tcpi_snd_ssthresh as u64);
+        history.rtt.push_front(tci.tcpi_rtt as u64);
+        history.rtt_variance.push_front(tci.tcpi_rttvar as u64);
+        history.min_rtt.push_front(tci.tcpi_min_rtt as u64);
+        history.retransmits.push_front(0);
+        history.pacing_rate.push_front(tci.tcpi_pacing_rate);
+        history.delivery_rate.push_front(tci.tcpi_delivery_rate);
+        history
+    }
+}
+
+impl SocketHistory {
+    // Drops samples older than `retention`, but always keeps two so there's
+    // a line to draw even at the slowest refresh.
+    fn trim(&mut self, now: Instant, retention: Duration) {
+        let keep = self.sampled_at
+            .iter()
+            .take_while(|at| now.duration_since(**at) <= retention)
+            .count()
+            .max(2);
+        self.sampled_at.truncate(keep);
+        self.send_bytes.truncate(keep);
+        self.recv_bytes.truncate(keep);
+        self.send_bps.truncate(keep);
+        self.recv_bps.truncate(keep);
+        self.packet_loss.truncate(keep);
+        self.congestion_window.truncate(keep);
+        self.slow_start_threshold.truncate(keep);
+        self.rtt.truncate(keep);
+        self.rtt_variance.truncate(keep);
+        self.min_rtt.truncate(keep);
+        self.retransmits.truncate(keep);
+        self.pacing_rate.truncate(keep);
+        self.delivery_rate.truncate(keep);
+    }
+
+    // A copy with only the newest `keep` samples.
+    fn recent(&self, keep: usize) -> SocketHistory {
+        SocketHistory {
+            sampled_at: recent(&self.sampled_at, keep),
+            send_bps: recent(&self.send_bps, keep),
+            recv_bps: recent(&self.recv_bps, keep),
+            send_bytes: recent(&self.send_bytes, keep),
+            recv_bytes: recent(&self.recv_bytes, keep),
+            packet_loss: recent(&self.packet_loss, keep),
+            congestion_window: recent(&self.congestion_window, keep),
+            slow_start_threshold: recent(&self.slow_start_threshold, keep),
+            rtt: recent(&self.rtt, keep),
+            rtt_variance: recent(&self.rtt_variance, keep),
+            min_rtt: recent(&self.min_rtt, keep),
+            retransmits: recent(&self.retransmits, keep),
+            pacing_rate: recent(&self.pacing_rate, keep),
+            delivery_rate: recent(&self.delivery_rate, keep),
+            previous: self.previous.clone(),
+            current: self.current.clone(),
+            fresh: self.fresh,
+            samples: self.samples,
+        }
+    }
+
+    // A copy with everything sampled after `taken` dropped, so it looks the way
+    // it did back then. We don't keep old tcp_info around, so there's nothing to
+    // diff against and `previous` is the same as `current`.
+    fn as_of(&self, taken: Instant, info: &TCPInfo) -> SocketHistory {
+        let mut history = self.clone();
+        while history.sampled_at.len() > 1 && history.sampled_at[0] > taken {
+            history.samples = history.samples.saturating_sub(1);
+            history.sampled_at.pop_front();
+            history.send_bps.pop_front();
+            history.recv_bps.pop_front();
+            history.send_bytes.pop_front();
+            history.recv_bytes.pop_front();
+            history.packet_loss.pop_front();
+            history.congestion_window.pop_front();
+            history.slow_start_threshold.pop_front();
+            history.rtt.pop_front();
+            history.rtt_variance.pop_front();
+            history.min_rtt.pop_front();
+            history.retransmits.pop_front();
+            history.pacing_rate.pop_front();
+            history.delivery_rate.pop_front();
+        }
+        history.previous = info.clone();
+        history.current = info.clone();
         history
     }
 }
 
+fn recent<T: Copy>(ring: &VecDeque<T>, keep: usize) -> VecDeque<T> {
+    ring.iter().take(keep).copied().collect()
+}
+
+// Everything gathered on one refresh, from netlink, a daemon or a fleet of agents.
+pub struct Sample {
+    pub at: SystemTime,
+    pub taken: Instant,
+    pub snapshot: Vec<DiagWithInode>,
+    pub listeners: Vec<DiagWithInode>,
+    // agents whose sockets are missing from this one, and why
+    pub down: Vec<String>,
+}
+
+// The sockets from one refresh, kept so a paused display can step back through them.
+struct Capture {
+    at: SystemTime,
+    taken: Instant,
+    snapshot: Vec<DiagWithInode>,
+    listeners: Vec<DiagWithInode>,
+}
+
+// A socket that was there on one refresh and gone on the next, with the last of
+// what we knew about it.
+#[derive(Clone)]
+pub struct ClosedSocket {
+    // counts up forever, so an entry can be told apart from a later socket that
+    // happened to reuse the same addresses
+    pub seq: u64,
+    pub at: SystemTime,
+    pub socket: DiagWithInode,
+    pub history: Arc<SocketHistory>,
+}
+
+// How many closed sockets the Closed tab remembers.
+pub const CLOSED_RETENTION: usize = 500;
+// How many of a closed socket's last samples are kept with it. With a daemon's
+// retention a whole history can run to megabytes, and there can be 500 of them.
+pub const CLOSED_SAMPLES: usize = 600;
+
+// What the table shows while paused. `age` counts captures back from the newest,
+// and goes up as new ones arrive underneath so the display stays put.
+pub struct Paused {
+    pub age: usize,
+    pub at: SystemTime,
+    snapshot: Vec<DiagWithInode>,
+    listeners: Vec<DiagWithInode>,
+    history: HashMap<SocketId, Arc<SocketHistory>>,
+}
+
+// The kernel's counters only go up for as long as a socket lives, so any of
+// them going down means it's a different socket.
+fn restarted(before: &TCPInfo, now: &TCPInfo) -> bool {
+    now.tcpi_bytes_sent < before.tcpi_bytes_sent
+        || now.tcpi_bytes_received < before.tcpi_bytes_received
+        || now.tcpi_data_segs_out < before.tcpi_data_segs_out
+        || now.tcpi_total_retrans < before.tcpi_total_retrans
+}
+
 fn is_bps(n: f64) -> bool { n < 1000.0 }
 fn is_kbps(n: f64) -> bool { n >= 1000.0 && n < 1000000.0 }
 fn is_mbps(n: f64) -> bool { n >= 1000000.0 }
 
-fn friendly_transfer_str(rate: u64) -> String {
+pub fn friendly_transfer_str(rate: u64) -> String {
     let rate = rate as f64;
     match rate {
         n if is_bps(n) => { format!("{} bps", rate) }
@@ -62,25 +261,85 @@ fn friendly_transfer_str(rate: u64) -> String {
 
 fn lookup_addr(ipaddr: IpAddr) -> String {
     let resolver = Resolver::new(ResolverConfig::default(), ResolverOpts::default()).unwrap();
-    let response = match resolver.reverse_lookup(ipaddr) {
+    match resolver.reverse_lookup(ipaddr) {
         Ok(record) => { record.iter().next().unwrap().to_ascii() } 
         Err(_) => { ipaddr.to_string() }
-    };
-    response
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SortBy {
+    pub column: usize,
+    pub descending: bool,
+}
+
+// Where a table's samples come from.
+pub enum Source {
+    // netlink, on every refresh
+    Local,
+    // a daemon or a fleet's agents, see daemon.rs and fleet.rs
+    Feed(Receiver<Sample>),
+    // a file written with --record, see recording.rs
+    Replay(Player),
 }
 
 pub struct StatefulTable {
     pub state: TableState,
     pub items: Vec<Vec<String>>,
+    // how each row in `items` should stand out
+    pub highlights: Vec<Highlight>,
+    pub thresholds: Thresholds,
+    // everything gathered on the last refresh, `sockets` is what survived the filter
+    pub snapshot: Vec<DiagWithInode>,
     pub sockets: Vec<DiagWithInode>,
-    pub history: HashMap<u32, SocketHistory>,
+    // shared so a daemon can hand them out without copying them while the
+    // table is locked, see daemon.rs
+    pub history: HashMap<SocketId, Arc<SocketHistory>>,
+    // LISTEN sockets are kept out of the table, they only exist to hand out new
+    // connections and have nothing interesting in their tcp_info
+    pub listeners: Vec<DiagWithInode>,
+    // newest first
+    pub closed: VecDeque<ClosedSocket>,
+    closed_seq: u64,
+    captures: VecDeque<Capture>,
+    // how many of the captures each socket is in, so its history can go when
+    // that drops to none
+    captured: HashMap<SocketId, usize>,
+    pub paused: Option<Paused>,
+    // the socket under the highlight, so it can be found again after a refresh
+    // moves it to another row
+    pub selected: Option<SocketId>,
+    pub filter: Filter,
+    // every column we know about, `layout` picks which of them are shown and in what order
+    pub registry: Vec<Column>,
+    pub layout: Vec<usize>,
+    pub sort: Option<SortBy>,
+    // sparklines either share a scale down the column or get one each
+    pub shared_spark_scale: bool,
+    spark_peaks: Option<Peaks>,
+    pub options: Options,
+    source: Source,
+    // every sample goes here too with --record
+    recorder: Option<Recorder>,
+    // why the recording stopped, until someone's told
+    pub record_error: Option<String>,
+    // the daemon has gone away, what's left is all there'll be
+    pub detached: bool,
+    // a fleet's agents by `DiagWithInode::host`, empty when the sockets are all ours
+    pub hosts: Vec<String>,
+    pub down: Vec<String>,
+    // where the other end of each shown socket is in the shown snapshot
+    peers: HashMap<SocketId, usize>,
+    // the inode each socket in `history` last had, to tell a new connection on
+    // the same addresses from the old one
+    inodes: HashMap<SocketId, u32>,
     name_channel: Sender<IpAddr>,
     name_lookups: Arc<RwLock<HashMap<IpAddr, String>>>,
 }
 
 
-impl<'a> StatefulTable {
-    pub fn new() -> StatefulTable {
+impl StatefulTable {
+    fn blank(options: Options, source: Source) -> StatefulTable {
         // non-blocking DNS resolution will be hanlded in a seperate thread with a channel
         // setup to receive requests that aren't already in our name hashmap. This this will be
         // "detached" and never joined. Im not sure if this matters or not since when the parent
@@ -102,36 +361,422 @@ impl<'a> StatefulTable {
             }
         });
 
-        let sockets: Vec<DiagWithInode> = gather_sockets();
-        let new_table = StatefulTable {
+        let registry = columns::registry();
+        let layout = DEFAULT_COLUMNS
+            .iter()
+            .filter_map(|id| columns::position(&registry, id))
+            .collect();
+        StatefulTable {
             state: TableState::default(),
             items: Vec::new(),
-            sockets: sockets,
+            highlights: Vec::new(),
+            thresholds: Thresholds::default(),
+            snapshot: Vec::new(),
+            sockets: Vec::new(),
             history: HashMap::new(),
-            name_channel: chan_tx.clone(),
-            name_lookups: name_map.clone(),
-        };
+            listeners: Vec::new(),
+            closed: VecDeque::new(),
+            closed_seq: 0,
+            captures: VecDeque::new(),
+            captured: HashMap::new(),
+            paused: None,
+            selected: None,
+            filter: Filter::parse("").unwrap(),
+            registry,
+            layout,
+            sort: None,
+            shared_spark_scale: false,
+            spark_peaks: None,
+            options,
+            source,
+            recorder: None,
+            record_error: None,
+            detached: false,
+            hosts: Vec::new(),
+            down: Vec::new(),
+            peers: HashMap::new(),
+            inodes: HashMap::new(),
+            name_channel: chan_tx,
+            name_lookups: name_map,
+        }
+    }
 
-        // TODO: this is maybe not a great pattern. we use data bound to the struct to generate the
-        // string which is also bound to the struct, so we'd have to make this table mutable to be
-        // able to assign items to the existing struct after creation.
-        new_table
+    pub fn new(options: Options) -> StatefulTable {
+        let mut table = StatefulTable::blank(options, Source::Local);
+        table.refresh();
+        table
     }
 
+    // A table that takes its samples from `feed`, picking up from a daemon's
+    // history and closed sockets as of `latest`.
+    pub fn attached(
+        options: Options,
+        feed: Receiver<Sample>,
+        latest: Sample,
+        history: HashMap<SocketId, Arc<SocketHistory>>,
+        closed: VecDeque<ClosedSocket>,
+    ) -> StatefulTable {
+        let mut table = StatefulTable::blank(options, Source::Feed(feed));
+        table.history = history;
+        table.closed_seq = closed.front().map_or(0, |closed| closed.seq);
+        table.closed = closed;
+        table.push_capture(Capture {
+            at: latest.at,
+            taken: latest.taken,
+            snapshot: latest.snapshot.clone(),
+            listeners: latest.listeners.clone(),
+        });
+        table.snapshot = latest.snapshot;
+        table.listeners = latest.listeners;
+        table.rebuild_rows();
+        table
+    }
+
+    // A table that takes merged samples from a fleet's agents, named by `hosts`.
+    pub fn fleet(options: Options, feed: Receiver<Sample>, hosts: Vec<String>) -> StatefulTable {
+        let mut table = StatefulTable::blank(options, Source::Feed(feed));
+        table.hosts = hosts;
+        table.layout = FLEET_COLUMNS.iter().filter_map(|id| columns::position(&table.registry, id)).collect();
+        table
+    }
+
+    // A table that plays back a recording, starting from its first sample.
+    pub fn replay(options: Options, player: Player) -> StatefulTable {
+        let mut table = StatefulTable::blank(options, Source::Replay(player));
+        table.seek(0);
+        table
+    }
+
+    // Writes every sample to `recorder` from now on, starting with the latest.
+    pub fn record_to(&mut self, mut recorder: Recorder) -> std::io::Result<()> {
+        if let Some(capture) = self.captures.front() {
+            recorder.write(capture.at, &capture.snapshot, &capture.listeners)?;
+        }
+        self.recorder = Some(recorder);
+        Ok(())
+    }
+
+    // Collection carries on while paused, the display just doesn't follow it.
     pub fn refresh(&mut self) {
-        let sockets: Vec<DiagWithInode> = gather_sockets();
-        let items = self.gen_socket_string_vector();
+        let mut samples = Vec::new();
+        match &mut self.source {
+            Source::Local => {
+                let (listeners, snapshot) =
+                    gather_sockets(self.options.protocol).into_iter().partition(|sock| sock.listening());
+                samples.push(Sample { at: SystemTime::now(), taken: Instant::now(), snapshot, listeners, down: Vec::new() });
+            }
+            Source::Replay(player) => samples = player.due(),
+            Source::Feed(feed) => loop {
+                match feed.try_recv() {
+                    Ok(sample) => samples.push(sample),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        self.detached = true;
+                        break;
+                    }
+                }
+            },
+        }
+        for sample in samples {
+            let written = self.recorder.as_mut().map(|recorder| recorder.write(sample.at, &sample.snapshot, &sample.listeners));
+            if let Some(Err(err)) = written {
+                self.record_error = Some(err.to_string());
+                self.recorder = None;
+            }
+            self.record(sample);
+        }
+    }
+
+    pub fn player(&self) -> Option<&Player> {
+        match &self.source {
+            Source::Replay(player) => Some(player),
+            _ => None,
+        }
+    }
+
+    pub fn player_mut(&mut self) -> Option<&mut Player> {
+        match &mut self.source {
+            Source::Replay(player) => Some(player),
+            _ => None,
+        }
+    }
+
+    // Jumps a replay to `frame`, starting the history over from the samples
+    // leading up to it.
+    pub fn seek(&mut self, frame: usize) {
+        let retention = self.options.retention;
+        let samples = match &mut self.source {
+            Source::Replay(player) => player.seek(frame, retention),
+            _ => return,
+        };
+        self.history.clear();
+        self.closed.clear();
+        self.captures.clear();
+        self.captured.clear();
+        self.inodes.clear();
+        self.paused = None;
+        self.snapshot.clear();
+        self.listeners.clear();
+        for sample in samples {
+            self.record(sample);
+        }
+        self.rebuild_rows();
+    }
+
+    fn record(&mut self, sample: Sample) {
+        self.listeners = sample.listeners;
+        self.snapshot = sample.snapshot;
+        self.down = sample.down;
+        self.update_history(sample.at, sample.taken);
+        match self.paused.as_ref().map(|paused| paused.age + 1) {
+            // the capture we were showing fell off the end, show the oldest one left
+            Some(age) if age >= self.captures.len() => self.freeze(self.captures.len() - 1),
+            Some(age) => self.paused.as_mut().unwrap().age = age,
+            None => self.rebuild_rows(),
+        }
+    }
+
+    fn freeze(&mut self, age: usize) {
+        let capture = &self.captures[age];
+        let mut history = HashMap::new();
+        let mut snapshot = Vec::new();
+        for sock in &capture.snapshot {
+            if let Some(socket_history) = self.history.get(&sock.id()) {
+                history.insert(sock.id(), Arc::new(socket_history.as_of(capture.taken, sock.info.as_ref().unwrap())));
+                snapshot.push(sock.clone());
+            }
+        }
+        let listeners = capture.listeners.clone();
+        self.paused = Some(Paused { age, at: capture.at, snapshot, listeners, history });
+        self.rebuild_rows();
+    }
+
+    pub fn pause(&mut self) {
+        if self.paused.is_none() {
+            self.freeze(0);
+        }
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = None;
+        self.rebuild_rows();
+    }
+
+    pub fn step_back(&mut self) {
+        if let Some(age) = self.paused.as_ref().map(|paused| paused.age + 1) {
+            self.freeze(age.min(self.captures.len() - 1));
+        }
+    }
+
+    pub fn step_forward(&mut self) {
+        if let Some(age) = self.paused.as_ref().map(|paused| paused.age) {
+            self.freeze(age.saturating_sub(1));
+        }
+    }
+
+    // The sockets and history on screen, which lag behind the latest ones when paused.
+    // When the newest capture was taken, by the wall clock and the monotonic one.
+    pub fn captured_at(&self) -> (SystemTime, Instant) {
+        match self.captures.front() {
+            Some(capture) => (capture.at, capture.taken),
+            None => (SystemTime::now(), Instant::now()),
+        }
+    }
+
+    pub fn shown(&self) -> (&[DiagWithInode], &HashMap<SocketId, Arc<SocketHistory>>) {
+        match &self.paused {
+            Some(paused) => (&paused.snapshot, &paused.history),
+            None => (&self.snapshot, &self.history),
+        }
+    }
+
+    pub fn shown_listeners(&self) -> &[DiagWithInode] {
+        match &self.paused {
+            Some(paused) => &paused.listeners,
+            None => &self.listeners,
+        }
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+        self.rebuild_rows();
+    }
+
+    pub fn set_thresholds(&mut self, thresholds: Thresholds) {
+        self.thresholds = thresholds;
+        self.rebuild_rows();
+    }
+
+    // Column ids that aren't in the registry are handed back so the caller can
+    // complain about them.
+    pub fn set_layout(&mut self, ids: &[String]) -> Result<(), String> {
+        let mut layout = Vec::new();
+        for id in ids {
+            match columns::position(&self.registry, id) {
+                Some(index) => layout.push(index),
+                None => return Err(id.clone()),
+            }
+        }
+        if layout.is_empty() {
+            return Err(String::new());
+        }
+        self.layout = layout;
+        self.rebuild_rows();
+        Ok(())
+    }
+
+    pub fn layout_ids(&self) -> Vec<&'static str> {
+        self.layout.iter().map(|i| self.registry[*i].id).collect()
+    }
+
+    // Steps the sort through the visible columns left to right, then back to
+    // the kernel's ordering.
+    pub fn sort_next(&mut self) {
+        let current = self.sort.and_then(|sort| self.layout.iter().position(|c| *c == sort.column));
+        let descending = self.sort.map(|sort| sort.descending).unwrap_or(false);
+        self.sort = match current {
+            None => self.layout.first().map(|column| SortBy { column: *column, descending }),
+            Some(i) => self.layout.get(i + 1).map(|column| SortBy { column: *column, descending }),
+        };
+        self.rebuild_rows();
+    }
+
+    // Sorts by the given column, or flips the order if it already is.
+    pub fn sort_by(&mut self, column: usize) {
+        self.sort = match self.sort {
+            Some(sort) if sort.column == column => Some(SortBy { column, descending: !sort.descending }),
+            _ => Some(SortBy { column, descending: false }),
+        };
+        self.rebuild_rows();
+    }
+
+    // "rtt_ms" or "rtt_ms:desc", as given in the config. The column doesn't
+    // have to be one that's shown.
+    pub fn set_sort(&mut self, spec: &str) -> Result<(), String> {
+        let (id, descending) = match spec.split_once(':') {
+            None | Some((_, "asc")) => (spec.split(':').next().unwrap(), false),
+            Some((id, "desc")) => (id, true),
+            Some((_, order)) => return Err(format!("unknown sort order '{}', expected asc or desc", order)),
+        };
+        let column = columns::position(&self.registry, id).ok_or(format!("unknown sort column '{}'", id))?;
+        self.sort = Some(SortBy { column, descending });
+        self.rebuild_rows();
+        Ok(())
+    }
+
+    pub fn toggle_spark_scale(&mut self) {
+        self.shared_spark_scale = !self.shared_spark_scale;
+        self.rebuild_rows();
+    }
+
+    pub fn sort_reverse(&mut self) {
+        if let Some(sort) = self.sort.as_mut() {
+            sort.descending = !sort.descending;
+            self.rebuild_rows();
+        }
+    }
+
+    // Re-runs the filter and sort over the last snapshot without gathering again,
+    // which is what lets the filter prompt and column picker preview as you type.
+    pub fn rebuild_rows(&mut self) {
+        if !self.hosts.is_empty() {
+            self.peers = fleet::pair(self.shown().0);
+        }
+        let mut sockets: Vec<DiagWithInode> = self.shown().0
+            .iter()
+            .filter(|sock| self.filter.matches(sock))
+            .cloned()
+            .collect();
+
+        if let Some(sort) = self.sort {
+            let column = &self.registry[sort.column];
+            let mut keyed: Vec<_> = sockets
+                .into_iter()
+                .map(|sock| (column.sort_key(&self.row_context(&sock)), sock))
+                .collect();
+            // flipping the comparison rather than the result keeps ties in the
+            // kernel's order both ways
+            match sort.descending {
+                true => keyed.sort_by(|a, b| b.0.compare(&a.0)),
+                false => keyed.sort_by(|a, b| a.0.compare(&b.0)),
+            }
+            sockets = keyed.into_iter().map(|(_, sock)| sock).collect();
+        }
+
         self.sockets = sockets;
-        self.items = items;
+        self.spark_peaks = match self.shared_spark_scale {
+            true => Some(Peaks::of(self.sockets.iter().map(|sock| &*self.shown().1[&sock.id()]))),
+            false => None,
+        };
+        self.items = self.gen_socket_string_vector();
+        let history = self.shown().1;
+        self.highlights = self.sockets
+            .iter()
+            .map(|sock| self.thresholds.classify(sock, &history[&sock.id()]))
+            .collect();
+
+        // follow the selected socket to wherever it ended up. If it's gone the
+        // highlight stays on the same row, or the last one if the list shrank.
+        let row = match self.selected.and_then(|id| self.sockets.iter().position(|sock| sock.id() == id)) {
+            Some(row) => Some(row),
+            None => self.state.selected().map(|row| row.min(self.sockets.len().saturating_sub(1))),
+        };
+        self.select(row);
     }
 
-    fn gen_socket_string_vector(&mut self) -> Vec<Vec<String>> {
-        let mut result: Vec<Vec<String>> = Vec::new();
-        for sock in &self.sockets {
+    pub fn select(&mut self, row: Option<usize>) {
+        let row = row.filter(|row| *row < self.sockets.len());
+        self.selected = row.map(|row| self.sockets[row].id());
+        self.state.select(row);
+    }
+
+    pub fn selected_socket(&self) -> Option<&DiagWithInode> {
+        self.state.selected().map(|row| &self.sockets[row])
+    }
+
+    // Looks a socket up in what's on screen, filtered out or not.
+    pub fn find(&self, id: SocketId) -> Option<(&DiagWithInode, &SocketHistory)> {
+        let (snapshot, history) = self.shown();
+        let sock = snapshot.iter().find(|sock| sock.id() == id)?;
+        Some((sock, history.get(&id)?))
+    }
+
+    fn update_history(&mut self, at: SystemTime, now: Instant) {
+        self.log_closed(at);
+        self.push_capture(Capture {
+            at,
+            taken: now,
+            snapshot: self.snapshot.clone(),
+            listeners: self.listeners.clone(),
+        });
+        let keep = self.options.retention.min(MAX_RETENTION);
+        while self.captures.len() > 1 && now.duration_since(self.captures.back().unwrap().taken) > keep {
+            self.pop_capture();
+        }
+
+        let fresh = self.captures.len() > 1;
+        for sock in &self.snapshot {
             let tcp_info = sock.info.as_ref().unwrap();
-            let history_data = self.history.entry(sock.inode).or_insert(SocketHistory::new(HISTORY_RETENTION, &tcp_info));
-            let send_bps = tcp_info.tcpi_bytes_sent - history_data.send_bytes[0]; 
-            let recv_bps = tcp_info.tcpi_bytes_received - history_data.recv_bytes[0];
+            // the addresses can be reused by a new connection while the old one's
+            // history is still kept, which starts from scratch. An orphaned
+            // socket's inode drops to 0, that's still the same one.
+            let inode = self.inodes.insert(sock.id(), sock.inode);
+            let replaced = matches!(inode, Some(before) if before != sock.inode && before != 0 && sock.inode != 0);
+            if replaced || self.history.get(&sock.id()).is_some_and(|history| restarted(&history.current, tcp_info)) {
+                self.history.remove(&sock.id());
+            }
+            // only copied if a daemon client is still holding on to it
+            let history_data = Arc::make_mut(self.history
+                .entry(sock.id())
+                .or_insert_with(|| Arc::new(SocketHistory::new(tcp_info, fresh, now))));
+            let send_bps = tcp_info.tcpi_bytes_sent.saturating_sub(history_data.send_bytes[0]);
+            let recv_bps = tcp_info.tcpi_bytes_received.saturating_sub(history_data.recv_bytes[0]);
+            // the refresh interval can be anything, so turn the change into a per second rate
+            let elapsed = now.duration_since(history_data.sampled_at[0]).as_secs_f64();
+            let per_second = |bytes: u64| match elapsed > 0.0 {
+                true => (bytes as f64 / elapsed) as u64,
+                false => bytes,
+            };
 
             // dont want to divide by zero
             let packet_loss = match tcp_info.tcpi_data_segs_out {
@@ -142,59 +787,162 @@ impl<'a> StatefulTable {
             if send_bps == tcp_info.tcpi_bytes_sent {
                 history_data.send_bps.push_front(0);
             } else {
-                history_data.send_bps.push_front(send_bps);
+                history_data.send_bps.push_front(per_second(send_bps));
             }
             if recv_bps == tcp_info.tcpi_bytes_received {
                 history_data.recv_bps.push_front(0);
             } else {
-                history_data.recv_bps.push_front(recv_bps);
+                history_data.recv_bps.push_front(per_second(recv_bps));
             }
 
+            history_data.samples += 1;
+            history_data.sampled_at.push_front(now);
             history_data.send_bytes.push_front(tcp_info.tcpi_bytes_sent);
             history_data.recv_bytes.push_front(tcp_info.tcpi_bytes_received);
             history_data.packet_loss.push_front(packet_loss);
             history_data.congestion_window.push_front(tcp_info.tcpi_snd_cwnd as u64);
+            history_data.slow_start_threshold.push_front(tcp_info.tcpi_snd_ssthresh as u64);
+            history_data.rtt.push_front(tcp_info.tcpi_rtt as u64);
+            history_data.rtt_variance.push_front(tcp_info.tcpi_rttvar as u64);
+            history_data.min_rtt.push_front(tcp_info.tcpi_min_rtt as u64);
+            history_data.retransmits.push_front(
+                tcp_info.tcpi_total_retrans.saturating_sub(history_data.current.tcpi_total_retrans) as u64,
+            );
+            history_data.pacing_rate.push_front(tcp_info.tcpi_pacing_rate);
+            history_data.delivery_rate.push_front(tcp_info.tcpi_delivery_rate);
+            history_data.previous = std::mem::replace(&mut history_data.current, tcp_info.clone());
 
-            // Remove extra items if we are past capacity
-            history_data.send_bytes.truncate(HISTORY_RETENTION);
-            history_data.recv_bytes.truncate(HISTORY_RETENTION);
-            history_data.send_bps.truncate(HISTORY_RETENTION);
-            history_data.recv_bps.truncate(HISTORY_RETENTION);
-            history_data.packet_loss.truncate(HISTORY_RETENTION);
-            history_data.congestion_window.truncate(HISTORY_RETENTION);
-
-            let src_name = match self.name_lookups.read().unwrap().get(&sock.src.ip()) {
-                Some(record) => record.to_string(), // why do i need this here?
-                None => { 
-                    self.name_channel.send(sock.src.ip()).unwrap();
-                    sock.src.ip().to_string()
-                }
-            };
-            let dst_name = match self.name_lookups.read().unwrap().get(&sock.dst.ip()) {
-                Some(record) => record.to_string(), // why do i need this here?
-                None => { 
-                    self.name_channel.send(sock.dst.ip()).unwrap();
-                    sock.dst.ip().to_string()
-                }
+            history_data.trim(now, self.options.retention);
+        }
+    }
+
+    fn push_capture(&mut self, capture: Capture) {
+        for sock in &capture.snapshot {
+            *self.captured.entry(sock.id()).or_insert(0) += 1;
+        }
+        self.captures.push_front(capture);
+    }
+
+    // Sockets that have gone away take their history with them, once there's no
+    // capture left to step back to them in.
+    fn pop_capture(&mut self) {
+        let capture = match self.captures.pop_back() {
+            Some(capture) => capture,
+            None => return,
+        };
+        for sock in &capture.snapshot {
+            let id = sock.id();
+            let count = match self.captured.get_mut(&id) {
+                Some(count) => count,
+                None => continue,
             };
+            *count -= 1;
+            if *count == 0 {
+                self.captured.remove(&id);
+                self.history.remove(&id);
+                self.inodes.remove(&id);
+            }
+        }
+    }
 
-            let entry = vec![
-                format!("{}:{}", src_name, sock.src.port().to_string()),
-                format!("{}:{}", dst_name, sock.dst.port().to_string()),
-                TCP_STATE::from_u8(tcp_info.tcpi_state).to_string(),
-                friendly_transfer_str(history_data.send_bps[0]),
-                friendly_transfer_str(history_data.recv_bps[0]),
-                format!("{}%", history_data.packet_loss[0].to_string()),
-            ];
+    // Anything in the last capture that isn't in the new snapshot has closed. Its
+    // history hasn't been pruned yet so there's still something to show for it.
+    fn log_closed(&mut self, at: SystemTime) {
+        let previous = match self.captures.front() {
+            Some(capture) => capture,
+            None => return,
+        };
+        let open: HashSet<SocketId> = self.snapshot.iter().map(|sock| sock.id()).collect();
+        for sock in previous.snapshot.iter().filter(|sock| !open.contains(&sock.id())) {
+            if let Some(history) = self.history.get(&sock.id()) {
+                self.closed_seq += 1;
+                self.closed.push_front(ClosedSocket {
+                    seq: self.closed_seq,
+                    at,
+                    socket: sock.clone(),
+                    history: Arc::new(history.recent(CLOSED_SAMPLES)),
+                });
+            }
+        }
+        self.closed.truncate(CLOSED_RETENTION);
+    }
+
+    pub fn resolve_name(&self, ipaddr: IpAddr) -> String {
+        if !self.options.dns {
+            return ipaddr.to_string();
+        }
+        match self.name_lookups.read().unwrap().get(&ipaddr) {
+            Some(record) => record.to_string(), // why do i need this here?
+            None => { 
+                self.name_channel.send(ipaddr).unwrap();
+                ipaddr.to_string()
+            }
+        }
+    }
+
+    fn row_context<'a>(&'a self, sock: &'a DiagWithInode) -> RowContext<'a> {
+        RowContext {
+            sock,
+            info: sock.info.as_ref().unwrap(),
+            history: &self.shown().1[&sock.id()],
+            src_name: self.resolve_name(sock.src.ip()),
+            dst_name: self.resolve_name(sock.dst.ip()),
+            host: self.host_name(sock.host),
+            peer: self.peers.get(&sock.id()).map(|i| {
+                let peer = &self.shown().0[*i];
+                Peer { host: self.host_name(peer.host), sock: peer }
+            }),
+            peaks: self.spark_peaks,
+        }
+    }
+
+    pub fn host_name(&self, host: usize) -> &str {
+        self.hosts.get(host).map_or("", String::as_str)
+    }
+
+    fn gen_socket_string_vector(&self) -> Vec<Vec<String>> {
+        let mut result: Vec<Vec<String>> = Vec::new();
+        for sock in &self.sockets {
+            let ctx = self.row_context(sock);
+            let entry = self.layout
+                .iter()
+                .map(|column| self.registry[*column].format(&ctx))
+                .collect();
             result.push(entry);
         }
         result 
     }
 
-    pub fn next(&mut self) {
-        let i = match self.state.selected() {
+    // The shown rows again, with plain values for exporting rather than display.
+    pub fn raw_rows(&self) -> Vec<Vec<String>> {
+        self.sockets
+            .iter()
+            .map(|sock| {
+                let ctx = self.row_context(sock);
+                self.layout.iter().map(|column| self.registry[*column].raw(&ctx)).collect()
+            })
+            .collect()
+    }
+}
+
+// What every tab's table has in common, so moving around, sorting and finding
+// work the same whichever one is showing.
+pub trait Rows {
+    fn items(&self) -> &[Vec<String>];
+    fn state(&mut self) -> &mut TableState;
+    fn selected_row(&self) -> Option<usize>;
+    fn select(&mut self, row: Option<usize>);
+    fn sort_next(&mut self);
+    fn sort_reverse(&mut self);
+    fn sort_by(&mut self, column: usize);
+
+    fn next(&mut self) {
+        if self.items().is_empty() {
+            return self.select(None);
+        }
+        let i = match self.selected_row() {
             Some(i) => {
-                if i >= self.items.len() - 1 {
+                if i >= self.items().len() - 1 {
                     0
                 } else {
                     i + 1
@@ -202,24 +950,91 @@ impl<'a> StatefulTable {
             }
             None => 0,
         };
-        self.state.select(Some(i));
+        self.select(Some(i));
+    }
+
+    // Moves the selection by a number of rows without wrapping, for paging.
+    fn move_by(&mut self, delta: isize) {
+        if self.items().is_empty() {
+            return self.select(None);
+        }
+        let current = self.selected_row().unwrap_or(0) as isize;
+        let row = (current + delta).clamp(0, self.items().len() as isize - 1);
+        self.select(Some(row as usize));
     }
 
-    pub fn previous(&mut self) {
-        let i = match self.state.selected() {
+    fn first(&mut self) {
+        self.move_by(isize::MIN / 2);
+    }
+
+    fn last(&mut self) {
+        self.move_by(isize::MAX / 2);
+    }
+
+    fn previous(&mut self) {
+        if self.items().is_empty() {
+            return self.select(None);
+        }
+        let i = match self.selected_row() {
             Some(i) => {
                 if i == 0 {
-                    self.items.len() - 1
+                    self.items().len() - 1
                 } else {
                     i - 1
                 }
             }
             None => 0,
         };
-        self.state.select(Some(i));
+        self.select(Some(i));
     }
 }
 
+impl Rows for StatefulTable {
+    fn items(&self) -> &[Vec<String>] {
+        &self.items
+    }
+
+    fn state(&mut self) -> &mut TableState {
+        &mut self.state
+    }
+
+    fn selected_row(&self) -> Option<usize> {
+        self.state.selected()
+    }
+
+    fn select(&mut self, row: Option<usize>) {
+        StatefulTable::select(self, row)
+    }
+
+    fn sort_next(&mut self) {
+        StatefulTable::sort_next(self)
+    }
+
+    fn sort_reverse(&mut self) {
+        StatefulTable::sort_reverse(self)
+    }
+
+    fn sort_by(&mut self, column: usize) {
+        StatefulTable::sort_by(self, column)
+    }
+}
+
+// The next row after `from` with a cell containing `needle`, ignoring case and
+// wrapping round the end of the table. `from` itself is checked last.
+pub fn find_row(items: &[Vec<String>], needle: &str, from: usize, forward: bool) -> Option<usize> {
+    if items.is_empty() || needle.is_empty() {
+        return None;
+    }
+    let needle = needle.to_lowercase();
+    let len = items.len();
+    (1..=len)
+        .map(|step| match forward {
+            true => (from + step) % len,
+            false => (from + len - step % len) % len,
+        })
+        .find(|row| items[*row].iter().any(|cell| cell.to_lowercase().contains(&needle)))
+}
+
 #[cfg(test)]
 mod tests {
   #[test]
@@ -246,6 +1061,152 @@ mod tests {
     assert_eq!(is_mbps(1001.0), false);
   }
 
+  #[test]
+  fn test_find_row() {
+    use super::find_row;
+
+    let items: Vec<Vec<String>> = vec![
+        vec!["10.0.0.1:443".to_string(), "ESTABLISHED".to_string()],
+        vec!["10.0.0.2:22".to_string(), "TIME_WAIT".to_string()],
+        vec!["10.0.0.3:443".to_string(), "ESTABLISHED".to_string()],
+    ];
+    assert_eq!(find_row(&items, ":443", 0, true), Some(2));
+    assert_eq!(find_row(&items, ":443", 2, true), Some(0));
+    assert_eq!(find_row(&items, ":443", 0, false), Some(2));
+    assert_eq!(find_row(&items, "time_wait", 1, true), Some(1));
+    assert_eq!(find_row(&items, "nope", 0, true), None);
+    assert_eq!(find_row(&[], "443", 0, true), None);
+  }
+
+  #[test]
+  fn test_reused_addresses() {
+    use super::{Options, Source, StatefulTable};
+    use crate::tcpdiag::tests::socket;
+    use std::time::{Duration, Instant};
+
+    let mut table = StatefulTable::blank(Options::default(), Source::Local);
+    let mut sock = socket("10.0.0.1:5000", "10.0.0.2:443");
+    let start = Instant::now();
+    let refresh = |table: &mut StatefulTable, sock: &super::DiagWithInode, after: u64| {
+        table.snapshot = vec![sock.clone()];
+        table.update_history(std::time::SystemTime::now(), start + Duration::from_secs(after));
+    };
+    sock.info.as_mut().unwrap().tcpi_bytes_sent = 5000;
+    refresh(&mut table, &sock, 0);
+    refresh(&mut table, &sock, 1);
+    assert_eq!(table.history[&sock.id()].samples, 3);
+
+    // a new connection from the same port, sending less than the old one had
+    sock.info.as_mut().unwrap().tcpi_bytes_sent = 100;
+    refresh(&mut table, &sock, 2);
+    assert_eq!(table.history[&sock.id()].samples, 2);
+    assert_eq!(table.history[&sock.id()].send_bps[0], 0);
+
+    // or with counters that happen to be higher, but a different inode
+    sock.inode = 2;
+    sock.info.as_mut().unwrap().tcpi_bytes_sent = 9000;
+    refresh(&mut table, &sock, 3);
+    assert_eq!(table.history[&sock.id()].samples, 2);
+  }
+
+  #[test]
+  fn test_sort_keeps_ties_in_order() {
+    use super::{Options, Source, StatefulTable};
+    use crate::tcpdiag::tests::socket;
+    use std::time::{Instant, SystemTime};
+
+    let options = Options { dns: false, ..Options::default() };
+    let mut table = StatefulTable::blank(options, Source::Local);
+    table.snapshot = (0..4)
+        .map(|i| socket(&format!("10.0.0.1:{}", 5000 + i), "10.0.0.2:443"))
+        .collect();
+    table.update_history(SystemTime::now(), Instant::now());
+    let order = |table: &StatefulTable| table.sockets.iter().map(|sock| sock.src.port()).collect::<Vec<_>>();
+    table.set_sort("state:desc").unwrap();
+    assert_eq!(order(&table), vec![5000, 5001, 5002, 5003]);
+    table.sort_reverse();
+    assert_eq!(order(&table), vec![5000, 5001, 5002, 5003]);
+  }
+
+  #[test]
+  fn test_history_pruned() {
+    use super::{Options, Source, StatefulTable};
+    use crate::tcpdiag::tests::socket;
+    use std::time::{Duration, Instant, SystemTime};
+
+    let mut table = StatefulTable::blank(Options::default(), Source::Local);
+    let gone = socket("10.0.0.1:5000", "10.0.0.2:443");
+    let stays = socket("10.0.0.1:5001", "10.0.0.2:443");
+    let start = Instant::now();
+    table.snapshot = vec![gone.clone(), stays.clone()];
+    table.update_history(SystemTime::now(), start);
+    for after in 1..=30 {
+        table.snapshot = vec![stays.clone()];
+        table.update_history(SystemTime::now(), start + Duration::from_secs(after));
+    }
+    // still there to step back to while paused
+    assert!(table.history.contains_key(&gone.id()));
+
+    table.update_history(SystemTime::now(), start + Duration::from_secs(31));
+    assert!(!table.history.contains_key(&gone.id()));
+    assert!(table.history.contains_key(&stays.id()));
+    assert_eq!(table.captured[&stays.id()], 31);
+    assert!(!table.captured.contains_key(&gone.id()));
+  }
+
+  #[test]
+  fn test_closed_history_trimmed() {
+    use super::{Options, Source, StatefulTable, CLOSED_SAMPLES};
+    use crate::tcpdiag::tests::socket;
+    use std::time::{Duration, Instant, SystemTime};
+
+    let options = Options { retention: Duration::from_secs(3600), ..Options::default() };
+    let mut table = StatefulTable::blank(options, Source::Local);
+    let sock = socket("10.0.0.1:5000", "10.0.0.2:443");
+    let start = Instant::now();
+    for after in 0..700 {
+        table.snapshot = vec![sock.clone()];
+        table.update_history(SystemTime::now(), start + Duration::from_secs(after));
+    }
+    table.snapshot.clear();
+    table.update_history(SystemTime::now(), start + Duration::from_secs(700));
+
+    assert!(table.history[&sock.id()].sampled_at.len() > CLOSED_SAMPLES);
+    let closed = &table.closed[0].history;
+    assert_eq!(closed.sampled_at.len(), CLOSED_SAMPLES);
+    assert_eq!(closed.rtt.len(), CLOSED_SAMPLES);
+    assert_eq!(closed.sampled_at[0], start + Duration::from_secs(699));
+  }
+
+  #[test]
+  fn test_history_as_of() {
+    use super::SocketHistory;
+    use crate::tcpdiag::TCPInfo;
+    use std::time::{Duration, Instant};
+
+    let info = TCPInfo { tcpi_rtt: 100, ..Default::default() };
+    let mut history = SocketHistory::new(&info, false, Instant::now());
+    let start = history.sampled_at[0];
+    for step in 1..4 {
+        history.sampled_at.push_front(start + Duration::from_secs(step));
+        history.rtt.push_front(100 + step);
+        for ring in [&mut history.send_bps, &mut history.recv_bps, &mut history.send_bytes, &mut history.recv_bytes,
+                     &mut history.congestion_window, &mut history.slow_start_threshold, &mut history.rtt_variance,
+                     &mut history.min_rtt, &mut history.retransmits, &mut history.pacing_rate, &mut history.delivery_rate] {
+            ring.push_front(0);
+        }
+        history.packet_loss.push_front(0);
+    }
+
+    let then = history.as_of(start + Duration::from_secs(1), &info);
+    assert_eq!(then.sampled_at.len(), 2);
+    assert_eq!(then.rtt, vec![101, 100]);
+    assert_eq!(then.send_bps.len(), 2);
+    // never empties out completely
+    assert_eq!(history.as_of(start - Duration::from_secs(5), &info).rtt.len(), 1);
+    assert_eq!(history.as_of(Instant::now() + Duration::from_secs(60), &info).rtt.len(), 4);
+  }
+
   #[test]
   fn test_friendly_transfer_str() {
       use super::friendly_transfer_str;
@@ -4,18 +4,26 @@ use nell::Message;
 use nell::Netlink;
 use nell::Socket;
 use nell::Family;
-use nell::ffi::diag::{inet_diag_msg, inet_diag_req_v2, SOCK_DIAG_BY_FAMILY, INET_DIAG_INFO};
-use nell::ffi::core::{NLM_F_DUMP, NLM_F_REQUEST, IPPROTO_TCP, AF_INET};
+use nell::ffi::diag::{inet_diag_msg, inet_diag_req_v2, SOCK_DIAG_BY_FAMILY, INET_DIAG_INFO, INET_DIAG_CONG};
+use nell::ffi::core::{NLM_F_DUMP, NLM_F_REQUEST, IPPROTO_TCP, AF_INET, AF_INET6};
+use nell::ffi::route::rtattr;
 use nell::sys::Bytes;
 use nell::err::Invalid;
 use std::net::{SocketAddr, IpAddr};
-use std::mem::transmute;
+use std::mem::{offset_of, size_of, transmute};
+use std::ptr;
 use std::convert::TryFrom;
 use std::vec::Vec;
 use std::string::String;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use crate::procs::{socket_owners, Process};
+use serde::{Deserialize, Serialize};
 
 #[repr(C)]
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct TCPInfo {
     pub tcpi_state:           u8,
     pub tcpi_ca_state:        u8,
@@ -67,42 +75,122 @@ pub struct TCPInfo {
     pub tcpi_delivered_ce:    u32,
     pub tcpi_bytes_sent:      u64,
     pub tcpi_bytes_retrains:  u64,
-    
-    /*
     pub tcpi_dsack_dups:      u32,
     pub tcpi_reord_seen:      u32,
     pub tcpi_rcv_ooopack:     u32,
-    pub tcpi_send_wnd:        u32,
-    */
+    pub tcpi_snd_wnd:         u32,
 }
 
 unsafe impl Bytes for TCPInfo{}
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct DiagWithInode<T = TCPInfo> {
-    family: u8,
-    pub src:    SocketAddr,
-    pub dst:    SocketAddr,
-    state:  u8,
-    pub inode:  u32,
-    pub info:   Option<T>,
+    #[allow(dead_code)]
+    pub family:  u8,
+    pub src:     SocketAddr,
+    pub dst:     SocketAddr,
+    pub state:   u8,
+    pub inode:   u32,
+    pub info:    Option<T>,
+    // how much of tcp_info the kernel sent, older kernels stop before the
+    // later fields. Taken as all of it from agents and recordings without it.
+    #[serde(default = "full_info_len")]
+    pub info_len: usize,
+    pub cc:      Option<String>,
+    pub process: Option<Process>,
+    // bytes waiting to be read and to be acked, or the accept queue and its
+    // limit for a listener
+    #[serde(default)]
+    pub rqueue:  u32,
+    #[serde(default)]
+    pub wqueue:  u32,
+    // which agent it came from in a fleet, see fleet.rs
+    #[serde(skip)]
+    pub host:    usize,
+}
+
+// A socket's address pair stays the same for its whole life, unlike its row in
+// the table or its inode (which is 0 once its process has closed it). In a fleet
+// the same pair can turn up on more than one host.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SocketId {
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    pub host: usize,
+}
+
+impl<T> DiagWithInode<T> {
+    pub fn id(&self) -> SocketId {
+        SocketId { src: self.src, dst: self.dst, host: self.host }
+    }
+
+    // For a listener tcp_info's unacked and sacked are the accept queue and its limit.
+    pub fn listening(&self) -> bool {
+        self.state == 10
+    }
+
+    // tcpi_snd_wnd only arrived in 5.4, before that it's one of the zeroed fields.
+    pub fn has_snd_wnd(&self) -> bool {
+        self.info_len >= offset_of!(TCPInfo, tcpi_snd_wnd) + size_of::<u32>()
+    }
+}
+
+fn full_info_len() -> usize {
+    size_of::<TCPInfo>()
 }
 
 fn diag_with_node(msg: &Message<inet_diag_msg>) -> Result<DiagWithInode, Invalid> {
     let src  = addr(msg.idiag_family, &msg.id.idiag_src, msg.id.idiag_sport)?;
     let dst  = addr(msg.idiag_family, &msg.id.idiag_dst, msg.id.idiag_dport)?;
-    let info = msg.info();
+    let (info, info_len, cc) = attributes(msg);
 
     Ok(DiagWithInode {
-        family: msg.idiag_family,
-        src:    src,
-        dst:    dst,
-        state:  msg.idiag_state,
-        info:   info,
-        inode:  msg.idiag_inode,
+        family:  msg.idiag_family,
+        src,
+        dst,
+        state:   msg.idiag_state,
+        info,
+        info_len,
+        inode:   msg.idiag_inode,
+        cc,
+        process: None,
+        rqueue:  msg.idiag_rqueue,
+        wqueue:  msg.idiag_wqueue,
+        host:    0,
     })
 }
 
+// nell's msg.info() only works when the kernel's tcp_info is at least as big as
+// ours, so walk the attributes ourselves. We need the congestion control name
+// out of here anyway.
+fn attributes(msg: &Message<inet_diag_msg>) -> (Option<TCPInfo>, usize, Option<String>) {
+    let (mut info, mut info_len, mut cc) = (None, 0, None);
+    let mut tail = msg.tail();
+    while let Some((attr, tail)) = tail.next::<rtattr>() {
+        match attr.rta_type {
+            INET_DIAG_INFO => {
+                let bytes = tail.bytes();
+                info = Some(tcp_info_from_bytes(bytes));
+                info_len = bytes.len();
+            }
+            INET_DIAG_CONG => cc = tail.asciiz().ok().map(|s| s.trim_end_matches('\0').to_string()),
+            _ => {}
+        }
+    }
+    (info, info_len, cc)
+}
+
+// Older kernels send a shorter tcp_info than we know about and newer ones a
+// longer one. Copy whatever overlaps and leave the rest zeroed.
+fn tcp_info_from_bytes(bytes: &[u8]) -> TCPInfo {
+    let mut info = TCPInfo::default();
+    let len = bytes.len().min(size_of::<TCPInfo>());
+    unsafe {
+        ptr::copy_nonoverlapping(bytes.as_ptr(), &mut info as *mut TCPInfo as *mut u8, len);
+    }
+    info
+}
+
 fn addr(family: u8, addr: &[u32; 4], port: u16) -> Result<SocketAddr, Invalid> {
     let octets: &[u8; 16] = unsafe { transmute(addr) };
     Ok(SocketAddr::new(match family {
@@ -112,33 +200,69 @@ fn addr(family: u8, addr: &[u32; 4], port: u16) -> Result<SocketAddr, Invalid> {
     }, port.to_be()))
 }
 
-pub fn gather_sockets() -> Vec<DiagWithInode> {
-    let mut socket = Socket::new(Family::INET_DIAG).unwrap();
-    let mut msg = Message::<inet_diag_req_v2>::new(SOCK_DIAG_BY_FAMILY);
-    msg.set_flags(NLM_F_REQUEST | NLM_F_DUMP);
-    msg.sdiag_family = AF_INET;
-    msg.sdiag_protocol = IPPROTO_TCP;
-    msg.idiag_states = !0;
-    msg.idiag_ext = 1 << (INET_DIAG_INFO as u8 - 1);
+// Which address families to ask the kernel about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    IPv4,
+    IPv6,
+    All,
+}
 
-    socket.send(&msg).unwrap();
+impl Protocol {
+    pub fn parse(name: &str) -> Result<Protocol, String> {
+        match name {
+            "ipv4" | "4" => Ok(Protocol::IPv4),
+            "ipv6" | "6" => Ok(Protocol::IPv6),
+            "all" => Ok(Protocol::All),
+            _ => Err(format!("unknown protocol '{}', expected ipv4, ipv6 or all", name)),
+        }
+    }
+
+    fn families(self) -> &'static [u8] {
+        match self {
+            Protocol::IPv4 => &[AF_INET],
+            Protocol::IPv6 => &[AF_INET6],
+            Protocol::All => &[AF_INET, AF_INET6],
+        }
+    }
+}
 
+pub fn gather_sockets(protocol: Protocol) -> Vec<DiagWithInode> {
+    let owners = socket_owners();
     let mut sockets: Vec<DiagWithInode> = Vec::new();
-    while let Netlink::Msg(msg) = socket.recv::<inet_diag_msg>().unwrap() {
-        let sockdiag = diag_with_node(&msg).unwrap();
-        match &sockdiag.info {
-            Some(info) => {
-                // LISTEN state is pretty pointless for this. It really only serves as a receive
-                // queue to create NEW sockets for clients. We will get the info from those newly
-                // created sockets, not the LISTEN one.
-                if info.tcpi_state != 10 {
-                    sockets.push(sockdiag)
-                }
-            },
-            None => continue
+    for family in protocol.families() {
+        let mut socket = Socket::new(Family::INET_DIAG).unwrap();
+        let mut msg = Message::<inet_diag_req_v2>::new(SOCK_DIAG_BY_FAMILY);
+        msg.set_flags(NLM_F_REQUEST | NLM_F_DUMP);
+        msg.sdiag_family = *family;
+        msg.sdiag_protocol = IPPROTO_TCP;
+        msg.idiag_states = !0;
+        msg.idiag_ext = 1 << (INET_DIAG_INFO as u8 - 1) | 1 << (INET_DIAG_CONG as u8 - 1);
+
+        socket.send(&msg).unwrap();
+
+        while let Netlink::Msg(msg) = socket.recv::<inet_diag_msg>().unwrap() {
+            let mut sockdiag = diag_with_node(&msg).unwrap();
+            sockdiag.process = owners.get(&sockdiag.inode).cloned();
+            // TIME_WAIT sockets come without a tcp_info, so they're left out
+            if sockdiag.info.is_some() {
+                sockets.push(sockdiag)
+            }
         }
     }
-    return sockets;
+    sockets
+}
+
+// Moves this process into another network namespace, so everything after this
+// sees that namespace's sockets. `name` is one made with `ip netns add`, or a
+// path to a namespace file such as /proc/<pid>/ns/net.
+pub fn enter_namespace(name: &str) -> Result<(), String> {
+    let path = if name.contains('/') { name.to_string() } else { format!("/var/run/netns/{}", name) };
+    let file = File::open(&path).map_err(|err| format!("namespace {}: {}", path, err))?;
+    if unsafe { libc::setns(file.as_raw_fd(), libc::CLONE_NEWNET) } != 0 {
+        return Err(format!("namespace {}: {}", path, io::Error::last_os_error()));
+    }
+    Ok(())
 }
 
 pub enum TCP_STATE {
@@ -174,26 +298,76 @@ impl TCP_STATE {
             11 => TCP_STATE::CLOSING,
             12 => TCP_STATE::NEW_SYN_REC,
             13 => TCP_STATE::MAX_STATES,
-            _ => panic!("dont do it")
+            _ => TCP_STATE::UNKNOWN
         }
     }
 
-    pub fn to_string(&self) -> String {
-        match self {
-            TCP_STATE::UNKNOWN => String::from("UNKNOWN"),
-            TCP_STATE::ESTABLISHED => String::from("ESTABLISHED"),
-            TCP_STATE::SYN_SENT => String::from("SYN_SENT"),
-            TCP_STATE::SYN_RECV => String::from("SYN_RECV"),
-            TCP_STATE::FIN_WAIT1 => String::from( "FIN_WAIT1"),
-            TCP_STATE::FIN_WAIT2 => String::from( "FIN_WAIT2"),
-            TCP_STATE::TIME_WAIT => String::from( "TIME_WAIT"),
-            TCP_STATE::CLOSE => String::from("CLOSE"),
-            TCP_STATE::CLOSE_WAIT => String::from("CLOSE_WAIT"),
-            TCP_STATE::LAST_ACK => String::from("LAST_ACK"),
-            TCP_STATE::LISTEN => String::from("LISTEN"),
-            TCP_STATE::CLOSING => String::from("CLOSING"),
-            TCP_STATE::NEW_SYN_REC => String::from("NEW_SYN_REC"),
-            TCP_STATE::MAX_STATES => String::from("MAX_STATES")
+    // Accepts the names we display as well as the lowercase/dashed spellings
+    // people tend to type, e.g. "established", "time-wait" or ss' "estab".
+    pub fn from_name(name: &str) -> Option<u8> {
+        let name = name.to_ascii_uppercase().replace('-', "_");
+        let state = match name.as_str() {
+            "ESTABLISHED" | "ESTAB" => 1,
+            "SYN_SENT" => 2,
+            "SYN_RECV" => 3,
+            "FIN_WAIT1" | "FIN_WAIT_1" => 4,
+            "FIN_WAIT2" | "FIN_WAIT_2" => 5,
+            "TIME_WAIT" => 6,
+            "CLOSE" => 7,
+            "CLOSE_WAIT" => 8,
+            "LAST_ACK" => 9,
+            "LISTEN" => 10,
+            "CLOSING" => 11,
+            "NEW_SYN_REC" | "NEW_SYN_RECV" => 12,
+            _ => return None,
+        };
+        Some(state)
+    }
+}
+
+impl fmt::Display for TCP_STATE {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TCP_STATE::UNKNOWN => "UNKNOWN",
+            TCP_STATE::ESTABLISHED => "ESTABLISHED",
+            TCP_STATE::SYN_SENT => "SYN_SENT",
+            TCP_STATE::SYN_RECV => "SYN_RECV",
+            TCP_STATE::FIN_WAIT1 => "FIN_WAIT1",
+            TCP_STATE::FIN_WAIT2 => "FIN_WAIT2",
+            TCP_STATE::TIME_WAIT => "TIME_WAIT",
+            TCP_STATE::CLOSE => "CLOSE",
+            TCP_STATE::CLOSE_WAIT => "CLOSE_WAIT",
+            TCP_STATE::LAST_ACK => "LAST_ACK",
+            TCP_STATE::LISTEN => "LISTEN",
+            TCP_STATE::CLOSING => "CLOSING",
+            TCP_STATE::NEW_SYN_REC => "NEW_SYN_REC",
+            TCP_STATE::MAX_STATES => "MAX_STATES"
+        };
+        f.write_str(name)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::procs::Process;
+
+    // An established socket owned by nginx, for tests to change what they need
+    // with `..socket(src, dst)`.
+    pub fn socket(src: &str, dst: &str) -> DiagWithInode {
+        DiagWithInode {
+            family:   2,
+            src:      src.parse().unwrap(),
+            dst:      dst.parse().unwrap(),
+            state:    1,
+            inode:    1,
+            info:     Some(TCPInfo { tcpi_state: 1, tcpi_rtt: 2000, tcpi_snd_cwnd: 10, ..Default::default() }),
+            info_len: size_of::<TCPInfo>(),
+            cc:       Some("cubic".to_string()),
+            process:  Some(Process { pid: 42, comm: "nginx".to_string() }),
+            rqueue:   0,
+            wqueue:   0,
+            host:     0,
         }
     }
 }
@@ -0,0 +1,408 @@
+use crate::config::{Config, StyleConfig};
+use ratatui::style::{Color, Modifier, Style};
+use std::env;
+
+// Every style the UI uses, by what it's for rather than what colour it is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    pub text: Style,
+    pub header: Style,
+    pub selected: Style,
+    pub title: Style,
+    pub heading: Style,
+    pub muted: Style,
+    pub changed: Style,
+    pub key: Style,
+    pub prompt: Style,
+    pub warning: Style,
+    pub critical: Style,
+    // rows for sockets that just showed up, are on their way out, or crossed
+    // one of the [highlight] thresholds
+    pub new_row: Style,
+    pub closing_row: Style,
+    pub alert_row: Style,
+    pub chart_title: Style,
+    pub chart_axis: Style,
+    // the main line on a chart, a second one to compare it with, anything that
+    // signals trouble (ssthresh, retransmits), and the faint rttvar band
+    pub chart_primary: Style,
+    pub chart_secondary: Style,
+    pub chart_alert: Style,
+    pub chart_band: Style,
+}
+
+pub const THEMES: [&str; 3] = ["dark", "light", "high-contrast"];
+
+fn fg(color: Color) -> Style {
+    Style::default().fg(color)
+}
+
+fn bold(style: Style) -> Style {
+    style.add_modifier(Modifier::BOLD)
+}
+
+impl Theme {
+    pub fn dark() -> Theme {
+        Theme {
+            text: fg(Color::White),
+            header: fg(Color::Yellow),
+            selected: bold(fg(Color::Yellow)),
+            title: bold(fg(Color::Magenta)),
+            heading: bold(fg(Color::Magenta)).add_modifier(Modifier::UNDERLINED),
+            muted: fg(Color::DarkGray),
+            changed: bold(fg(Color::Yellow)),
+            key: bold(Style::default().bg(Color::Gray).fg(Color::Blue)),
+            prompt: bold(fg(Color::Yellow)),
+            warning: fg(Color::Yellow),
+            critical: bold(Style::default().bg(Color::Red).fg(Color::White)),
+            new_row: fg(Color::Green),
+            closing_row: fg(Color::DarkGray).add_modifier(Modifier::DIM),
+            alert_row: bold(fg(Color::Red)),
+            chart_title: bold(fg(Color::Cyan)),
+            chart_axis: fg(Color::Gray),
+            chart_primary: fg(Color::Yellow),
+            chart_secondary: fg(Color::Cyan),
+            chart_alert: fg(Color::Red),
+            chart_band: fg(Color::DarkGray),
+        }
+    }
+
+    // Yellow and white disappear on a pale background, so everything here is
+    // either the terminal's own foreground or a dark colour.
+    pub fn light() -> Theme {
+        Theme {
+            text: Style::default(),
+            header: bold(fg(Color::Blue)),
+            selected: bold(Style::default().bg(Color::LightBlue).fg(Color::Black)),
+            title: bold(fg(Color::Magenta)),
+            heading: bold(fg(Color::Magenta)).add_modifier(Modifier::UNDERLINED),
+            muted: fg(Color::Gray),
+            changed: bold(fg(Color::Blue)),
+            key: bold(Style::default().bg(Color::Blue).fg(Color::White)),
+            prompt: bold(fg(Color::Blue)),
+            warning: bold(fg(Color::Rgb(0xa0, 0x80, 0x00))),
+            critical: bold(Style::default().bg(Color::Red).fg(Color::White)),
+            new_row: fg(Color::Green),
+            closing_row: fg(Color::Gray).add_modifier(Modifier::DIM),
+            alert_row: bold(fg(Color::Red)),
+            chart_title: bold(fg(Color::Blue)),
+            chart_axis: fg(Color::DarkGray),
+            chart_primary: fg(Color::Blue),
+            chart_secondary: fg(Color::Magenta),
+            chart_alert: fg(Color::Red),
+            chart_band: fg(Color::Gray),
+        }
+    }
+
+    pub fn high_contrast() -> Theme {
+        Theme {
+            text: fg(Color::White),
+            header: bold(fg(Color::White)).add_modifier(Modifier::UNDERLINED),
+            selected: bold(Style::default().bg(Color::White).fg(Color::Black)),
+            title: bold(fg(Color::White)),
+            heading: bold(fg(Color::White)).add_modifier(Modifier::UNDERLINED),
+            muted: fg(Color::Gray),
+            changed: bold(fg(Color::LightYellow)),
+            key: bold(Style::default().bg(Color::White).fg(Color::Black)),
+            prompt: bold(fg(Color::White)),
+            warning: bold(Style::default().bg(Color::Yellow).fg(Color::Black)),
+            critical: bold(Style::default().bg(Color::Red).fg(Color::White)),
+            new_row: bold(fg(Color::LightGreen)),
+            closing_row: fg(Color::Gray).add_modifier(Modifier::DIM),
+            alert_row: bold(fg(Color::LightRed)),
+            chart_title: bold(fg(Color::White)),
+            chart_axis: fg(Color::White),
+            chart_primary: fg(Color::LightYellow),
+            chart_secondary: fg(Color::LightCyan),
+            chart_alert: fg(Color::LightRed),
+            chart_band: fg(Color::Gray),
+        }
+    }
+
+    pub fn named(name: &str) -> Option<Theme> {
+        match name {
+            "dark" => Some(Theme::dark()),
+            "light" => Some(Theme::light()),
+            "high-contrast" => Some(Theme::high_contrast()),
+            _ => None,
+        }
+    }
+
+    // `theme` names either a shipped theme or one from `[themes.<name>]`, which
+    // starts from its `base` (dark unless given) and overrides slots on top.
+    pub fn from_config(config: &Config) -> Result<Theme, String> {
+        let name = config.theme.as_deref().unwrap_or("dark");
+        let custom = config.themes.as_ref().and_then(|themes| themes.get(name));
+        let theme = match custom {
+            None => Theme::named(name).ok_or_else(|| {
+                format!("unknown theme '{}', expected one of {} or a [themes.{}] table", name, THEMES.join(", "), name)
+            })?,
+            Some(custom) => {
+                let base = custom.base.as_deref().unwrap_or("dark");
+                let mut theme = Theme::named(base).ok_or(format!("unknown base theme '{}' for theme '{}'", base, name))?;
+                for (slot, style) in &custom.slots {
+                    let style = parse_style(style).map_err(|err| format!("{} in theme '{}'", err, name))?;
+                    *theme.slot_mut(slot).ok_or(format!("unknown style '{}' in theme '{}'", slot, name))? = style;
+                }
+                theme
+            }
+        };
+        Ok(theme.for_depth(Depth::detect()))
+    }
+
+    fn slot_mut(&mut self, name: &str) -> Option<&mut Style> {
+        let slot = match name {
+            "text" => &mut self.text,
+            "header" => &mut self.header,
+            "selected" => &mut self.selected,
+            "title" => &mut self.title,
+            "heading" => &mut self.heading,
+            "muted" => &mut self.muted,
+            "changed" => &mut self.changed,
+            "key" => &mut self.key,
+            "prompt" => &mut self.prompt,
+            "warning" => &mut self.warning,
+            "critical" => &mut self.critical,
+            "new_row" => &mut self.new_row,
+            "closing_row" => &mut self.closing_row,
+            "alert_row" => &mut self.alert_row,
+            "chart_title" => &mut self.chart_title,
+            "chart_axis" => &mut self.chart_axis,
+            "chart_primary" => &mut self.chart_primary,
+            "chart_secondary" => &mut self.chart_secondary,
+            "chart_alert" => &mut self.chart_alert,
+            "chart_band" => &mut self.chart_band,
+            _ => return None,
+        };
+        Some(slot)
+    }
+
+    fn slots_mut(&mut self) -> [&mut Style; 20] {
+        [
+            &mut self.text,
+            &mut self.header,
+            &mut self.selected,
+            &mut self.title,
+            &mut self.heading,
+            &mut self.muted,
+            &mut self.changed,
+            &mut self.key,
+            &mut self.prompt,
+            &mut self.warning,
+            &mut self.critical,
+            &mut self.new_row,
+            &mut self.closing_row,
+            &mut self.alert_row,
+            &mut self.chart_title,
+            &mut self.chart_axis,
+            &mut self.chart_primary,
+            &mut self.chart_secondary,
+            &mut self.chart_alert,
+            &mut self.chart_band,
+        ]
+    }
+
+    pub fn for_depth(mut self, depth: Depth) -> Theme {
+        for style in self.slots_mut() {
+            *style = match depth {
+                Depth::Full => *style,
+                Depth::Ansi16 => Style {
+                    fg: style.fg.map(to_ansi16),
+                    bg: style.bg.map(to_ansi16),
+                    ..*style
+                },
+                // anything that stood out by its background gets reversed instead
+                Depth::None => {
+                    let reversed = match style.bg {
+                        Some(Color::Reset) | None => Modifier::empty(),
+                        Some(_) => Modifier::REVERSED,
+                    };
+                    Style { fg: None, bg: None, ..*style }.add_modifier(reversed)
+                }
+            };
+        }
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Depth {
+    None,
+    Ansi16,
+    Full,
+}
+
+impl Depth {
+    // NO_COLOR (https://no-color.org) wins over everything. Otherwise anything
+    // that doesn't say it can do 256 colours or more only gets the basic 16.
+    pub fn detect() -> Depth {
+        let var = |name: &str| env::var(name).unwrap_or_default();
+        Depth::from_env(&var("NO_COLOR"), &var("TERM"), &var("COLORTERM"))
+    }
+
+    fn from_env(no_color: &str, term: &str, colorterm: &str) -> Depth {
+        if !no_color.is_empty() || term == "dumb" {
+            return Depth::None;
+        }
+        if colorterm == "truecolor" || colorterm == "24bit" || term.contains("256color") || term.contains("direct") {
+            return Depth::Full;
+        }
+        Depth::Ansi16
+    }
+}
+
+const ANSI16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+fn to_ansi16(color: Color) -> Color {
+    let (r, g, b) = match color {
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Indexed(i) if i < 16 => return ANSI16[i as usize].0,
+        // the 6x6x6 cube
+        Color::Indexed(i) if i < 232 => {
+            let level = |n: u8| if n == 0 { 0 } else { 55 + n * 40 };
+            let i = i - 16;
+            (level(i / 36), level(i / 6 % 6), level(i % 6))
+        }
+        // the grey ramp
+        Color::Indexed(i) => {
+            let level = 8 + (i - 232) * 10;
+            (level, level, level)
+        }
+        other => return other,
+    };
+    let distance = |(pr, pg, pb): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, pr) + d(g, pg) + d(b, pb)
+    };
+    ANSI16.iter().min_by_key(|(_, rgb)| distance(*rgb)).unwrap().0
+}
+
+pub fn parse_color(name: &str) -> Result<Color, String> {
+    let color = match name.to_lowercase().replace(['-', '_', ' '], "").as_str() {
+        "reset" | "default" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        hex if hex.starts_with('#') && hex.len() == 7 => {
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+            match (channel(1), channel(3), channel(5)) {
+                (Ok(r), Ok(g), Ok(b)) => Color::Rgb(r, g, b),
+                _ => return Err(format!("bad colour '{}'", name)),
+            }
+        }
+        index => match index.parse::<u8>() {
+            Ok(i) => Color::Indexed(i),
+            Err(_) => return Err(format!("unknown colour '{}'", name)),
+        },
+    };
+    Ok(color)
+}
+
+fn parse_style(config: &StyleConfig) -> Result<Style, String> {
+    let mut style = Style::default();
+    if let Some(color) = &config.fg {
+        style = style.fg(parse_color(color)?);
+    }
+    if let Some(color) = &config.bg {
+        style = style.bg(parse_color(color)?);
+    }
+    for modifier in config.modifiers.iter().flatten() {
+        style = style.add_modifier(match modifier.as_str() {
+            "bold" => Modifier::BOLD,
+            "dim" => Modifier::DIM,
+            "italic" => Modifier::ITALIC,
+            "underlined" => Modifier::UNDERLINED,
+            "reversed" => Modifier::REVERSED,
+            _ => return Err(format!("unknown modifier '{}'", modifier)),
+        });
+    }
+    Ok(style)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("Light-Blue"), Ok(Color::LightBlue));
+        assert_eq!(parse_color("#ff8000"), Ok(Color::Rgb(255, 128, 0)));
+        assert_eq!(parse_color("208"), Ok(Color::Indexed(208)));
+        assert!(parse_color("#ff80").is_err());
+        assert!(parse_color("chartreuse").is_err());
+    }
+
+    #[test]
+    fn test_depth() {
+        assert_eq!(Depth::from_env("1", "xterm-256color", "truecolor"), Depth::None);
+        assert_eq!(Depth::from_env("", "dumb", ""), Depth::None);
+        assert_eq!(Depth::from_env("", "xterm-256color", ""), Depth::Full);
+        assert_eq!(Depth::from_env("", "linux", ""), Depth::Ansi16);
+
+        let theme = Theme::light().for_depth(Depth::Ansi16);
+        assert_eq!(theme.warning.fg, Some(Color::Yellow));
+        let theme = Theme::dark().for_depth(Depth::None);
+        assert_eq!(theme.selected, Style::default().add_modifier(Modifier::BOLD));
+        assert_eq!(theme.critical, Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED));
+    }
+
+    #[test]
+    fn test_to_ansi16() {
+        assert_eq!(to_ansi16(Color::Rgb(250, 10, 10)), Color::LightRed);
+        assert_eq!(to_ansi16(Color::Indexed(9)), Color::LightRed);
+        assert_eq!(to_ansi16(Color::Indexed(16)), Color::Black);
+        assert_eq!(to_ansi16(Color::Indexed(255)), Color::Gray);
+        assert_eq!(to_ansi16(Color::Rgb(255, 255, 255)), Color::White);
+        assert_eq!(to_ansi16(Color::Cyan), Color::Cyan);
+    }
+
+    #[test]
+    fn test_custom_theme() {
+        let config: Config = toml::from_str(
+            r##"
+            theme = "mine"
+            [themes.mine]
+            base = "light"
+            selected = { fg = "black", bg = "#ffd700", modifiers = ["bold"] }
+            "##,
+        )
+        .unwrap();
+        let theme = Theme::from_config(&config).unwrap();
+        assert_eq!(theme.header, Theme::light().for_depth(Depth::detect()).header);
+
+        let bad: Config = toml::from_str("theme = \"mine\"\n[themes.mine]\nselcted = { fg = \"red\" }").unwrap();
+        assert!(Theme::from_config(&bad).is_err());
+        let unknown: Config = toml::from_str("theme = \"solarized\"").unwrap();
+        assert!(Theme::from_config(&unknown).is_err());
+    }
+}
@@ -0,0 +1,392 @@
+use crate::cli::clock;
+use crate::columns::SortKey;
+use crate::filter::Filter;
+use crate::table::{friendly_transfer_str, Rows, SortBy, StatefulTable};
+use crate::tcpdiag::{DiagWithInode, TCP_STATE};
+use ratatui::widgets::TableState;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tab {
+    Sockets,
+    Listeners,
+    Hosts,
+    Processes,
+    Closed,
+}
+
+impl Tab {
+    pub const ALL: [Tab; 5] = [Tab::Sockets, Tab::Listeners, Tab::Hosts, Tab::Processes, Tab::Closed];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Tab::Sockets => "Sockets",
+            Tab::Listeners => "Listeners",
+            Tab::Hosts => "Hosts",
+            Tab::Processes => "Processes",
+            Tab::Closed => "Closed",
+        }
+    }
+
+    fn columns(&self) -> &'static [&'static str] {
+        match self {
+            Tab::Sockets => &[],
+            Tab::Listeners => &["Address", "Port", "Process", "Accept queue", "Conns"],
+            Tab::Hosts => &["Host", "Address", "Conns", "Send", "Recv", "Avg RTT ms", "Retrans"],
+            Tab::Processes => &["PID", "Command", "Conns", "Listening", "Send", "Recv"],
+            Tab::Closed => &["Closed", "Source", "Dest", "Process", "Last state", "Sent", "Received"],
+        }
+    }
+
+    fn index(&self) -> usize {
+        Tab::ALL.iter().position(|tab| tab == self).unwrap()
+    }
+
+    pub fn next(&self) -> Tab {
+        Tab::ALL[(self.index() + 1) % Tab::ALL.len()]
+    }
+
+    pub fn previous(&self) -> Tab {
+        Tab::ALL[(self.index() + Tab::ALL.len() - 1) % Tab::ALL.len()]
+    }
+}
+
+// What a row in one of the other tabs stands for. It's how the selection is
+// found again after a refresh, and what the drill-down view shows.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    Listener(SocketAddr),
+    Host(IpAddr),
+    // pid 0 collects the sockets we couldn't find an owner for
+    Process(u32),
+    Closed(u64),
+}
+
+impl Target {
+    // Whether a socket is one of this row's connections. Anything on a listener's
+    // port counts as accepted by it, it's rare for an outgoing connection to be
+    // bound to the same port.
+    pub fn contains(&self, sock: &DiagWithInode) -> bool {
+        match self {
+            Target::Listener(addr) => {
+                sock.src.port() == addr.port() && (addr.ip().is_unspecified() || sock.src.ip() == addr.ip())
+            }
+            Target::Host(ip) => sock.dst.ip() == *ip,
+            Target::Process(pid) => owner(sock) == *pid,
+            Target::Closed(_) => false,
+        }
+    }
+}
+
+fn owner(sock: &DiagWithInode) -> u32 {
+    sock.process.as_ref().map(|process| process.pid).unwrap_or(0)
+}
+
+fn command(sock: &DiagWithInode) -> String {
+    sock.process.as_ref().map(|process| process.comm.clone()).unwrap_or_else(|| "-".to_string())
+}
+
+struct ListRow {
+    target: Target,
+    cells: Vec<String>,
+    keys: Vec<SortKey>,
+}
+
+// The table behind every tab but Sockets. Rows are built from whatever the socket
+// table is showing, so they pause and step along with it, and each tab has its
+// own filter (applied to sockets before they're grouped), sort and selection.
+pub struct Listing {
+    pub tab: Tab,
+    pub state: TableState,
+    pub items: Vec<Vec<String>>,
+    targets: Vec<Target>,
+    pub filter: Filter,
+    pub sort: Option<SortBy>,
+    selected: Option<Target>,
+}
+
+impl Listing {
+    pub fn new(tab: Tab) -> Listing {
+        Listing {
+            tab,
+            state: TableState::default(),
+            items: Vec::new(),
+            targets: Vec::new(),
+            filter: Filter::parse("").unwrap(),
+            sort: None,
+            selected: None,
+        }
+    }
+
+    pub fn titles(&self) -> &'static [&'static str] {
+        self.tab.columns()
+    }
+
+    pub fn selected_target(&self) -> Option<Target> {
+        self.selected
+    }
+
+    pub fn rebuild(&mut self, table: &StatefulTable) {
+        let mut rows = match self.tab {
+            Tab::Sockets => Vec::new(),
+            Tab::Listeners => self.listener_rows(table),
+            Tab::Hosts => self.host_rows(table),
+            Tab::Processes => self.process_rows(table),
+            Tab::Closed => self.closed_rows(table),
+        };
+        if let Some(sort) = self.sort {
+            let column = sort.column;
+            match sort.descending {
+                true => rows.sort_by(|a, b| b.keys[column].compare(&a.keys[column])),
+                false => rows.sort_by(|a, b| a.keys[column].compare(&b.keys[column])),
+            }
+        }
+        self.targets = rows.iter().map(|row| row.target).collect();
+        self.items = rows.into_iter().map(|row| row.cells).collect();
+
+        // same as the socket table: follow the selection, or stay on the row
+        let row = match self.selected.and_then(|target| self.targets.iter().position(|t| *t == target)) {
+            Some(row) => Some(row),
+            None => self.state.selected().map(|row| row.min(self.items.len().saturating_sub(1))),
+        };
+        Rows::select(self, row);
+    }
+
+    fn listener_rows(&self, table: &StatefulTable) -> Vec<ListRow> {
+        let sockets = table.shown().0;
+        table
+            .shown_listeners()
+            .iter()
+            .filter(|listener| self.filter.matches(listener))
+            .map(|listener| {
+                let target = Target::Listener(listener.src);
+                let info = listener.info.as_ref().unwrap();
+                let conns = sockets.iter().filter(|sock| target.contains(sock)).count();
+                ListRow {
+                    target,
+                    cells: vec![
+                        listener.src.ip().to_string(),
+                        listener.src.port().to_string(),
+                        command(listener),
+                        format!("{}/{}", info.tcpi_unacked, info.tcpi_sacked),
+                        conns.to_string(),
+                    ],
+                    keys: vec![
+                        SortKey::Text(listener.src.ip().to_string()),
+                        SortKey::Num(listener.src.port() as f64),
+                        SortKey::Text(command(listener)),
+                        SortKey::Num(info.tcpi_unacked as f64),
+                        SortKey::Num(conns as f64),
+                    ],
+                }
+            })
+            .collect()
+    }
+
+    fn host_rows(&self, table: &StatefulTable) -> Vec<ListRow> {
+        let (sockets, history) = table.shown();
+        // conns, send, recv, rtt total, retransmits
+        let mut hosts: BTreeMap<IpAddr, (usize, u64, u64, u64, u64)> = BTreeMap::new();
+        for sock in sockets.iter().filter(|sock| self.filter.matches(sock)) {
+            let info = sock.info.as_ref().unwrap();
+            let history = &history[&sock.id()];
+            let host = hosts.entry(sock.dst.ip()).or_default();
+            host.0 += 1;
+            host.1 += history.send_bps[0];
+            host.2 += history.recv_bps[0];
+            host.3 += info.tcpi_rtt as u64;
+            host.4 += info.tcpi_total_retrans as u64;
+        }
+        hosts
+            .into_iter()
+            .map(|(ip, (conns, send, recv, rtt, retrans))| {
+                let name = table.resolve_name(ip);
+                let rtt_ms = rtt as f64 / conns as f64 / 1000.0;
+                ListRow {
+                    target: Target::Host(ip),
+                    cells: vec![
+                        name.clone(),
+                        ip.to_string(),
+                        conns.to_string(),
+                        friendly_transfer_str(send),
+                        friendly_transfer_str(recv),
+                        format!("{:.2}", rtt_ms),
+                        retrans.to_string(),
+                    ],
+                    keys: vec![
+                        SortKey::Text(name),
+                        SortKey::Text(ip.to_string()),
+                        SortKey::Num(conns as f64),
+                        SortKey::Num(send as f64),
+                        SortKey::Num(recv as f64),
+                        SortKey::Num(rtt_ms),
+                        SortKey::Num(retrans as f64),
+                    ],
+                }
+            })
+            .collect()
+    }
+
+    fn process_rows(&self, table: &StatefulTable) -> Vec<ListRow> {
+        let (sockets, history) = table.shown();
+        // command, conns, listening, send, recv
+        let mut procs: BTreeMap<u32, (String, usize, usize, u64, u64)> = BTreeMap::new();
+        for sock in sockets.iter().filter(|sock| self.filter.matches(sock)) {
+            let history = &history[&sock.id()];
+            let entry = procs.entry(owner(sock)).or_insert_with(|| (command(sock), 0, 0, 0, 0));
+            entry.1 += 1;
+            entry.3 += history.send_bps[0];
+            entry.4 += history.recv_bps[0];
+        }
+        for listener in table.shown_listeners().iter().filter(|sock| self.filter.matches(sock)) {
+            procs.entry(owner(listener)).or_insert_with(|| (command(listener), 0, 0, 0, 0)).2 += 1;
+        }
+        procs
+            .into_iter()
+            .map(|(pid, (comm, conns, listening, send, recv))| {
+                let shown_pid = match pid {
+                    0 => "-".to_string(),
+                    pid => pid.to_string(),
+                };
+                ListRow {
+                    target: Target::Process(pid),
+                    cells: vec![
+                        shown_pid,
+                        comm.clone(),
+                        conns.to_string(),
+                        listening.to_string(),
+                        friendly_transfer_str(send),
+                        friendly_transfer_str(recv),
+                    ],
+                    keys: vec![
+                        SortKey::Num(pid as f64),
+                        SortKey::Text(comm),
+                        SortKey::Num(conns as f64),
+                        SortKey::Num(listening as f64),
+                        SortKey::Num(send as f64),
+                        SortKey::Num(recv as f64),
+                    ],
+                }
+            })
+            .collect()
+    }
+
+    fn closed_rows(&self, table: &StatefulTable) -> Vec<ListRow> {
+        table
+            .closed
+            .iter()
+            .filter(|closed| self.filter.matches(&closed.socket))
+            .map(|closed| {
+                let sock = &closed.socket;
+                let info = sock.info.as_ref().unwrap();
+                ListRow {
+                    target: Target::Closed(closed.seq),
+                    cells: vec![
+                        clock(closed.at),
+                        sock.src.to_string(),
+                        sock.dst.to_string(),
+                        command(sock),
+                        TCP_STATE::from_u8(info.tcpi_state).to_string(),
+                        info.tcpi_bytes_sent.to_string(),
+                        info.tcpi_bytes_received.to_string(),
+                    ],
+                    keys: vec![
+                        SortKey::Num(closed.seq as f64),
+                        SortKey::Text(sock.src.to_string()),
+                        SortKey::Text(sock.dst.to_string()),
+                        SortKey::Text(command(sock)),
+                        SortKey::Num(info.tcpi_state as f64),
+                        SortKey::Num(info.tcpi_bytes_sent as f64),
+                        SortKey::Num(info.tcpi_bytes_received as f64),
+                    ],
+                }
+            })
+            .collect()
+    }
+}
+
+impl Rows for Listing {
+    fn items(&self) -> &[Vec<String>] {
+        &self.items
+    }
+
+    fn state(&mut self) -> &mut TableState {
+        &mut self.state
+    }
+
+    fn selected_row(&self) -> Option<usize> {
+        self.state.selected()
+    }
+
+    fn select(&mut self, row: Option<usize>) {
+        let row = row.filter(|row| *row < self.items.len());
+        self.selected = row.map(|row| self.targets[row]);
+        self.state.select(row);
+    }
+
+    // Same stepping as the socket table, left to right and then unsorted.
+    fn sort_next(&mut self) {
+        let descending = self.sort.map(|sort| sort.descending).unwrap_or(false);
+        let column = match self.sort {
+            None => 0,
+            Some(sort) => sort.column + 1,
+        };
+        self.sort = match column < self.titles().len() {
+            true => Some(SortBy { column, descending }),
+            false => None,
+        };
+    }
+
+    fn sort_reverse(&mut self) {
+        if let Some(sort) = self.sort.as_mut() {
+            sort.descending = !sort.descending;
+        }
+    }
+
+    fn sort_by(&mut self, column: usize) {
+        self.sort = match self.sort {
+            Some(sort) if sort.column == column => Some(SortBy { column, descending: !sort.descending }),
+            _ => Some(SortBy { column, descending: false }),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tcpdiag::tests::socket;
+
+    fn sock(src: &str, dst: &str, pid: Option<u32>) -> DiagWithInode {
+        DiagWithInode {
+            info: None,
+            info_len: 0,
+            cc: None,
+            process: pid.map(|pid| crate::procs::Process { pid, comm: "nginx".to_string() }),
+            ..socket(src, dst)
+        }
+    }
+
+    #[test]
+    fn test_target_contains() {
+        let accepted = sock("10.0.0.1:443", "192.168.1.5:50000", Some(42));
+        let outgoing = sock("10.0.0.1:50001", "192.168.1.5:443", None);
+
+        assert!(Target::Listener("0.0.0.0:443".parse().unwrap()).contains(&accepted));
+        assert!(Target::Listener("10.0.0.1:443".parse().unwrap()).contains(&accepted));
+        assert!(!Target::Listener("10.0.0.2:443".parse().unwrap()).contains(&accepted));
+        assert!(!Target::Listener("0.0.0.0:443".parse().unwrap()).contains(&outgoing));
+
+        assert!(Target::Host("192.168.1.5".parse().unwrap()).contains(&outgoing));
+        assert!(Target::Process(42).contains(&accepted));
+        assert!(Target::Process(0).contains(&outgoing));
+        assert!(!Target::Process(42).contains(&outgoing));
+    }
+
+    #[test]
+    fn test_tab_cycle() {
+        assert_eq!(Tab::Closed.next(), Tab::Sockets);
+        assert_eq!(Tab::Sockets.previous(), Tab::Closed);
+        assert_eq!(Tab::Hosts.next(), Tab::Processes);
+    }
+}